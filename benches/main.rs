@@ -1,10 +1,72 @@
 // https://bheisler.github.io/criterion.rs/book/getting_started.html
+#![allow(unused_imports)]
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use statical::config::Config;
+use statical::model::arena::StringInterner;
+use statical::model::calendar::Calendar;
+use statical::model::calendar_collection::{validate_config, CalendarCollection};
 use statical::*;
+use time::ext::NumericalDuration;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+/// Number of events in the generated calendar
+const BULK_EVENTS: i64 = 20_000;
+
+/// Generates a calendar with many events which repeat a handful of summaries and locations
+fn bulk_calendar() -> String {
+    let start = OffsetDateTime::now_utc().replace_second(0).unwrap() - 100.days();
+    let format = format_description!("[year][month][day]T[hour][minute][second]Z");
+    let mut ics = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nX-WR-CALNAME:Bulk\r\n");
+    for n in 0..BULK_EVENTS {
+        let event_start = start + (n * 17).minutes();
+        ics.push_str(&format!(
+            "BEGIN:VEVENT\r\nUID:bulk-{n}\r\nSUMMARY:Meeting {}\r\nLOCATION:Room {}\r\nCATEGORIES:Work\r\n\
+             DTSTART:{}\r\nDTEND:{}\r\nEND:VEVENT\r\n",
+            n % 10,
+            n % 4,
+            event_start.format(format).unwrap(),
+            (event_start + 45.minutes()).format(format).unwrap(),
+        ));
+    }
+    ics.push_str("END:VCALENDAR\r\n");
+    ics
+}
 
 pub fn criterion_benchmark(c: &mut Criterion) {
-    // c.bench_function("hello", |b| b.iter(|| black_box(hello())));
+    let ics = bulk_calendar();
+    let config = Config::default();
+    let time_zone = validate_config(&config).unwrap();
+
+    c.bench_function("parse bulk calendar", |b| {
+        b.iter(|| {
+            Calendar::parse_calendars(
+                black_box(ics.as_bytes()),
+                "bulk",
+                &mut StringInterner::new(),
+                time_zone,
+            )
+            .unwrap()
+        })
+    });
+
+    c.bench_function("group bulk calendar", |b| {
+        b.iter_batched(
+            || {
+                Calendar::parse_calendars(
+                    ics.as_bytes(),
+                    "bulk",
+                    &mut StringInterner::new(),
+                    time_zone,
+                )
+                .unwrap()
+                .0
+            },
+            |calendars| CalendarCollection::from_calendars(calendars, Vec::new(), &config).unwrap(),
+            criterion::BatchSize::LargeInput,
+        )
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
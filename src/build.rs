@@ -0,0 +1,269 @@
+//! The steps of a build after the calendars have been read: rendering the views, writing redirects, the manifest,
+//! and the report, and checking the result.
+
+use color_eyre::eyre::{self, bail};
+use serde_json::json;
+use std::path::PathBuf;
+use std::time::Instant;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::changes;
+use crate::config::{Config, HtmlValidation, UploadTarget};
+use crate::git;
+use crate::html_check;
+use crate::link_check;
+use crate::log;
+use crate::manifest::Manifest;
+use crate::model::calendar_collection::{CalendarCollection, ViewRenderer};
+use crate::report::{BuildReport, PhaseTiming};
+use crate::upload;
+
+/// Options of a single build which are not part of the config
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuildOptions {
+    /// Skip the views which the previous build completed
+    pub resume: bool,
+    /// Fail the build if a generated page links to a page which was not generated
+    pub check_links: bool,
+}
+
+/// Returns every view with whether it is enabled and the function rendering it, in the order they are built
+pub fn views<'c>(config: &Config) -> [(&'static str, bool, ViewRenderer<'c>); 13] {
+    [
+        (
+            "year",
+            config.render_year,
+            CalendarCollection::create_year_pages,
+        ),
+        (
+            "month",
+            config.render_month,
+            CalendarCollection::create_month_pages,
+        ),
+        (
+            "week",
+            config.render_week,
+            CalendarCollection::create_week_pages,
+        ),
+        (
+            "day",
+            config.render_day,
+            CalendarCollection::create_day_pages,
+        ),
+        (
+            "event",
+            config.render_event_pages,
+            CalendarCollection::create_event_pages,
+        ),
+        (
+            "series",
+            config.render_series_pages,
+            CalendarCollection::create_series_pages,
+        ),
+        (
+            "countdown",
+            config.has_featured_events(),
+            CalendarCollection::create_countdown_pages,
+        ),
+        (
+            "agenda",
+            config.render_agenda,
+            CalendarCollection::create_agenda_pages,
+        ),
+        (
+            "tracks",
+            config.render_tracks,
+            CalendarCollection::create_track_pages,
+        ),
+        (
+            "locations",
+            config.render_week_by_location,
+            CalendarCollection::create_week_location_pages,
+        ),
+        (
+            "feed",
+            config.render_feed,
+            CalendarCollection::create_feed_pages,
+        ),
+        (
+            "freebusy",
+            config.render_free_busy,
+            CalendarCollection::create_free_busy_pages,
+        ),
+        (
+            "sqlite",
+            config.render_sqlite,
+            CalendarCollection::create_sqlite_pages,
+        ),
+    ]
+}
+
+/// Renders all enabled views of a collection into the output directory and returns the build report
+///
+/// The manifest is saved after every view, so a failed build can be resumed with `resume`.
+pub fn build_site(
+    calendar_collection: &CalendarCollection,
+    config: &Config,
+    options: BuildOptions,
+) -> eyre::Result<BuildReport> {
+    let started = Instant::now();
+    let output_dir = PathBuf::from(&config.output_dir);
+    let previous_manifest = Manifest::load(&output_dir)?.unwrap_or_default();
+    let mut manifest = if options.resume {
+        previous_manifest.clone()
+    } else {
+        Manifest {
+            uploaded: previous_manifest.uploaded.clone(),
+            deployed: previous_manifest.deployed.clone(),
+            ..Manifest::default()
+        }
+    };
+    let events = calendar_collection.event_records();
+    let mut latest_changes = Vec::new();
+    if !options.resume {
+        latest_changes = changes::compare_events(
+            &previous_manifest.events,
+            &events,
+            OffsetDateTime::now_utc(),
+        );
+        manifest.changes =
+            changes::detect_changes(&previous_manifest, &latest_changes, config.feed_max_entries);
+    }
+    manifest.events = events;
+
+    let mut report = BuildReport {
+        sources: calendar_collection.source_summaries().to_vec(),
+        uid_conflicts: calendar_collection.uid_conflicts().to_vec(),
+        timings: calendar_collection.timings().to_vec(),
+    };
+    report.print_source_table();
+    report.print_uid_conflicts();
+    for summary in &report.sources {
+        log::event(
+            "parse_source",
+            json!({
+                "source": summary.source,
+                "events_parsed": summary.events_parsed,
+                "events_skipped": summary.events_skipped,
+                "recurrences_expanded": summary.recurrences_expanded,
+                "warnings": summary.warnings.len(),
+            }),
+        );
+    }
+
+    calendar_collection.setup_output_dir()?;
+
+    let views = views(config);
+
+    for (view, enabled, create_pages) in views {
+        if !enabled {
+            continue;
+        }
+        if manifest.completed_views.contains(view) {
+            info!(
+                "Skipping {} pages, they were completed by the previous build",
+                view
+            );
+            continue;
+        }
+
+        let view_started = Instant::now();
+        let files_before = calendar_collection.written_files().len();
+        let result = create_pages(calendar_collection);
+        let timing = PhaseTiming::since(&format!("view:{}", view), view_started);
+        log::event(
+            "render_view",
+            json!({
+                "view": view,
+                "ok": result.is_ok(),
+                "files": calendar_collection.written_files().len() - files_before,
+                "duration_ms": timing.duration_ms,
+            }),
+        );
+        report.timings.push(timing);
+        manifest.record_files(&output_dir, calendar_collection.written_files());
+        manifest.pages.extend(calendar_collection.pages());
+        if let Err(error) = result {
+            // keep track of the progress so far so the build can be resumed
+            manifest.save(&output_dir)?;
+            report.save(&output_dir)?;
+            return Err(error.wrap_err(format!(
+                "could not create {} pages, fix the problem and run again with --resume to continue",
+                view
+            )));
+        }
+        manifest.completed_views.insert(view.to_string());
+        manifest.save(&output_dir)?;
+    }
+
+    if config.render_feed {
+        calendar_collection.create_changes_feed(&manifest.changes)?;
+    }
+    // a resumed build keeps the changes calendar of the build it resumes, as it no longer knows the previous events
+    if config.render_changes_ics && !options.resume {
+        calendar_collection.create_changes_ics(&latest_changes)?;
+    }
+    calendar_collection.create_landing_page()?;
+    calendar_collection.create_alias_pages()?;
+    calendar_collection.create_redirect_pages(&previous_manifest.pages)?;
+    calendar_collection.finish_writes()?;
+    manifest.record_files(&output_dir, calendar_collection.written_files());
+    manifest.save(&output_dir)?;
+
+    report.print_timings();
+    report.save(&output_dir)?;
+
+    if config.html_validation != HtmlValidation::Off {
+        let written_files = calendar_collection.written_files();
+        let invalid_pages = html_check::check_files(written_files.iter().map(PathBuf::as_path))?;
+        for (page, problems) in &invalid_pages {
+            for problem in problems {
+                warn!("Invalid HTML in {:?}, {}", page, problem);
+            }
+        }
+        if config.html_validation == HtmlValidation::Strict && !invalid_pages.is_empty() {
+            bail!("{} pages contain invalid HTML", invalid_pages.len());
+        }
+    }
+
+    if options.check_links {
+        let broken_links = link_check::check_links(&output_dir, &manifest.files)?;
+        for broken_link in &broken_links {
+            warn!("Broken link: {}", broken_link);
+        }
+        if !broken_links.is_empty() {
+            bail!("found {} broken links", broken_links.len());
+        }
+    }
+
+    if config.upload_target != UploadTarget::None {
+        let result = upload::upload_site(&output_dir, &mut manifest, config);
+        // keep the hashes of the files uploaded so far, so they are not uploaded again
+        manifest.save(&output_dir)?;
+        result?;
+    }
+
+    if config.commit_output {
+        git::commit_output(&output_dir, &manifest, &report, config)?;
+    }
+
+    info!(
+        "Built {} files into {:?} in {:.1} s",
+        manifest.files.len(),
+        output_dir,
+        started.elapsed().as_secs_f64()
+    );
+    log::event(
+        "build",
+        json!({
+            "sources": report.sources.len(),
+            "warnings": report.sources.iter().map(|s| s.warnings.len()).sum::<usize>(),
+            "uid_conflicts": report.uid_conflicts.len(),
+            "files": manifest.files.len(),
+            "duration_ms": log::millis(started.elapsed()),
+        }),
+    );
+
+    Ok(report)
+}
@@ -0,0 +1,489 @@
+//! Detects the events which changed since the previous build, for the changes feed and the incremental calendar.
+//!
+//! The changes for the feed are kept in the manifest, so the feed still lists them after later builds which found no
+//! changes. The incremental calendar only holds the changes of the latest build.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use time::{macros::format_description, Date, OffsetDateTime};
+
+use crate::free_busy::ics_time;
+use crate::manifest::{EventRecord, Manifest};
+use crate::model::ics::escape_text;
+
+/// Name of the calendar with the changes of the latest build, written into the output directory
+pub const CHANGES_ICS_FILE_NAME: &str = "changes.ics";
+
+/// What happened to an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Moved,
+    /// The event kept its time but its summary, location, or description changed
+    Updated,
+    Cancelled,
+}
+
+impl ChangeKind {
+    /// The word the feed titles start with, e.g. "Moved: Open mic"
+    pub fn label(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "Added",
+            ChangeKind::Moved => "Moved",
+            ChangeKind::Updated => "Updated",
+            ChangeKind::Cancelled => "Cancelled",
+        }
+    }
+}
+
+/// An upcoming event which changed between two builds
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EventChange {
+    pub kind: ChangeKind,
+    /// The instance id of the event, the one it had before if it was cancelled
+    pub id: String,
+    /// The event as it is now, or as it was before it was cancelled
+    pub event: EventRecord,
+    /// When a moved event started before
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub previous_start: Option<OffsetDateTime>,
+    /// The `SEQUENCE` a moved or updated event had in the previous build
+    #[serde(default)]
+    pub previous_sequence: u32,
+    /// The build which found the change
+    #[serde(with = "time::serde::rfc3339")]
+    pub detected: OffsetDateTime,
+}
+
+impl EventChange {
+    /// The `SEQUENCE` to publish the change with, higher than the previous build's so mirrors apply it
+    pub fn sequence(&self) -> u32 {
+        match self.kind {
+            ChangeKind::Added => self.event.sequence,
+            ChangeKind::Moved | ChangeKind::Updated => {
+                self.event.sequence.max(self.previous_sequence + 1)
+            }
+            ChangeKind::Cancelled => self.event.sequence + 1,
+        }
+    }
+}
+
+/// Compares the upcoming events with the ones of the previous build
+///
+/// Instances which disappeared are reported as moved when an instance with the same UID appeared, pairing them in the
+/// order they start, and as cancelled otherwise. Instances which kept their id but not their content are reported as
+/// updated. Without previous events, every upcoming event is reported as added.
+pub fn compare_events(
+    previous: &BTreeMap<String, EventRecord>,
+    events: &BTreeMap<String, EventRecord>,
+    now: OffsetDateTime,
+) -> Vec<EventChange> {
+    let upcoming = |event: &&EventRecord| event.end > now;
+    let change = |kind, id: &String, event: &EventRecord, previous_event: Option<&EventRecord>| {
+        EventChange {
+            kind,
+            id: id.clone(),
+            event: event.clone(),
+            previous_start: previous_event
+                .filter(|_| kind == ChangeKind::Moved)
+                .map(|previous_event| previous_event.start),
+            previous_sequence: previous_event.map_or(0, |previous_event| previous_event.sequence),
+            detected: now,
+        }
+    };
+
+    let mut removed: Vec<(&String, &EventRecord)> = previous
+        .iter()
+        .filter(|(id, event)| !events.contains_key(*id) && upcoming(event))
+        .collect();
+    removed.sort_by_key(|(_, event)| event.start);
+    let mut removed_by_uid: BTreeMap<&str, VecDeque<&EventRecord>> = BTreeMap::new();
+    for (_, event) in &removed {
+        if let Some(uid) = &event.uid {
+            removed_by_uid.entry(uid).or_default().push_back(event);
+        }
+    }
+
+    let mut upcoming_events: Vec<(&String, &EventRecord)> =
+        events.iter().filter(|(_, event)| upcoming(event)).collect();
+    upcoming_events.sort_by_key(|(_, event)| event.start);
+    let mut changes = Vec::new();
+    let mut moved_from = BTreeSet::new();
+    for (id, event) in upcoming_events {
+        match previous.get(id) {
+            Some(previous_event) if previous_event != event => {
+                changes.push(change(ChangeKind::Updated, id, event, Some(previous_event)))
+            }
+            Some(_) => {}
+            None => {
+                let previous_event = event
+                    .uid
+                    .as_deref()
+                    .and_then(|uid| removed_by_uid.get_mut(uid))
+                    .and_then(VecDeque::pop_front);
+                if let Some(previous_event) = previous_event {
+                    moved_from.insert((previous_event.uid.clone(), previous_event.start));
+                    changes.push(change(ChangeKind::Moved, id, event, Some(previous_event)));
+                } else {
+                    changes.push(change(ChangeKind::Added, id, event, None));
+                }
+            }
+        }
+    }
+
+    changes.extend(
+        removed
+            .into_iter()
+            .filter(|(_, event)| !moved_from.contains(&(event.uid.clone(), event.start)))
+            .map(|(id, event)| change(ChangeKind::Cancelled, id, event, None)),
+    );
+    changes
+}
+
+/// Adds the additions, moves, and cancellations among `changes` to the earlier changes of the previous build
+///
+/// New instances of a UID the previous build already had are left out, as they are usually recurring events which
+/// the generated range now reaches, and so are updates. Nothing is added when the previous build did not record its
+/// events. The newest `max_changes` changes are returned, newest first.
+pub fn detect_changes(
+    previous: &Manifest,
+    changes: &[EventChange],
+    max_changes: usize,
+) -> Vec<EventChange> {
+    let mut detected = Vec::new();
+    if !previous.events.is_empty() {
+        let previous_uids: BTreeSet<&str> = previous
+            .events
+            .values()
+            .filter_map(|event| event.uid.as_deref())
+            .collect();
+        detected.extend(
+            changes
+                .iter()
+                .filter(|change| match change.kind {
+                    ChangeKind::Added => !change
+                        .event
+                        .uid
+                        .as_deref()
+                        .is_some_and(|uid| previous_uids.contains(uid)),
+                    ChangeKind::Moved | ChangeKind::Cancelled => true,
+                    ChangeKind::Updated => false,
+                })
+                .cloned(),
+        );
+    }
+
+    detected.extend(previous.changes.iter().cloned());
+    detected.sort_by_key(|change| std::cmp::Reverse(change.detected));
+    detected.truncate(max_changes);
+    detected
+}
+
+/// Renders a `METHOD:PUBLISH` calendar with the changed events, so mirrors can apply them instead of re-importing
+///
+/// Events keep their UID, or get one from their instance id, and instances of recurring events are identified by the
+/// `RECURRENCE-ID` they had before the change. Cancelled events are published with `STATUS:CANCELLED`. `now` is the
+/// `DTSTAMP` of the events. All-day events are written with `VALUE=DATE` dates, other times in UTC. Texts are escaped again, as events keep them unescaped.
+pub fn changes_ics(changes: &[EventChange], now: OffsetDateTime) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//statical//changes//EN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+    ];
+    for change in changes {
+        let event = &change.event;
+        let time = |name: &str, time: OffsetDateTime| {
+            if event.all_day {
+                format!("{};VALUE=DATE:{}", name, ics_date(time.date()))
+            } else {
+                format!("{}:{}", name, ics_time(time))
+            }
+        };
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!(
+            "UID:{}",
+            event
+                .uid
+                .clone()
+                .unwrap_or_else(|| format!("{}@statical", change.id))
+        ));
+        lines.push(format!("DTSTAMP:{}", ics_time(now)));
+        if event.recurring {
+            lines.push(time(
+                "RECURRENCE-ID",
+                change.previous_start.unwrap_or(event.start),
+            ));
+        }
+        lines.push(time("DTSTART", event.start));
+        lines.push(time("DTEND", event.end));
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        if let Some(location) = &event.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        if let Some(description) = &event.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        lines.push(format!("SEQUENCE:{}", change.sequence()));
+        if change.kind == ChangeKind::Cancelled {
+            lines.push("STATUS:CANCELLED".to_string());
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+fn ics_date(date: Date) -> String {
+    date.format(format_description!("[year][month][day]"))
+        .unwrap_or_default()
+}
+
+/// Folds a content line into lines of at most 75 octets, each ending with CRLF
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use time::macros::datetime;
+
+    fn record(summary: &str, start: OffsetDateTime, end: OffsetDateTime) -> EventRecord {
+        EventRecord {
+            uid: Some(format!("{}@example.org", summary.to_lowercase())),
+            summary: summary.to_string(),
+            start,
+            end,
+            location: None,
+            description: None,
+            sequence: 0,
+            recurring: false,
+            all_day: false,
+        }
+    }
+
+    fn added(id: &str, event: EventRecord) -> EventChange {
+        EventChange {
+            kind: ChangeKind::Added,
+            id: id.to_string(),
+            event,
+            previous_start: None,
+            previous_sequence: 0,
+            detected: datetime!(2024-05-01 12:00 UTC),
+        }
+    }
+
+    fn event_lines(ics: &str) -> Vec<&str> {
+        ics.lines()
+            .filter(|line| line.starts_with("DT") || line.starts_with("RECURRENCE-ID"))
+            .filter(|line| !line.starts_with("DTSTAMP"))
+            .collect()
+    }
+
+    #[test]
+    fn changes_ics_writes_all_day_events_as_dates() {
+        // midnight in the display timezone is the evening before in UTC
+        let fair = EventRecord {
+            all_day: true,
+            recurring: true,
+            ..record(
+                "Fair",
+                datetime!(2024-05-14 00:00 +02:00),
+                datetime!(2024-05-16 00:00 +02:00),
+            )
+        };
+        let talk = record(
+            "Talk",
+            datetime!(2024-05-14 19:00 +02:00),
+            datetime!(2024-05-14 20:00 +02:00),
+        );
+        let ics = changes_ics(
+            &[added("fair", fair), added("talk", talk)],
+            datetime!(2024-05-01 12:00 UTC),
+        );
+        assert_eq!(
+            event_lines(&ics),
+            [
+                "RECURRENCE-ID;VALUE=DATE:20240514",
+                "DTSTART;VALUE=DATE:20240514",
+                "DTEND;VALUE=DATE:20240516",
+                "DTSTART:20240514T170000Z",
+                "DTEND:20240514T180000Z",
+            ]
+        );
+    }
+
+    /// The kind, id, and start of each change
+    fn summary(changes: &[EventChange]) -> Vec<(ChangeKind, &str, OffsetDateTime)> {
+        changes
+            .iter()
+            .map(|change| (change.kind, change.id.as_str(), change.event.start))
+            .collect()
+    }
+
+    fn hour_from(start: OffsetDateTime) -> (OffsetDateTime, OffsetDateTime) {
+        (start, start + time::Duration::HOUR)
+    }
+
+    #[test]
+    fn compare_events_detects_added_updated_moved_and_cancelled_events() {
+        let now = datetime!(2024-05-10 12:00 UTC);
+        let event = |summary: &str, start| {
+            let (start, end) = hour_from(start);
+            record(summary, start, end)
+        };
+        let previous = BTreeMap::from([
+            (
+                "past".to_string(),
+                event("Past", datetime!(2024-05-01 10:00 UTC)),
+            ),
+            (
+                "same".to_string(),
+                event("Same", datetime!(2024-05-12 10:00 UTC)),
+            ),
+            (
+                "talk".to_string(),
+                event("Talk", datetime!(2024-05-13 10:00 UTC)),
+            ),
+            (
+                "jam-1".to_string(),
+                event("Jam", datetime!(2024-05-14 10:00 UTC)),
+            ),
+            (
+                "jam-2".to_string(),
+                event("Jam", datetime!(2024-05-21 10:00 UTC)),
+            ),
+            (
+                "gone".to_string(),
+                event("Gone", datetime!(2024-05-15 10:00 UTC)),
+            ),
+        ]);
+        let mut talk = event("Talk", datetime!(2024-05-13 10:00 UTC));
+        talk.location = Some("Room 4".to_string());
+        talk.sequence = 1;
+        let events = BTreeMap::from([
+            (
+                "same".to_string(),
+                event("Same", datetime!(2024-05-12 10:00 UTC)),
+            ),
+            ("talk".to_string(), talk),
+            (
+                "jam-3".to_string(),
+                event("Jam", datetime!(2024-05-15 18:00 UTC)),
+            ),
+            (
+                "jam-4".to_string(),
+                event("Jam", datetime!(2024-05-22 18:00 UTC)),
+            ),
+            (
+                "new".to_string(),
+                event("New", datetime!(2024-05-16 10:00 UTC)),
+            ),
+        ]);
+
+        let changes = compare_events(&previous, &events, now);
+        assert_eq!(
+            summary(&changes),
+            [
+                (ChangeKind::Updated, "talk", datetime!(2024-05-13 10:00 UTC)),
+                (ChangeKind::Moved, "jam-3", datetime!(2024-05-15 18:00 UTC)),
+                (ChangeKind::Added, "new", datetime!(2024-05-16 10:00 UTC)),
+                (ChangeKind::Moved, "jam-4", datetime!(2024-05-22 18:00 UTC)),
+                (
+                    ChangeKind::Cancelled,
+                    "gone",
+                    datetime!(2024-05-15 10:00 UTC)
+                ),
+            ]
+        );
+        // moved instances are paired with the removed ones in the order they start
+        assert_eq!(
+            changes[1].previous_start,
+            Some(datetime!(2024-05-14 10:00 UTC))
+        );
+        assert_eq!(
+            changes[3].previous_start,
+            Some(datetime!(2024-05-21 10:00 UTC))
+        );
+        assert_eq!(changes[0].sequence(), 1);
+        assert_eq!(changes[1].sequence(), 1);
+        assert_eq!(changes[4].sequence(), 1);
+    }
+
+    #[test]
+    fn compare_events_without_previous_events_adds_the_upcoming_ones() {
+        let now = datetime!(2024-05-10 12:00 UTC);
+        let (start, end) = hour_from(datetime!(2024-05-13 10:00 UTC));
+        let (past_start, past_end) = hour_from(datetime!(2024-05-01 10:00 UTC));
+        let events = BTreeMap::from([
+            ("talk".to_string(), record("Talk", start, end)),
+            ("past".to_string(), record("Past", past_start, past_end)),
+        ]);
+        assert_eq!(
+            summary(&compare_events(&BTreeMap::new(), &events, now)),
+            [(ChangeKind::Added, "talk", start)]
+        );
+    }
+
+    #[test]
+    fn detect_changes_keeps_additions_moves_and_cancellations() {
+        let now = datetime!(2024-05-10 12:00 UTC);
+        let (start, end) = hour_from(datetime!(2024-05-13 10:00 UTC));
+        let change = |kind, id: &str, summary: &str, detected| EventChange {
+            kind,
+            detected,
+            ..added(id, record(summary, start, end))
+        };
+        let earlier = change(
+            ChangeKind::Added,
+            "old",
+            "Old",
+            datetime!(2024-05-02 12:00 UTC),
+        );
+        let previous = Manifest {
+            events: BTreeMap::from([("jam-1".to_string(), record("Jam", start, end))]),
+            changes: vec![earlier.clone()],
+            ..Default::default()
+        };
+        let changes = [
+            change(ChangeKind::Added, "talk", "Talk", now),
+            // a new instance of a UID the previous build had, like a recurrence the range now reaches
+            change(ChangeKind::Added, "jam-2", "Jam", now),
+            change(ChangeKind::Updated, "fix", "Fix", now),
+            change(ChangeKind::Moved, "moved", "Moved", now),
+            change(ChangeKind::Cancelled, "gone", "Gone", now),
+        ];
+        let ids = |changes: Vec<EventChange>| -> Vec<String> {
+            changes.into_iter().map(|change| change.id).collect()
+        };
+        assert_eq!(
+            ids(detect_changes(&previous, &changes, 10)),
+            ["talk", "moved", "gone", "old"]
+        );
+        assert_eq!(
+            ids(detect_changes(&previous, &changes, 2)),
+            ["talk", "moved"]
+        );
+        // the first build has nothing to compare with
+        let first = Manifest {
+            changes: vec![earlier],
+            ..Default::default()
+        };
+        assert_eq!(ids(detect_changes(&first, &changes, 10)), ["old"]);
+    }
+}
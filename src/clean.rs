@@ -0,0 +1,51 @@
+//! Removes the output of previous builds.
+
+use color_eyre::eyre::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::config::Config;
+use crate::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::report::REPORT_FILE_NAME;
+
+/// Removes the files the last build recorded in its manifest, and the directories they leave empty
+///
+/// Only files written by statical are removed, anything else in the output directory is kept. Without a manifest
+/// nothing is removed.
+pub fn clean(config: &Config) -> Result<()> {
+    let output_dir = PathBuf::from(&config.output_dir);
+    let Some(manifest) = Manifest::load(&output_dir)? else {
+        info!("No manifest in {:?}, nothing to clean", output_dir);
+        return Ok(());
+    };
+
+    let mut files: Vec<PathBuf> = manifest.files.iter().cloned().collect();
+    if config.copy_stylesheet_to_output {
+        files.push(PathBuf::from("styles/style.css"));
+    }
+    files.push(PathBuf::from(REPORT_FILE_NAME));
+    files.push(PathBuf::from(MANIFEST_FILE_NAME));
+
+    let mut removed = 0;
+    let mut dirs = BTreeSet::new();
+    for file in &files {
+        let path = output_dir.join(file);
+        if path.is_file() {
+            fs::remove_file(&path).context(format!("could not remove {:?}", path))?;
+            removed += 1;
+        }
+        dirs.extend(file.ancestors().skip(1).filter(|dir| dir != &Path::new("")));
+    }
+    // every build creates the styles directory, even when it does not copy the stylesheet
+    dirs.insert(Path::new("styles"));
+    // deepest first, so parents are empty by the time they are reached
+    for dir in dirs.iter().rev() {
+        fs::remove_dir(output_dir.join(dir)).ok();
+    }
+    fs::remove_dir(&output_dir).ok();
+
+    info!("Removed {} files from {:?}", removed, output_dir);
+    Ok(())
+}
@@ -1,25 +1,147 @@
-use serde::{Deserialize, Serialize};
+use color_eyre::eyre::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use time::{macros::format_description, Date, Month};
+
+/// Number of minutes in a day, used to validate and compute grid slots
+pub const MINUTES_PER_DAY: u16 = 24 * 60;
 
 /// A struct containing the configuration options.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
+    /// The calendar files to read when none are given on the command line
+    pub calendar_files: Vec<String>,
+    /// The calendar urls to read when none are given on the command line
+    pub calendar_urls: Vec<String>,
+    /// Shell commands printing calendars to read when none are given on the command line, e.g. a script exporting the
+    /// events of a booking system without an ICS export
+    pub calendar_commands: Vec<String>,
+    /// The directory the `*.html` templates are loaded from
+    pub templates_dir: String,
     /// Flag to control rendering of the agenda pages.
     pub render_agenda: bool,
     /// Flag to control rendering of the day pages.
     pub render_day: bool,
     /// Flag to control rendering of the month pages.
     pub render_month: bool,
+    /// Write a page per year with its months in miniature, linking to the month pages
+    pub render_year: bool,
     /// Flag to control rendering of the week pages.
     pub render_week: bool,
+    /// Write `today.html`, `this-week.html`, and `this-month.html`, leading to the pages of the current periods
+    pub render_alias_pages: bool,
+    /// Also write the month, week, and day pages without events between the first and the last event, so the
+    /// previous and next links step through every period
+    pub render_empty_periods: bool,
+    /// Write a detail page per event, which the day and week pages link the events to
+    pub render_event_pages: bool,
+    /// Write a page per series, the instances sharing a UID like the occurrences of a recurring event, listing its
+    /// dates, which each instance links to
+    pub render_series_pages: bool,
+    /// The categories whose events are featured: each instance gets a countdown page and upcoming ones are listed on
+    /// the index pages as `featured_events`
+    pub featured_categories: Vec<String>,
+    /// The UIDs of featured events, in addition to those in `featured_categories`
+    pub featured_uids: Vec<String>,
+    /// Write `freebusy.ics` with the busy times of the next `free_busy_days` days
+    pub render_free_busy: bool,
+    /// How many days from today `freebusy.ics` covers
+    pub free_busy_days: u32,
+    /// Write `events.sqlite`, a SQLite database with every event instance for analytics and ad-hoc queries
+    pub render_sqlite: bool,
+    /// Render a page per day with the events in parallel tracks, see `track_categories`
+    pub render_tracks: bool,
+    /// The categories used as tracks, in the order of their columns. If empty, the first category of each event is
+    /// its track and tracks are ordered by name
+    pub track_categories: Vec<String>,
+    /// The track of events which have none of the track categories
+    pub default_track: String,
+    /// Render a variant of each week page with the events of every day grouped by location, into `locations/`
+    pub render_week_by_location: bool,
+    /// The group of events without a location in the week by location pages
+    pub default_location: String,
+    /// Also write the data of each month, week, day, agenda, and tracks page as JSON next to the page, e.g.
+    /// `month/2024-05.json`, and link it from the page
+    pub render_period_json: bool,
+    /// Flag to control writing the Atom and JSON feeds of upcoming events.
+    pub render_feed: bool,
+    /// The title of the feeds
+    pub feed_title: String,
+    /// Also write a feed for each category, into `feeds/category/`
+    pub render_category_feeds: bool,
+    /// Also write a feed for each calendar, into `feeds/calendar/`
+    pub render_calendar_feeds: bool,
+    /// Also write a feed of the upcoming events which were added, moved, or cancelled, into `changes.atom`
+    pub render_changes_feed: bool,
+    /// Also write the upcoming events which changed since the previous build into `changes.ics`, so mirrors of the
+    /// calendar can apply the changes instead of re-importing every event
+    pub render_changes_ics: bool,
+    /// Maximum number of upcoming events in the feeds, and of changes in the changes feed
+    pub feed_max_entries: usize,
+    /// Where the agenda and the feeds link an event to, see `EventLinks`
+    pub event_links: EventLinks,
+    /// Where the location of an event links to: "none", "openstreetmap", "google", or "custom" for
+    /// `map_link_template`
+    pub map_links: MapLinks,
+    /// The map link of `map_links = "custom"`, `{latitude}`, `{longitude}`, and `{location}` are replaced by those of
+    /// the event. Events lacking a value the template uses get no link
+    pub map_link_template: String,
+    /// The public address of the output directory (e.g. `https://example.com/calendar`), used for absolute links in
+    /// the feeds, which use relative links while this is empty
+    pub site_url: String,
     /// The path to the output directory where files will be written.
     pub output_dir: String,
-    /// Name of the timezone used to format time
+    /// IANA name of the timezone used to format time, e.g. `Europe/Paris`
     pub display_timezone: String,
+    /// The language of generated text such as recurrence descriptions (en or fr)
+    pub language: Language,
     /// Number of events per page in agenda
     pub agenda_events_per_page: usize,
-    /// Agenda page 0 starts at this `yyyy-mm-dd` date (or now if empty)
+    /// Maximum number of upcoming events listed by the agenda (0 to list all of them)
+    pub agenda_max_events: usize,
+    /// Directory of per-day notes in Markdown, named like `2024-05-14.md`
+    pub notes_dir: String,
+    /// Extra data attached to every day from a file or command, e.g. a weather forecast, see `enrichment`
+    pub day_enrichments: Vec<DayEnrichment>,
+    /// Announcement shown on every page, in Markdown
+    pub announcement: String,
+    /// Markdown file with the announcement, used instead of `announcement` if set
+    pub announcement_file: String,
+    /// First day the announcement is shown (YYYY-MM-DD), empty to show it right away
+    pub announcement_start: String,
+    /// Last day the announcement is shown (YYYY-MM-DD), empty to keep showing it
+    pub announcement_end: String,
+    /// Simple events defined in the config, added to the calendars
+    pub virtual_events: Vec<VirtualEvent>,
+    /// Sources read as CSV although they do not end in `.csv`, e.g. the url of a published sheet or a command, given as
+    /// they are listed in the sources
+    pub csv_sources: Vec<String>,
+    /// The columns identifying a row of a CSV source which has no `uid`, they are hashed into the UID of its event.
+    /// Changing another column of the row updates the event instead of replacing it
+    pub csv_uid_columns: Vec<String>,
+    /// Leave out the events whose `STATUS` is `CANCELLED`. Otherwise they are shown with `event.status` "cancelled",
+    /// like tentative events get "tentative", so templates can strike them through
+    pub hide_cancelled_events: bool,
+    /// Closures like holidays during which events are hidden or marked as suspended
+    pub blackouts: Vec<Blackout>,
+    /// The place whose sunrise and sunset the day contexts get, in degrees with north positive
+    pub latitude: Option<f64>,
+    /// In degrees with east positive, see `latitude`
+    pub longitude: Option<f64>,
+    /// Give the day contexts the phase of the moon
+    pub moon_phases: bool,
+    /// Mark the public holidays of this country (e.g. "US", "FR") on the day pages, empty for none
+    pub holidays_country: String,
+    /// Also list the holidays of `holidays_country` as all-day events, in a "Holidays" calendar
+    pub holiday_events: bool,
+    /// Day pages with more events than this are split into morning, afternoon, and evening sections (0 to never
+    /// split)
+    pub day_split_threshold: usize,
+    /// Agenda page 0 starts at this `yyyy-mm-dd` date (or with the events which have not ended yet if empty)
     pub agenda_start_date: String,
-    /// The view (month, week, or day) to use for the main index page
+    /// The view to use for the main index page, month, week, and day embed their current page, other views redirect
     pub default_calendar_view: String,
     /// The path to add into the stylesheet link tag
     pub stylesheet_path: String,
@@ -27,6 +149,369 @@ pub struct Config {
     pub copy_stylesheet_to_output: bool,
     /// The stylesheet to copy to the output dir
     pub copy_stylesheet_from: String,
+    /// Size of the time slots in the week and day grids in minutes (15, 30, 60, or any other divisor of a day)
+    pub time_slot_minutes: u16,
+    /// Rules used to decorate events with colors and icons. Color and icon are looked up separately: each comes from the
+    /// first matching rule which sets it, so a rule with only an icon leaves the color to later rules
+    pub style_rules: Vec<StyleRule>,
+    /// Finds the price in the description of events without an `X-COST` property, the first capture group (or the
+    /// whole match) is the price, e.g. `(?i)price: *(.+)`
+    #[serde(with = "optional_regex", skip_serializing_if = "Option::is_none")]
+    pub price_regex: Option<Regex>,
+    /// Finds the ticket link in the description of events without an `X-TICKET-URL` property, like `price_regex`
+    #[serde(with = "optional_regex", skip_serializing_if = "Option::is_none")]
+    pub ticket_url_regex: Option<Regex>,
+    /// The property holding how many people an event admits, e.g. the places of a class
+    pub capacity_property: String,
+    /// Finds the capacity in the description of events without `capacity_property`, like `price_regex`
+    #[serde(with = "optional_regex", skip_serializing_if = "Option::is_none")]
+    pub capacity_regex: Option<Regex>,
+    /// The property holding how many places of an event are still free
+    pub spots_left_property: String,
+    /// Finds the free places in the description of events without `spots_left_property`, like `price_regex`
+    #[serde(with = "optional_regex", skip_serializing_if = "Option::is_none")]
+    pub spots_left_regex: Option<Regex>,
+    /// Marks events whose description matches as sold out, e.g. `(?i)sold out|fully booked`, in addition to events
+    /// without spots left
+    #[serde(with = "optional_regex", skip_serializing_if = "Option::is_none")]
+    pub sold_out_regex: Option<Regex>,
+    /// Rules extracting fields like the audience or an age limit from events, exposed to templates as `fields`
+    pub field_rules: Vec<FieldRule>,
+    /// Rules deriving deadline events some days before the matching events, e.g. when registration closes
+    pub deadline_rules: Vec<DeadlineRule>,
+    /// Place the month, week, and day pages into a subdirectory per year
+    pub group_pages_by_year: bool,
+    /// Zero-pad month and week numbers in file names (`2024-05.html`, `2024-W07.html`) so they sort chronologically
+    pub zero_pad_file_names: bool,
+    /// Write redirects from the unpadded file names used by earlier versions to the zero-padded ones
+    pub redirect_legacy_file_names: bool,
+    /// Write redirects from the previous build's page paths when the naming scheme changed
+    pub redirect_renamed_pages: bool,
+    /// Additional redirects to write, mapping old paths to new paths (both relative to the output directory)
+    pub redirects: BTreeMap<String, String>,
+    /// Check the rendered pages for unclosed and misnested tags (off, warn, or strict)
+    pub html_validation: HtmlValidation,
+    /// Where to upload the site after a successful build, see `UploadTarget`
+    pub upload_target: UploadTarget,
+    /// The WebDAV collection to upload to, or the endpoint of the S3 service, e.g.
+    /// `https://s3.eu-west-1.amazonaws.com`
+    pub upload_url: String,
+    /// The S3 bucket to upload to
+    pub upload_bucket: String,
+    /// The region S3 requests are signed for, `us-east-1` if empty
+    pub upload_region: String,
+    /// Where `statical deploy` publishes the site, see `DeployTarget`
+    pub deploy_target: DeployTarget,
+    /// The API id of the Netlify site to deploy to
+    pub deploy_site_id: String,
+    /// The SFTP server to deploy to, e.g. `user@example.com`
+    pub deploy_host: String,
+    /// The directory on the SFTP server to deploy into, the login directory if empty
+    pub deploy_path: String,
+    /// Commit the output directory to a git repository after every build, creating the repository if needed
+    pub commit_output: bool,
+    /// The message of the commits, a Tera template of `changed`, `files`, `events`, `sources`, `warnings`, and `date`
+    pub commit_message: String,
+    /// Push the output repository after committing, to its configured upstream
+    pub commit_push: bool,
+    /// Address of the calendar's owner, used to hide invitations they declined or did not answer
+    pub attendee_email: String,
+    /// Show events `attendee_email` declined
+    pub show_declined_events: bool,
+    /// Show events `attendee_email` did not answer yet
+    pub show_unanswered_events: bool,
+    /// First day (`yyyy-mm-dd`) events are kept and recurring events expanded from, empty to follow the events
+    pub start_date: String,
+    /// Last day (`yyyy-mm-dd`) events are kept and recurring events expanded to, empty to follow the events
+    pub end_date: String,
+    /// Start with the month this many months before the current one, if `start_date` is empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub months_back: Option<u32>,
+    /// End with the month this many months after the current one, if `end_date` is empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub months_ahead: Option<u32>,
+    /// Events ending more than this many days before the build are reported as outliers and not rendered
+    pub max_days_in_past: u32,
+    /// Events starting more than this many days after the build are reported as outliers and not rendered
+    pub max_days_in_future: u32,
+    /// Directory keeping a copy of each url source, used when fetching the url fails (empty to not cache)
+    pub source_cache_dir: String,
+    /// Each source keeps at most this many events, the ones closest to the build (0 for no limit)
+    pub max_events_per_source: usize,
+    /// Each recurring event is expanded to at most this many instances (0 for no limit)
+    pub max_recurrence_instances: u16,
+    /// When the events are estimated to need more than this many MiB, rendered pages are written right away instead of
+    /// being kept in memory for deferred writes (0 for no budget)
+    pub memory_budget_mb: usize,
+    /// When `statical daemon` refetches the sources, as a cron expression in the display timezone
+    pub daemon_schedule: String,
+    /// The address `statical daemon` serves its health endpoint on
+    pub daemon_listen_address: String,
+    /// Bearer token required by the daemon's `/rebuild` endpoint, the endpoint is disabled while this is empty
+    pub daemon_rebuild_token: String,
+    /// The daemon's health endpoint reports the site as stale once it was not confirmed up to date for this many
+    /// minutes (0 to never report it as stale)
+    pub daemon_max_age_minutes: u32,
+}
+
+/// How the rendered pages are checked after a build
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HtmlValidation {
+    /// Do not check the pages
+    #[default]
+    Off,
+    /// Report problems but keep the build successful
+    Warn,
+    /// Fail the build if any page has problems
+    Strict,
+}
+
+/// Where the site is uploaded to after a build
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadTarget {
+    /// Keep the site in the output directory only
+    #[default]
+    None,
+    /// An S3-compatible bucket at `upload_url`, signed with `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+    S3,
+    /// A WebDAV collection at `upload_url`, with `STATICAL_UPLOAD_USER` and `STATICAL_UPLOAD_PASSWORD` if set
+    WebDav,
+}
+
+/// Where `statical deploy` publishes the site
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployTarget {
+    /// No deploy target is configured
+    #[default]
+    None,
+    /// The Netlify site `deploy_site_id`, with `NETLIFY_AUTH_TOKEN`
+    Netlify,
+    /// The directory `deploy_path` on `deploy_host`, with the `sftp` command
+    Sftp,
+}
+
+impl DeployTarget {
+    pub fn name(self) -> &'static str {
+        match self {
+            DeployTarget::None => "nowhere",
+            DeployTarget::Netlify => "Netlify",
+            DeployTarget::Sftp => "SFTP",
+        }
+    }
+}
+
+/// The language of text generated by statical
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    /// English
+    #[default]
+    En,
+    /// French
+    Fr,
+}
+
+/// An event defined in the config instead of a calendar file, e.g. opening hours
+///
+/// Times are in the display timezone.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VirtualEvent {
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub categories: Vec<String>,
+    /// The first day of the event (YYYY-MM-DD)
+    pub date: String,
+    /// When the event starts (HH:MM)
+    pub start_time: String,
+    /// When the event ends (HH:MM), on the same day
+    pub end_time: String,
+    /// How the event repeats as an `RRULE`, e.g. `FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR`, empty for a single event
+    pub rrule: String,
+}
+
+/// A range of days during which events do not take place, e.g. a holiday closure
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Blackout {
+    /// Why the events do not take place, e.g. "Winter break"
+    pub name: String,
+    /// The first day of the blackout (YYYY-MM-DD)
+    pub start: String,
+    /// The last day of the blackout (YYYY-MM-DD)
+    pub end: String,
+    pub mode: BlackoutMode,
+}
+
+impl Blackout {
+    /// Parses the first and last day of the blackout
+    pub fn dates(&self) -> Result<(Date, Date)> {
+        let parse = |date: &str| {
+            Date::parse(date, format_description!("[year]-[month]-[day]")).wrap_err(format!(
+                "blackout {:?} needs dates like 2024-12-24, got {:?}",
+                self.name, date
+            ))
+        };
+        Ok((parse(&self.start)?, parse(&self.end)?))
+    }
+}
+
+/// What happens to the events during a blackout
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlackoutMode {
+    /// Remove the events
+    Hide,
+    /// Keep the events but mark them as suspended, so templates can show them as cancelled
+    #[default]
+    Suspend,
+}
+
+/// Where the agenda and the feeds link an event to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventLinks {
+    /// The event on its day page
+    #[default]
+    Page,
+    /// The `URL` of the event, e.g. its ticketing page, falling back to the day page for events without one
+    Url,
+}
+
+/// Where the location of an event links to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapLinks {
+    /// No link
+    #[default]
+    None,
+    /// OpenStreetMap, a marker at the `GEO` position, or a search for the `LOCATION`
+    OpenStreetMap,
+    /// Google Maps, searching for the `GEO` position or the `LOCATION`
+    Google,
+    /// `Config::map_link_template`
+    Custom,
+}
+
+/// Attaches the JSON of a file or command to the days, templates get it as `day.extra.<name>`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DayEnrichment {
+    /// The key of the data in `day.extra`, e.g. `weather`
+    pub name: String,
+    /// A JSON file with an object keyed by day, e.g. `{"2024-05-14": {"forecast": "sunny"}}`
+    pub file: Option<String>,
+    /// A shell command printing such an object, called with the first and last day as arguments
+    pub command: Option<String>,
+}
+
+/// Extracts a field from an `X-` property or the description of events
+///
+/// Several rules can fill the same field, the first one finding a value wins.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FieldRule {
+    /// The name of the field in the template context, e.g. `age_limit`
+    pub field: String,
+    /// The property holding the value, e.g. `X-AGE-LIMIT`
+    pub property: Option<String>,
+    /// Finds the value in the description, the first capture group (or the whole match) is the value
+    #[serde(with = "optional_regex", skip_serializing_if = "Option::is_none")]
+    pub description_regex: Option<Regex>,
+    pub kind: FieldKind,
+}
+
+/// How the text found by a `FieldRule` is exposed to templates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    /// The text as it is
+    #[default]
+    Text,
+    /// The first number in the text, e.g. 18 for "18+", the field is left out if there is none
+    Number,
+    /// The comma separated items of the text, e.g. "families, seniors"
+    List,
+    /// True if the value was found, unless it is "false", "no", or "0"
+    Flag,
+}
+
+/// Derives an all-day deadline event before each matching event, e.g. "Registration closes: Summer camp"
+///
+/// The deadline is on the day `days_before` days before the day the event starts, in the display timezone.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeadlineRule {
+    /// Match events having this category (case insensitive)
+    pub category: Option<String>,
+    /// Match events whose summary matches this regular expression
+    #[serde(with = "optional_regex")]
+    pub summary_regex: Option<Regex>,
+    /// How many days before the event the deadline falls
+    pub days_before: u32,
+    /// The summary of the deadline events, `{summary}` is replaced by the summary of the event
+    pub summary: String,
+}
+
+impl Default for DeadlineRule {
+    fn default() -> Self {
+        Self {
+            category: None,
+            summary_regex: None,
+            days_before: 7,
+            summary: "Registration closes: {summary}".into(),
+        }
+    }
+}
+
+/// Maps events to a color and/or icon by category or summary
+///
+/// A rule without any condition matches every event, which is useful as a final fallback.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StyleRule {
+    /// Match events having this category (case insensitive)
+    pub category: Option<String>,
+    /// Match events whose summary matches this regular expression
+    #[serde(with = "optional_regex")]
+    pub summary_regex: Option<Regex>,
+    /// The color to expose on matching events
+    pub color: Option<String>,
+    /// The icon name (or emoji) to expose on matching events
+    pub icon: Option<String>,
+}
+
+/// The first day of the month `months` months after the month of `day`
+fn first_of_month(day: Date, months: i64) -> Result<Date> {
+    let month_index = i64::from(day.year()) * 12 + i64::from(day.month() as u8 - 1) + months;
+    let year = i32::try_from(month_index.div_euclid(12))?;
+    let month = Month::try_from(month_index.rem_euclid(12) as u8 + 1)?;
+    Ok(Date::from_calendar_date(year, month, 1)?)
+}
+
+/// (De)serializes an optional regex from its string form so invalid patterns are reported when the config is read
+mod optional_regex {
+    use super::*;
+
+    pub fn serialize<S>(regex: &Option<Regex>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        regex.as_ref().map(Regex::as_str).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+            .transpose()
+    }
 }
 
 /// Sane default values for the config struct.
@@ -36,15 +521,182 @@ impl Default for Config {
             render_agenda: true,
             render_day: true,
             render_month: true,
+            render_year: false,
             render_week: true,
+            render_event_pages: false,
+            render_series_pages: false,
+            featured_categories: Vec::new(),
+            featured_uids: Vec::new(),
+            render_empty_periods: false,
+            render_alias_pages: true,
+            render_free_busy: false,
+            free_busy_days: 60,
+            render_sqlite: false,
+            render_tracks: false,
+            render_period_json: false,
+            track_categories: Vec::new(),
+            default_track: "General".into(),
+            render_week_by_location: false,
+            default_location: "Other".into(),
+            render_feed: true,
+            feed_title: "Upcoming events".into(),
+            render_category_feeds: false,
+            render_calendar_feeds: false,
+            render_changes_feed: false,
+            render_changes_ics: false,
+            feed_max_entries: 50,
+            site_url: String::new(),
             output_dir: "output".into(),
+            calendar_files: Vec::new(),
+            calendar_urls: Vec::new(),
+            calendar_commands: Vec::new(),
+            templates_dir: "templates".into(),
             display_timezone: "GMT".into(),
+            language: Language::En,
             agenda_events_per_page: 5,
+            agenda_max_events: 0,
+            event_links: EventLinks::Page,
+            map_links: MapLinks::None,
+            map_link_template: String::new(),
+            announcement: String::new(),
+            announcement_file: String::new(),
+            announcement_start: String::new(),
+            announcement_end: String::new(),
+            virtual_events: Vec::new(),
+            csv_sources: Vec::new(),
+            csv_uid_columns: vec!["summary".into(), "date".into(), "start_time".into()],
+            hide_cancelled_events: true,
+            blackouts: Vec::new(),
+            latitude: None,
+            longitude: None,
+            moon_phases: false,
+            holidays_country: String::new(),
+            holiday_events: false,
+            day_split_threshold: 0,
+            notes_dir: "content".into(),
+            day_enrichments: Vec::new(),
             agenda_start_date: String::new(),
             default_calendar_view: "month".into(),
             stylesheet_path: "/styles/style.css".into(),
             copy_stylesheet_to_output: false,
             copy_stylesheet_from: "public/statical.css".into(),
+            time_slot_minutes: 60,
+            style_rules: Vec::new(),
+            price_regex: None,
+            ticket_url_regex: None,
+            capacity_property: "X-CAPACITY".into(),
+            capacity_regex: None,
+            spots_left_property: "X-SPOTS-LEFT".into(),
+            spots_left_regex: None,
+            sold_out_regex: None,
+            field_rules: Vec::new(),
+            deadline_rules: Vec::new(),
+            group_pages_by_year: false,
+            zero_pad_file_names: true,
+            redirect_legacy_file_names: false,
+            redirect_renamed_pages: false,
+            redirects: BTreeMap::new(),
+            html_validation: HtmlValidation::Off,
+            upload_target: UploadTarget::None,
+            upload_url: String::new(),
+            upload_bucket: String::new(),
+            upload_region: String::new(),
+            deploy_target: DeployTarget::None,
+            deploy_site_id: String::new(),
+            deploy_host: String::new(),
+            deploy_path: String::new(),
+            commit_output: false,
+            commit_message: "Update the calendar: {{ changed }} of {{ files }} files changed, {{ events }} events from {{ sources }} sources".into(),
+            commit_push: false,
+            attendee_email: String::new(),
+            show_declined_events: false,
+            show_unanswered_events: true,
+            start_date: String::new(),
+            end_date: String::new(),
+            months_back: None,
+            months_ahead: None,
+            source_cache_dir: String::new(),
+            max_days_in_past: 5 * 365,
+            max_days_in_future: 5 * 365,
+            max_events_per_source: 20_000,
+            max_recurrence_instances: 5_000,
+            memory_budget_mb: 0,
+            daemon_schedule: "*/30 * * * *".into(),
+            daemon_listen_address: "127.0.0.1:8087".into(),
+            daemon_rebuild_token: String::new(),
+            daemon_max_age_minutes: 24 * 60,
+        }
+    }
+}
+
+impl Config {
+    /// Whether any events can be featured, which writes the countdown pages
+    pub fn has_featured_events(&self) -> bool {
+        !self.featured_categories.is_empty() || !self.featured_uids.is_empty()
+    }
+
+    /// The glob matching the templates in `templates_dir`
+    pub fn template_glob(&self) -> String {
+        format!("{}/**/*.html", self.templates_dir.trim_end_matches('/'))
+    }
+
+    /// The first and last day of the generated range, `None` where the range follows the events
+    ///
+    /// Explicit dates win over `months_back` and `months_ahead`, which count whole months from the month of `today`.
+    pub fn generation_range(&self, today: Date) -> Result<(Option<Date>, Option<Date>)> {
+        let parse = |name: &str, date: &str| {
+            Date::parse(date, format_description!("[year]-[month]-[day]")).wrap_err(format!(
+                "{} needs a date like 2024-05-14, got {:?}",
+                name, date
+            ))
+        };
+        let start = if !self.start_date.is_empty() {
+            Some(parse("start_date", &self.start_date)?)
+        } else {
+            self.months_back
+                .map(|months| first_of_month(today, -i64::from(months)))
+                .transpose()?
+        };
+        let end = if !self.end_date.is_empty() {
+            Some(parse("end_date", &self.end_date)?)
+        } else {
+            self.months_ahead
+                .map(|months| {
+                    first_of_month(today, i64::from(months) + 1).map(|next| next.previous_day())
+                })
+                .transpose()?
+                .flatten()
+        };
+        if let (Some(start), Some(end)) = (start, end) {
+            if start > end {
+                bail!(
+                    "the generated range starts on {} after it ends on {}",
+                    start,
+                    end
+                );
+            }
+        }
+        Ok((start, end))
+    }
+
+    /// The first day of agenda page 0, `None` to start with the events which have not ended at build time
+    pub fn agenda_start(&self) -> Result<Option<Date>> {
+        if self.agenda_start_date.is_empty() {
+            return Ok(None);
         }
+        Date::parse(
+            &self.agenda_start_date,
+            format_description!("[year]-[month]-[day]"),
+        )
+        .map(Some)
+        .wrap_err(format!(
+            "agenda_start_date needs a date like 2024-05-14, got {:?}",
+            self.agenda_start_date
+        ))
+    }
+
+    /// Number of grid slots in a single day
+    pub fn slots_per_day(&self) -> u16 {
+        MINUTES_PER_DAY / self.time_slot_minutes
     }
 }
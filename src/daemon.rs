@@ -0,0 +1,352 @@
+//! The resident mode: refetches the sources on a schedule, rebuilds when they changed, and answers health checks.
+//!
+//! Calendar hosts can also trigger an immediate rebuild by sending `POST /rebuild` with the configured bearer token.
+
+use color_eyre::eyre::{eyre, Context, Result};
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use time::{Duration, OffsetDateTime};
+use time_tz::OffsetDateTimeExt;
+use tracing::{error, info, warn};
+
+use crate::build::{self, BuildOptions};
+use crate::config::Config;
+use crate::model::calendar_collection::{
+    validate_config, CalendarCollection, SourceData, SourceLocation,
+};
+use crate::options::Opt;
+use crate::report::PhaseTiming;
+use crate::schedule::Schedule;
+
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The outcome of the rebuilds so far, shared with the health endpoint
+#[derive(Debug, Default, Serialize)]
+pub struct DaemonState {
+    /// When the sources were last fetched
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_check: Option<OffsetDateTime>,
+    /// When the site was last built successfully
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_success: Option<OffsetDateTime>,
+    /// When the site was last confirmed to match the sources, by a build or by a check finding no changes
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_up_to_date: Option<OffsetDateTime>,
+    /// The error of the last fetch or build, cleared by the next success
+    pub last_error: Option<String>,
+    /// Number of successful builds since the daemon started
+    pub builds: u64,
+    /// Number of warnings of the last successful build
+    pub warnings: usize,
+    /// The status of each source as of the last check
+    pub sources: Vec<SourceStatus>,
+    /// Hash of the source data of the last successful build
+    #[serde(skip)]
+    sources_hash: Option<u64>,
+}
+
+/// The fetch status of a single source
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SourceStatus {
+    /// The file path or url of the source
+    pub source: String,
+    /// Why the last fetch failed, `None` if it succeeded
+    pub error: Option<String>,
+    /// When the source was last fetched successfully
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_fetched: Option<OffsetDateTime>,
+    /// Number of warnings of the source in the last successful build
+    pub warnings: usize,
+}
+
+impl DaemonState {
+    /// Healthy once a build succeeded and the last attempt did not fail
+    pub fn healthy(&self) -> bool {
+        self.last_success.is_some() && self.last_error.is_none()
+    }
+
+    /// Whether the site was not confirmed up to date within `max_age`
+    pub fn stale(&self, max_age: Option<Duration>, now: OffsetDateTime) -> bool {
+        max_age.is_some_and(|max_age| {
+            self.last_up_to_date
+                .is_none_or(|up_to_date| now - up_to_date > max_age)
+        })
+    }
+}
+
+/// The response of the health endpoint
+#[derive(Debug, Serialize)]
+struct Health<'s> {
+    healthy: bool,
+    stale: bool,
+    #[serde(flatten)]
+    state: &'s DaemonState,
+}
+
+/// Runs until the process is stopped, rebuilding on the configured schedule
+pub fn run(args: Opt, config: Config) -> Result<()> {
+    let time_zone = validate_config(&config)?;
+    let schedule = Schedule::parse(&config.daemon_schedule)?;
+    let state = Arc::new(Mutex::new(DaemonState::default()));
+
+    let listener = TcpListener::bind(&config.daemon_listen_address).wrap_err(format!(
+        "could not listen on {}",
+        config.daemon_listen_address
+    ))?;
+    info!(
+        "Serving the health endpoint on http://{}/healthz",
+        config.daemon_listen_address
+    );
+    let (rebuild_requests, rebuild_requested) = mpsc::channel();
+    let server = Server {
+        state: state.clone(),
+        rebuild_token: config.daemon_rebuild_token.clone(),
+        max_age: (config.daemon_max_age_minutes > 0)
+            .then(|| Duration::minutes(config.daemon_max_age_minutes.into())),
+        rebuild_requests,
+    };
+    thread::spawn(move || server.serve(listener));
+
+    let mut forced = false;
+    loop {
+        rebuild(&args, &config, &state, forced);
+
+        let now = OffsetDateTime::now_utc().to_timezone(time_zone);
+        let next = schedule
+            .next_after(now)
+            .ok_or_else(|| eyre!("schedule {} never matches", schedule))?;
+        info!("Next rebuild at {}", next);
+        forced = wait_for_rebuild(
+            &rebuild_requested,
+            (next - now).try_into().unwrap_or_default(),
+        );
+    }
+}
+
+/// Waits until the next scheduled rebuild or a rebuild request, returns true for a request
+fn wait_for_rebuild(rebuild_requested: &Receiver<()>, timeout: std::time::Duration) -> bool {
+    match rebuild_requested.recv_timeout(timeout) {
+        Ok(()) => {
+            // requests which arrived meanwhile are covered by this rebuild as well
+            while rebuild_requested.try_recv().is_ok() {}
+            info!("Rebuild requested");
+            true
+        }
+        Err(RecvTimeoutError::Timeout) => false,
+        // the server is gone, keep to the schedule
+        Err(RecvTimeoutError::Disconnected) => {
+            thread::sleep(timeout);
+            false
+        }
+    }
+}
+
+/// Fetches the sources and rebuilds the site if they changed since the last successful build, or if `forced`
+///
+/// If any source cannot be fetched the previous build is kept, rather than publishing a calendar which silently lacks
+/// the events of that source.
+fn rebuild(args: &Opt, config: &Config, state: &Mutex<DaemonState>, forced: bool) {
+    let now = OffsetDateTime::now_utc();
+    let fetch_started = Instant::now();
+    let mut sources = Vec::new();
+    let mut statuses = Vec::new();
+    for location in SourceLocation::from_args(args) {
+        let mut status = SourceStatus {
+            source: location.name(),
+            ..Default::default()
+        };
+        if let Some(previous) = lock(state)
+            .sources
+            .iter()
+            .find(|previous| previous.source == status.source)
+        {
+            status.last_fetched = previous.last_fetched;
+            status.warnings = previous.warnings;
+        }
+        match location.read() {
+            Ok(data) => {
+                status.last_fetched = Some(now);
+                sources.push(data);
+            }
+            Err(error) => status.error = Some(format!("{:#}", error)),
+        }
+        statuses.push(status);
+    }
+    let fetch_timing = PhaseTiming::since("fetch", fetch_started);
+    let failed_sources = statuses.iter().filter(|s| s.error.is_some()).count();
+    let source_count = statuses.len();
+    lock(state).sources = statuses;
+
+    let result = if failed_sources > 0 {
+        Err(eyre!(
+            "could not fetch {} of {} sources, keeping the previous build",
+            failed_sources,
+            source_count
+        ))
+    } else {
+        build_if_changed(sources, fetch_timing, config, state, forced)
+    };
+
+    let mut state = lock(state);
+    state.last_check = Some(now);
+    if result.is_ok() {
+        state.last_up_to_date = Some(OffsetDateTime::now_utc());
+    }
+    state.last_error = result.err().map(|error| {
+        error!("Rebuild failed: {:#}", error);
+        format!("{:#}", error)
+    });
+}
+
+fn build_if_changed(
+    sources: Vec<SourceData>,
+    fetch_timing: PhaseTiming,
+    config: &Config,
+    state: &Mutex<DaemonState>,
+    forced: bool,
+) -> Result<()> {
+    let mut hasher = DefaultHasher::new();
+    sources.hash(&mut hasher);
+    let sources_hash = hasher.finish();
+    if !forced && lock(state).sources_hash == Some(sources_hash) {
+        info!("Sources did not change, skipping the rebuild");
+        return Ok(());
+    }
+
+    let mut calendar_collection = CalendarCollection::from_sources(sources, config)?;
+    calendar_collection.add_earlier_timing(fetch_timing);
+    let report = build::build_site(&calendar_collection, config, BuildOptions::default())?;
+
+    let mut state = lock(state);
+    state.sources_hash = Some(sources_hash);
+    state.last_success = Some(OffsetDateTime::now_utc());
+    state.builds += 1;
+    state.warnings = report.sources.iter().map(|s| s.warnings.len()).sum();
+    for summary in &report.sources {
+        if let Some(status) = state
+            .sources
+            .iter_mut()
+            .find(|status| status.source == summary.source)
+        {
+            status.warnings = summary.warnings.len();
+        }
+    }
+    Ok(())
+}
+
+/// The HTTP side of the daemon
+struct Server {
+    state: Arc<Mutex<DaemonState>>,
+    rebuild_token: String,
+    /// How long the site may go without being confirmed up to date before it is reported as stale
+    max_age: Option<Duration>,
+    rebuild_requests: Sender<()>,
+}
+
+impl Server {
+    /// Answers HTTP requests one at a time, none of them take long
+    fn serve(&self, listener: TcpListener) {
+        for stream in listener.incoming().flatten() {
+            if let Err(error) = self.handle_request(stream) {
+                warn!("Could not answer request: {:#}", error);
+            }
+        }
+    }
+
+    fn handle_request(&self, mut stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut authorization = None;
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("authorization") {
+                    authorization = Some(value.trim().to_string());
+                }
+            }
+            header.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/healthz")) => {
+                let state = lock(&self.state);
+                let health = Health {
+                    healthy: state.healthy(),
+                    stale: state.stale(self.max_age, OffsetDateTime::now_utc()),
+                    state: &state,
+                };
+                let status = if health.healthy && !health.stale {
+                    "200 OK"
+                } else {
+                    "503 Service Unavailable"
+                };
+                (status, serde_json::to_string_pretty(&health)?)
+            }
+            (Some("POST"), Some("/rebuild")) if self.rebuild_token.is_empty() => (
+                "404 Not Found",
+                error_body("set daemon_rebuild_token to enable rebuilds"),
+            ),
+            (Some("POST"), Some("/rebuild")) => {
+                let token = authorization
+                    .as_deref()
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .unwrap_or_default();
+                if !tokens_match(token.trim(), &self.rebuild_token) {
+                    ("401 Unauthorized", error_body("invalid token"))
+                } else if self.rebuild_requests.send(()).is_err() {
+                    (
+                        "503 Service Unavailable",
+                        error_body("not accepting rebuilds"),
+                    )
+                } else {
+                    (
+                        "202 Accepted",
+                        serde_json::json!({ "rebuild": "scheduled" }).to_string(),
+                    )
+                }
+            }
+            _ => ("404 Not Found", error_body("not found")),
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+/// Compares tokens without returning early, so the response time does not reveal how much of a token was right
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Locks the state, a panic while holding the lock does not make the state unusable
+fn lock(state: &Mutex<DaemonState>) -> std::sync::MutexGuard<'_, DaemonState> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
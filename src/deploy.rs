@@ -0,0 +1,252 @@
+//! Deploys the built site to a hosting service.
+//!
+//! The files to deploy are the ones recorded in the manifest of the last build. Netlify only asks for the files whose
+//! digest it does not have yet, SFTP targets get the files whose hash changed since the last deploy, which the
+//! manifest remembers.
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use serde::Deserialize;
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::{debug, info};
+
+use crate::config::{Config, DeployTarget};
+use crate::manifest::Manifest;
+use crate::upload::{hex, site_files};
+
+/// The API Netlify deploys are created with
+const NETLIFY_API: &str = "https://api.netlify.com/api/v1";
+
+/// Pushes the files of a build to a hosting service
+trait Deployer {
+    /// Deploys `files`, relative to `output_dir`, and returns how many of them were sent
+    fn deploy(
+        &self,
+        output_dir: &Path,
+        files: &BTreeSet<PathBuf>,
+        manifest: &mut Manifest,
+    ) -> Result<usize>;
+}
+
+/// Deploys the last build in the output directory to the configured `deploy_target`
+///
+/// Only the files which changed since the last deploy are sent, the manifest is saved with what was deployed.
+pub fn deploy(output_dir: &Path, config: &Config) -> Result<()> {
+    let deployer: Box<dyn Deployer> = match config.deploy_target {
+        DeployTarget::None => bail!("set deploy_target to netlify or sftp to deploy the site"),
+        DeployTarget::Netlify => Box::new(Netlify::from_config(config)?),
+        DeployTarget::Sftp => Box::new(Sftp::from_config(config)?),
+    };
+    let mut manifest = Manifest::load(output_dir)?.ok_or_else(|| {
+        eyre!(
+            "no build manifest in {:?}, build the site first",
+            output_dir
+        )
+    })?;
+
+    let files = site_files(output_dir, &manifest);
+    let result = deployer.deploy(output_dir, &files, &mut manifest);
+    // keep what was deployed so far, so it is not sent again
+    manifest.save(output_dir)?;
+    let sent = result?;
+    info!(
+        "Deployed {} changed files of {} to {}",
+        sent,
+        files.len(),
+        config.deploy_target.name()
+    );
+    Ok(())
+}
+
+/// A Netlify site, deployed through the file digest API with `NETLIFY_AUTH_TOKEN`
+struct Netlify {
+    site_id: String,
+    authorization: String,
+}
+
+/// The answer of Netlify to a new deploy
+#[derive(Debug, Deserialize)]
+struct NetlifyDeploy {
+    id: String,
+    /// The SHA-1 digests of the files Netlify does not have yet
+    #[serde(default)]
+    required: Vec<String>,
+}
+
+impl Netlify {
+    fn from_config(config: &Config) -> Result<Netlify> {
+        if config.deploy_site_id.is_empty() {
+            bail!("deploying to Netlify needs deploy_site_id");
+        }
+        let token = std::env::var("NETLIFY_AUTH_TOKEN")
+            .wrap_err("deploying to Netlify needs NETLIFY_AUTH_TOKEN to be set")?;
+        Ok(Netlify {
+            site_id: config.deploy_site_id.clone(),
+            authorization: format!("Bearer {}", token),
+        })
+    }
+}
+
+impl Deployer for Netlify {
+    fn deploy(
+        &self,
+        output_dir: &Path,
+        files: &BTreeSet<PathBuf>,
+        _manifest: &mut Manifest,
+    ) -> Result<usize> {
+        let mut digests = BTreeMap::new();
+        for file in files {
+            let contents =
+                fs::read(output_dir.join(file)).wrap_err(format!("could not read {:?}", file))?;
+            digests.insert(netlify_path(file), hex(&Sha1::digest(&contents)));
+        }
+
+        let response = ureq::post(&format!("{}/sites/{}/deploys", NETLIFY_API, self.site_id))
+            .set("Authorization", &self.authorization)
+            .set("Content-Type", "application/json")
+            .send_string(&json!({ "files": digests }).to_string())
+            .wrap_err("could not create the Netlify deploy")?
+            .into_string()?;
+        let deploy: NetlifyDeploy =
+            serde_json::from_str(&response).wrap_err("could not read the Netlify deploy")?;
+
+        let required: BTreeSet<&str> = deploy.required.iter().map(String::as_str).collect();
+        let mut sent = 0;
+        for file in files {
+            let path = netlify_path(file);
+            if !required.contains(digests[&path].as_str()) {
+                continue;
+            }
+            debug!("Uploading {:?}", file);
+            let contents = fs::read(output_dir.join(file))?;
+            ureq::put(&format!(
+                "{}/deploys/{}/files{}",
+                NETLIFY_API, deploy.id, path
+            ))
+            .set("Authorization", &self.authorization)
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(&contents)
+            .wrap_err(format!("could not upload {:?} to Netlify", file))?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+}
+
+/// The path of a file in a Netlify deploy, absolute with forward slashes
+fn netlify_path(file: &Path) -> String {
+    file.components()
+        .map(|component| format!("/{}", component.as_os_str().to_string_lossy()))
+        .collect()
+}
+
+/// A directory on an SFTP server, written with the `sftp` command and the usual SSH keys and agent
+struct Sftp {
+    host: String,
+    path: String,
+}
+
+impl Sftp {
+    fn from_config(config: &Config) -> Result<Sftp> {
+        if config.deploy_host.is_empty() {
+            bail!("deploying over SFTP needs deploy_host");
+        }
+        Ok(Sftp {
+            host: config.deploy_host.clone(),
+            path: config.deploy_path.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn remote_path(&self, file: &Path) -> String {
+        let file = file.to_string_lossy();
+        if self.path.is_empty() {
+            file.to_string()
+        } else {
+            format!("{}/{}", self.path, file)
+        }
+    }
+}
+
+impl Deployer for Sftp {
+    fn deploy(
+        &self,
+        output_dir: &Path,
+        files: &BTreeSet<PathBuf>,
+        manifest: &mut Manifest,
+    ) -> Result<usize> {
+        let mut batch = Vec::new();
+        let mut changed = BTreeMap::new();
+        let mut dirs = BTreeSet::new();
+        for file in files {
+            let local = output_dir.join(file);
+            let contents = fs::read(&local).wrap_err(format!("could not read {:?}", file))?;
+            let hash = hex(&Sha256::digest(&contents));
+            if manifest.deployed.get(file) == Some(&hash) {
+                continue;
+            }
+            for dir in file
+                .ancestors()
+                .skip(1)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+            {
+                if !dir.as_os_str().is_empty() && dirs.insert(dir.to_path_buf()) {
+                    // a leading `-` lets the batch continue when the directory exists
+                    batch.push(format!("-mkdir \"{}\"", self.remote_path(dir)));
+                }
+            }
+            batch.push(format!(
+                "put \"{}\" \"{}\"",
+                local.to_string_lossy(),
+                self.remote_path(file)
+            ));
+            changed.insert(file.clone(), hash);
+        }
+        let removed: Vec<PathBuf> = manifest
+            .deployed
+            .keys()
+            .filter(|file| !files.contains(*file))
+            .cloned()
+            .collect();
+        for file in &removed {
+            batch.push(format!("-rm \"{}\"", self.remote_path(file)));
+        }
+        if changed.is_empty() && removed.is_empty() {
+            return Ok(0);
+        }
+
+        debug!(
+            "Running sftp with {} commands for {}",
+            batch.len(),
+            self.host
+        );
+        let mut sftp = Command::new("sftp")
+            .args(["-b", "-", &self.host])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .wrap_err("could not run sftp")?;
+        sftp.stdin
+            .take()
+            .ok_or_else(|| eyre!("could not write to sftp"))?
+            .write_all(format!("{}\n", batch.join("\n")).as_bytes())?;
+        let status = sftp.wait()?;
+        if !status.success() {
+            bail!("sftp to {} failed with {}", self.host, status);
+        }
+
+        let sent = changed.len();
+        manifest.deployed.extend(changed);
+        for file in removed {
+            manifest.deployed.remove(&file);
+        }
+        Ok(sent)
+    }
+}
@@ -0,0 +1,144 @@
+//! Compares the output of two builds.
+//!
+//! Events are compared through the records of the manifests, pages by their contents on disk, so the result shows
+//! both what changed upstream and what it changed on the site.
+
+use color_eyre::eyre::{eyre, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::macros::format_description;
+use tracing::info;
+
+use crate::manifest::{EventRecord, Manifest};
+
+/// The differences between two builds
+#[derive(Debug, Default, Serialize)]
+pub struct BuildDiff {
+    /// Event instances only the new build has
+    pub added_events: Vec<EventRecord>,
+    /// Event instances only the old build has
+    pub removed_events: Vec<EventRecord>,
+    /// Event instances both builds have, with different content
+    pub changed_events: Vec<ChangedEvent>,
+    /// Files only the new build wrote, relative to its output directory
+    pub added_pages: Vec<PathBuf>,
+    /// Files only the old build wrote
+    pub removed_pages: Vec<PathBuf>,
+    /// Files both builds wrote, with different contents
+    pub changed_pages: Vec<PathBuf>,
+}
+
+/// An event instance as the old and the new build have it
+#[derive(Debug, Serialize)]
+pub struct ChangedEvent {
+    pub old: EventRecord,
+    pub new: EventRecord,
+}
+
+impl BuildDiff {
+    /// Compares the builds in two output directories, both need the manifest written by their build
+    pub fn between(old_dir: &Path, new_dir: &Path) -> Result<BuildDiff> {
+        let load = |dir: &Path| {
+            Manifest::load(dir)?.ok_or_else(|| eyre!("no build manifest in {:?}", dir))
+        };
+        let (old, new) = (load(old_dir)?, load(new_dir)?);
+
+        let mut diff = BuildDiff::default();
+        for (id, event) in &new.events {
+            match old.events.get(id) {
+                None => diff.added_events.push(event.clone()),
+                Some(old_event) if old_event != event => diff.changed_events.push(ChangedEvent {
+                    old: old_event.clone(),
+                    new: event.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        diff.removed_events = old
+            .events
+            .iter()
+            .filter(|(id, _)| !new.events.contains_key(*id))
+            .map(|(_, event)| event.clone())
+            .collect();
+
+        for file in &new.files {
+            if !old.files.contains(file) {
+                diff.added_pages.push(file.clone());
+            } else if fs::read(old_dir.join(file)).ok() != fs::read(new_dir.join(file)).ok() {
+                diff.changed_pages.push(file.clone());
+            }
+        }
+        diff.removed_pages = old.files.difference(&new.files).cloned().collect();
+        Ok(diff)
+    }
+
+    /// Whether the builds have the same events and pages
+    pub fn is_empty(&self) -> bool {
+        self.added_events.is_empty()
+            && self.removed_events.is_empty()
+            && self.changed_events.is_empty()
+            && self.added_pages.is_empty()
+            && self.removed_pages.is_empty()
+            && self.changed_pages.is_empty()
+    }
+
+    /// Prints the differences with one line per event and page
+    pub fn print(&self) {
+        if self.is_empty() {
+            info!("The builds have the same events and pages");
+            return;
+        }
+        for event in &self.added_events {
+            info!("+ event {}", describe(event));
+        }
+        for event in &self.removed_events {
+            info!("- event {}", describe(event));
+        }
+        for ChangedEvent { old, new } in &self.changed_events {
+            info!("~ event {}", describe(old));
+            info!("     to {}", describe(new));
+        }
+        for page in &self.added_pages {
+            info!("+ page {}", page.display());
+        }
+        for page in &self.removed_pages {
+            info!("- page {}", page.display());
+        }
+        for page in &self.changed_pages {
+            info!("~ page {}", page.display());
+        }
+        info!(
+            "{} events added, {} removed, {} changed; {} pages added, {} removed, {} changed",
+            self.added_events.len(),
+            self.removed_events.len(),
+            self.changed_events.len(),
+            self.added_pages.len(),
+            self.removed_pages.len(),
+            self.changed_pages.len()
+        );
+    }
+}
+
+/// An event on a single line, e.g. `2024-05-14 18:00 Open mic (Main hall)`
+pub fn describe(event: &EventRecord) -> String {
+    let start = event
+        .start
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap_or_default();
+    match &event.location {
+        Some(location) => format!("{} {} ({})", start, event.summary, location),
+        None => format!("{} {}", start, event.summary),
+    }
+}
+
+/// Prints the differences between the builds in two output directories, as JSON if `json` is set
+pub fn diff(old_dir: &Path, new_dir: &Path, json: bool) -> Result<()> {
+    let diff = BuildDiff::between(old_dir, new_dir)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        diff.print();
+    }
+    Ok(())
+}
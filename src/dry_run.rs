@@ -0,0 +1,76 @@
+//! Reports what a build would write without touching the output directory.
+
+use clap::ArgEnum;
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::build;
+use crate::config::Config;
+use crate::model::calendar_collection::CalendarCollection;
+
+/// How the files of a dry run are printed
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DryRunFormat {
+    /// One line per file
+    #[default]
+    Text,
+    /// A JSON array of the files, e.g. for CI checks
+    Json,
+}
+
+/// A file a build would write
+#[derive(Debug, Serialize)]
+pub struct PlannedFile {
+    /// The file, relative to the output directory
+    pub file: PathBuf,
+    /// The number of events on the page or in the feed, `None` for other files like `freebusy.ics`
+    pub events: Option<usize>,
+}
+
+/// Renders every enabled view of the collection without writing anything and prints the files it would write
+pub fn dry_run(
+    mut calendar_collection: CalendarCollection,
+    config: &Config,
+    format: DryRunFormat,
+) -> Result<()> {
+    calendar_collection.set_dry_run();
+    for (_view, enabled, create_pages) in build::views(config) {
+        if enabled {
+            create_pages(&calendar_collection)?;
+        }
+    }
+
+    let output_dir = Path::new(&config.output_dir);
+    let event_counts = calendar_collection.event_counts();
+    let files: Vec<PlannedFile> = calendar_collection
+        .written_files()
+        .into_iter()
+        .map(|file| PlannedFile {
+            events: event_counts.get(&file).copied(),
+            file: file
+                .strip_prefix(output_dir)
+                .map(Path::to_path_buf)
+                .unwrap_or(file),
+        })
+        .collect();
+
+    match format {
+        DryRunFormat::Json => println!("{}", serde_json::to_string_pretty(&files)?),
+        DryRunFormat::Text => {
+            for planned in &files {
+                match planned.events {
+                    Some(events) => info!("{} ({} events)", planned.file.display(), events),
+                    None => info!("{}", planned.file.display()),
+                }
+            }
+            info!(
+                "Would write {} files into {:?}",
+                files.len(),
+                config.output_dir
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+//! Extra data attached to the days of every view, e.g. a weather forecast.
+//!
+//! statical ships no clients for weather or similar services. A `DayEnricher` provides a JSON value per day instead,
+//! which templates get as `day.extra.<name>`. The enrichers configured with `[[day_enrichments]]` read the values from
+//! a JSON file or from the output of a command, embedders can add their own with
+//! `CalendarCollection::add_day_enrichment()`.
+
+use color_eyre::eyre::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::Command;
+use time::{macros::format_description, Date};
+use tracing::warn;
+
+use crate::config::{Config, DayEnrichment};
+
+/// Provides extra data for days
+pub trait DayEnricher {
+    /// The key of the data in `day.extra`, e.g. "weather"
+    fn name(&self) -> &str;
+
+    /// Returns the data of the days from `first` to `last`, days without data are left out
+    fn enrich(&self, first: Date, last: Date) -> Result<BTreeMap<Date, Value>>;
+}
+
+/// Reads the data from a JSON object keyed by day, e.g. `{"2024-05-14": {"forecast": "sunny"}}`
+#[derive(Debug, Clone)]
+pub struct FileEnricher {
+    name: String,
+    path: String,
+}
+
+impl FileEnricher {
+    pub fn new(name: impl Into<String>, path: impl Into<String>) -> FileEnricher {
+        FileEnricher {
+            name: name.into(),
+            path: path.into(),
+        }
+    }
+}
+
+impl DayEnricher for FileEnricher {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn enrich(&self, first: Date, last: Date) -> Result<BTreeMap<Date, Value>> {
+        let json = fs::read_to_string(&self.path)
+            .context(format!("could not read day data {:?}", self.path))?;
+        let mut days = parse_days(&json).context(format!("invalid day data in {:?}", self.path))?;
+        days.retain(|date, _| (first..=last).contains(date));
+        Ok(days)
+    }
+}
+
+/// Runs a shell command which prints a JSON object keyed by day, like the file of a `FileEnricher`
+///
+/// The first and last day are passed as arguments (`YYYY-MM-DD`), so the command can limit what it fetches.
+#[derive(Debug, Clone)]
+pub struct CommandEnricher {
+    name: String,
+    command: String,
+}
+
+impl CommandEnricher {
+    pub fn new(name: impl Into<String>, command: impl Into<String>) -> CommandEnricher {
+        CommandEnricher {
+            name: name.into(),
+            command: command.into(),
+        }
+    }
+}
+
+impl DayEnricher for CommandEnricher {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn enrich(&self, first: Date, last: Date) -> Result<BTreeMap<Date, Value>> {
+        let output = Command::new("sh")
+            // `"$@"` hands the days on to the command, e.g. `./weather.sh 2024-05-01 2024-05-31`
+            .args([
+                "-c",
+                &format!("{} \"$@\"", self.command),
+                "sh",
+                &day_name(first),
+                &day_name(last),
+            ])
+            .output()
+            .context(format!("could not run {:?}", self.command))?;
+        if !output.status.success() {
+            bail!(
+                "{:?} failed with {}: {}",
+                self.command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let mut days = parse_days(&String::from_utf8_lossy(&output.stdout))
+            .context(format!("invalid day data from {:?}", self.command))?;
+        days.retain(|date, _| (first..=last).contains(date));
+        Ok(days)
+    }
+}
+
+/// Creates the enrichers of `Config::day_enrichments`, skipping entries without a file or command
+pub fn configured_enrichers(config: &Config) -> Vec<Box<dyn DayEnricher>> {
+    let mut enrichers: Vec<Box<dyn DayEnricher>> = Vec::new();
+    for DayEnrichment {
+        name,
+        file,
+        command,
+    } in &config.day_enrichments
+    {
+        match (command, file) {
+            (Some(command), _) => enrichers.push(Box::new(CommandEnricher::new(name, command))),
+            (None, Some(file)) => enrichers.push(Box::new(FileEnricher::new(name, file))),
+            (None, None) => warn!(
+                "Skipping day enrichment {:?}, it has neither a file nor a command",
+                name
+            ),
+        }
+    }
+    enrichers
+}
+
+/// Parses a JSON object keyed by day, keys which are not days are skipped with a warning
+fn parse_days(json: &str) -> Result<BTreeMap<Date, Value>> {
+    let object: BTreeMap<String, Value> = serde_json::from_str(json)?;
+    Ok(object
+        .into_iter()
+        .filter_map(|(key, value)| {
+            match Date::parse(&key, format_description!("[year]-[month]-[day]")) {
+                Ok(date) => Some((date, value)),
+                Err(_) => {
+                    warn!(
+                        "Skipping day data for {:?}, it is not a day (YYYY-MM-DD)",
+                        key
+                    );
+                    None
+                }
+            }
+        })
+        .collect())
+}
+
+fn day_name(date: Date) -> String {
+    date.format(format_description!("[year]-[month]-[day]"))
+        .unwrap_or_default()
+}
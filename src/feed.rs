@@ -0,0 +1,217 @@
+//! Atom and JSON feeds of the upcoming events.
+//!
+//! Entry ids come from the stable event instance ids and the updated times from the events' `LAST-MODIFIED`, so
+//! rebuilding the site does not make feed readers see every event as new, while edited events show up as updates.
+
+use color_eyre::eyre::Result;
+use serde_json::json;
+use std::path::PathBuf;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Name of the Atom feed written into the output directory
+pub const ATOM_FILE_NAME: &str = "feed.atom";
+/// Name of the JSON feed written into the output directory
+pub const JSON_FILE_NAME: &str = "feed.json";
+/// Content type of the Atom feeds
+pub const ATOM_CONTENT_TYPE: &str = "application/atom+xml";
+/// Content type of the JSON feeds
+pub const JSON_CONTENT_TYPE: &str = "application/feed+json";
+
+/// Directory of the feeds of a single category or calendar, within the output directory
+pub const SLICE_DIR: &str = "feeds";
+
+/// Which of the upcoming events a feed contains
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedFilter {
+    All,
+    /// The events with a category of this slug, see `slug()`
+    Category(String),
+    /// The events of the calendars whose name has this slug
+    Calendar(String),
+    /// The events which were added, moved, or cancelled since earlier builds, see `changes`
+    Changes,
+}
+
+/// A feed of all upcoming events or of one category or calendar
+#[derive(Debug, Clone)]
+pub struct FeedSlice {
+    pub title: String,
+    pub filter: FeedFilter,
+}
+
+impl FeedSlice {
+    /// The Atom feed, relative to the output directory, e.g. `feeds/category/music.atom`
+    pub fn atom_page(&self) -> PathBuf {
+        self.page(ATOM_FILE_NAME)
+    }
+
+    /// The JSON feed, relative to the output directory
+    pub fn json_page(&self) -> PathBuf {
+        self.page(JSON_FILE_NAME)
+    }
+
+    fn page(&self, file_name: &str) -> PathBuf {
+        let extension = file_name.rsplit('.').next().unwrap_or_default();
+        match &self.filter {
+            FeedFilter::All => PathBuf::from(file_name),
+            FeedFilter::Category(slug) => PathBuf::from(SLICE_DIR)
+                .join("category")
+                .join(format!("{}.{}", slug, extension)),
+            FeedFilter::Calendar(slug) => PathBuf::from(SLICE_DIR)
+                .join("calendar")
+                .join(format!("{}.{}", slug, extension)),
+            FeedFilter::Changes => PathBuf::from(format!("changes.{}", extension)),
+        }
+    }
+
+    /// The id of the feed when it has no public address
+    pub fn urn(&self) -> String {
+        match &self.filter {
+            FeedFilter::All => "urn:statical:feed".to_string(),
+            FeedFilter::Category(slug) => format!("urn:statical:feed:category:{}", slug),
+            FeedFilter::Calendar(slug) => format!("urn:statical:feed:calendar:{}", slug),
+            FeedFilter::Changes => "urn:statical:feed:changes".to_string(),
+        }
+    }
+}
+
+/// Turns a category or calendar name into a file name, e.g. "Live Music" into `live-music`
+pub fn slug(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// A single event in a feed
+#[derive(Debug, Clone)]
+pub struct FeedEntry {
+    /// The stable instance id of the event
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    /// Link to the page showing the event
+    pub link: String,
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    /// When the event was last changed, events without a modification time use their start
+    pub updated: OffsetDateTime,
+    /// The `SEQUENCE` of the event
+    pub sequence: u32,
+}
+
+impl FeedEntry {
+    /// The globally unique id of the entry
+    pub fn guid(&self) -> String {
+        format!("urn:statical:event:{}", self.id)
+    }
+
+    /// A plain text summary of when and where the event takes place, followed by its description
+    fn content(&self) -> String {
+        let mut content = format!("{} - {}", rfc3339(self.start), rfc3339(self.end));
+        if let Some(location) = &self.location {
+            content.push_str(&format!("\n{}", location));
+        }
+        if let Some(description) = &self.description {
+            content.push_str(&format!("\n\n{}", description));
+        }
+        content
+    }
+}
+
+/// Renders an Atom feed
+///
+/// `feed_url` is the public address of the feed, if known, and `urn` its id otherwise. The feed is only updated when
+/// one of its entries is, so rebuilding without changes produces the same feed.
+pub fn atom(title: &str, urn: &str, feed_url: Option<&str>, entries: &[FeedEntry]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape(title)));
+    xml.push_str(&format!("  <id>{}</id>\n", escape(feed_url.unwrap_or(urn))));
+    if let Some(feed_url) = feed_url {
+        xml.push_str(&format!(
+            "  <link rel=\"self\" href=\"{}\" />\n",
+            escape(feed_url)
+        ));
+    }
+    xml.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        rfc3339(last_updated(entries))
+    ));
+    for entry in entries {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}</id>\n", escape(&entry.guid())));
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&entry.title)));
+        xml.push_str(&format!("    <link href=\"{}\" />\n", escape(&entry.link)));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            rfc3339(entry.updated)
+        ));
+        xml.push_str(&format!(
+            "    <content type=\"text\">{}</content>\n",
+            escape(&entry.content())
+        ));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Renders a JSON feed (version 1.1)
+///
+/// The event times and sequence are added to each item as a `_statical` extension.
+pub fn json_feed(title: &str, feed_url: Option<&str>, entries: &[FeedEntry]) -> Result<String> {
+    let items: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "id": entry.guid(),
+                "url": entry.link,
+                "title": entry.title,
+                "content_text": entry.content(),
+                "date_modified": rfc3339(entry.updated),
+                "_statical": {
+                    "start": rfc3339(entry.start),
+                    "end": rfc3339(entry.end),
+                    "location": entry.location,
+                    "sequence": entry.sequence,
+                },
+            })
+        })
+        .collect();
+    let mut feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": title,
+        "items": items,
+    });
+    if let Some(feed_url) = feed_url {
+        feed["feed_url"] = json!(feed_url);
+    }
+    Ok(serde_json::to_string_pretty(&feed)?)
+}
+
+/// The latest update of any entry
+fn last_updated(entries: &[FeedEntry]) -> OffsetDateTime {
+    entries
+        .iter()
+        .map(|entry| entry.updated)
+        .max()
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+fn rfc3339(time: OffsetDateTime) -> String {
+    time.format(&Rfc3339)
+        .unwrap_or_else(|_| "1970-01-01T00:00:00Z".to_string())
+}
+
+/// Escapes text for use in XML content and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
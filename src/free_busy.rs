@@ -0,0 +1,88 @@
+//! Publishes the availability of the calendars as a `VFREEBUSY` component, for scheduling tools which only need to
+//! know when the calendar is busy.
+
+use time::{macros::format_description, OffsetDateTime, UtcOffset};
+
+use crate::model::event::BusyStatus;
+
+/// Name of the free/busy file written into the output directory
+pub const FILE_NAME: &str = "freebusy.ics";
+
+/// A busy interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusyPeriod {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub status: BusyStatus,
+}
+
+/// Clips the periods to `start`..`end`, drops free ones, and merges overlapping periods of the same status
+pub fn merge_periods(
+    mut periods: Vec<BusyPeriod>,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Vec<BusyPeriod> {
+    periods.retain(|period| {
+        period.status != BusyStatus::Free && period.end > start && period.start < end
+    });
+    for period in periods.iter_mut() {
+        period.start = period.start.max(start);
+        period.end = period.end.min(end);
+    }
+    periods.sort_by_key(|period| (period.status as u8, period.start));
+
+    let mut merged: Vec<BusyPeriod> = Vec::new();
+    for period in periods {
+        match merged.last_mut() {
+            Some(last) if last.status == period.status && period.start <= last.end => {
+                last.end = last.end.max(period.end)
+            }
+            _ => merged.push(period),
+        }
+    }
+    merged.sort_by_key(|period| period.start);
+    merged
+}
+
+/// Renders a calendar with a single `VFREEBUSY` covering `start`..`end`
+///
+/// `start` doubles as the `DTSTAMP`, so the file only changes when the window or the periods do.
+pub fn vfreebusy(start: OffsetDateTime, end: OffsetDateTime, periods: &[BusyPeriod]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//statical//free busy//EN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        "BEGIN:VFREEBUSY".to_string(),
+        format!("UID:statical-freebusy-{}", ics_time(start)),
+        format!("DTSTAMP:{}", ics_time(start)),
+        format!("DTSTART:{}", ics_time(start)),
+        format!("DTEND:{}", ics_time(end)),
+    ];
+    for period in periods {
+        let fb_type = match period.status {
+            BusyStatus::Tentative => "BUSY-TENTATIVE",
+            BusyStatus::Oof => "BUSY-UNAVAILABLE",
+            _ => "BUSY",
+        };
+        lines.push(format!(
+            "FREEBUSY;FBTYPE={}:{}/{}",
+            fb_type,
+            ics_time(period.start),
+            ics_time(period.end)
+        ));
+    }
+    lines.push("END:VFREEBUSY".to_string());
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| format!("{}\r\n", line)).collect()
+}
+
+/// Formats a time in UTC like `20240514T090000Z`
+pub fn ics_time(time: OffsetDateTime) -> String {
+    time.to_offset(UtcOffset::UTC)
+        .format(format_description!(
+            "[year][month][day]T[hour][minute][second]Z"
+        ))
+        .unwrap_or_default()
+}
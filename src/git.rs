@@ -0,0 +1,124 @@
+//! Commits the output directory to a git repository after a build.
+//!
+//! Hosting which publishes a branch, like GitHub or GitLab Pages, then deploys every build which changed the site. The
+//! output directory is made a repository of its own if it is not one yet.
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tera::{Context, Tera};
+use time::OffsetDateTime;
+use tracing::{debug, info};
+
+use crate::config::Config;
+use crate::manifest::Manifest;
+use crate::report::BuildReport;
+use crate::upload::site_files;
+
+/// Commits the files of the build with `commit_message`, and pushes them if `commit_push` is set
+///
+/// The repository tracks exactly the files of the site: files of earlier builds which the site no longer has are
+/// removed from it, the manifest and the report are never added. Nothing is committed when the site did not change.
+pub fn commit_output(
+    output_dir: &Path,
+    manifest: &Manifest,
+    report: &BuildReport,
+    config: &Config,
+) -> Result<()> {
+    if !output_dir.join(".git").exists() {
+        info!("Creating a git repository in {:?}", output_dir);
+        git(output_dir, &["init", "--quiet"], None)?;
+    }
+    let files = site_files(output_dir, manifest);
+    let tracked = git(output_dir, &["ls-files", "-z"], None)?;
+    let removed: Vec<&str> = std::str::from_utf8(&tracked.stdout)
+        .wrap_err("git listed a file name which is not UTF-8")?
+        .split('\0')
+        .filter(|file| !file.is_empty() && !files.contains(Path::new(file)))
+        .collect();
+    if !removed.is_empty() {
+        git(
+            output_dir,
+            &["rm", "--cached", "--quiet", "--pathspec-from-file=-"],
+            Some(removed.join("\n")),
+        )?;
+    }
+    let added: Vec<String> = files
+        .iter()
+        .map(|file| file.to_string_lossy().to_string())
+        .collect();
+    git(
+        output_dir,
+        &["add", "--all", "--pathspec-from-file=-"],
+        Some(added.join("\n")),
+    )?;
+    let staged = git(output_dir, &["diff", "--cached", "--name-only"], None)?;
+    let changed = String::from_utf8_lossy(&staged.stdout).lines().count();
+    if changed == 0 {
+        info!("The site did not change, not committing {:?}", output_dir);
+        return Ok(());
+    }
+
+    let mut context = Context::new();
+    context.insert("changed", &changed);
+    context.insert("files", &files.len());
+    context.insert("events", &manifest.events.len());
+    context.insert("sources", &report.sources.len());
+    context.insert(
+        "warnings",
+        &report
+            .sources
+            .iter()
+            .map(|source| source.warnings.len())
+            .sum::<usize>(),
+    );
+    context.insert("date", &OffsetDateTime::now_utc().date().to_string());
+    let message = Tera::one_off(&config.commit_message, &context, false)
+        .wrap_err("could not render commit_message")?;
+    git(
+        output_dir,
+        &["commit", "--quiet", "--message", &message],
+        None,
+    )?;
+    info!("Committed {} changed files in {:?}", changed, output_dir);
+
+    if config.commit_push {
+        git(output_dir, &["push", "--quiet"], None)?;
+        info!("Pushed the output repository");
+    }
+    Ok(())
+}
+
+/// Runs a git command in `dir` with `input` on its standard input, and fails with its error output if it does not
+/// succeed
+fn git(dir: &Path, args: &[&str], input: Option<String>) -> Result<Output> {
+    debug!("Running git {}", args.join(" "));
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .wrap_err("could not run git")?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| eyre!("could not write to git"))?;
+    if let Some(input) = input {
+        stdin.write_all(input.as_bytes())?;
+    }
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed with {}: {}",
+            args.first().copied().unwrap_or_default(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output)
+}
@@ -0,0 +1,192 @@
+//! Public holidays computed from a country code, so sites need no holiday calendar feed.
+//!
+//! The rules cover the national holidays of a few countries, in the language of the country. Regional holidays,
+//! substitute days for holidays falling on a weekend, and one-off holidays are not included.
+
+use color_eyre::eyre::{bail, Result};
+use time::ext::NumericalDuration;
+use time::{Date, Month, Weekday};
+
+/// The countries with holiday rules, as ISO 3166-1 alpha-2 codes
+pub const COUNTRIES: [&str; 6] = ["CA", "CH", "DE", "FR", "GB", "US"];
+
+/// When a holiday falls in a year
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    /// The same day every year
+    Fixed(Month, u8),
+    /// Days after Easter Sunday, negative for days before it
+    Easter(i64),
+    /// The nth weekday of a month, counting from its end if negative
+    NthWeekday(Month, i8, Weekday),
+    /// The last `Weekday` before the day of the month, e.g. the Monday before May 25
+    WeekdayBefore(Month, u8, Weekday),
+}
+
+/// The holidays of a country, the name and the rule of each
+fn rules(country: &str) -> Option<&'static [(&'static str, Rule)]> {
+    use Month::*;
+    use Rule::*;
+    use Weekday::*;
+
+    let rules: &'static [(&'static str, Rule)] = match country {
+        "CA" => &[
+            ("New Year's Day", Fixed(January, 1)),
+            ("Good Friday", Easter(-2)),
+            ("Victoria Day", WeekdayBefore(May, 25, Monday)),
+            ("Canada Day", Fixed(July, 1)),
+            ("Labour Day", NthWeekday(September, 1, Monday)),
+            (
+                "National Day for Truth and Reconciliation",
+                Fixed(September, 30),
+            ),
+            ("Thanksgiving", NthWeekday(October, 2, Monday)),
+            ("Remembrance Day", Fixed(November, 11)),
+            ("Christmas Day", Fixed(December, 25)),
+            ("Boxing Day", Fixed(December, 26)),
+        ],
+        "CH" => &[
+            ("Neujahr", Fixed(January, 1)),
+            ("Karfreitag", Easter(-2)),
+            ("Ostermontag", Easter(1)),
+            ("Auffahrt", Easter(39)),
+            ("Pfingstmontag", Easter(50)),
+            ("Bundesfeier", Fixed(August, 1)),
+            ("Weihnachten", Fixed(December, 25)),
+            ("Stephanstag", Fixed(December, 26)),
+        ],
+        "DE" => &[
+            ("Neujahr", Fixed(January, 1)),
+            ("Karfreitag", Easter(-2)),
+            ("Ostermontag", Easter(1)),
+            ("Tag der Arbeit", Fixed(May, 1)),
+            ("Christi Himmelfahrt", Easter(39)),
+            ("Pfingstmontag", Easter(50)),
+            ("Tag der Deutschen Einheit", Fixed(October, 3)),
+            ("1. Weihnachtstag", Fixed(December, 25)),
+            ("2. Weihnachtstag", Fixed(December, 26)),
+        ],
+        "FR" => &[
+            ("Jour de l'an", Fixed(January, 1)),
+            ("Lundi de Pâques", Easter(1)),
+            ("Fête du Travail", Fixed(May, 1)),
+            ("Victoire 1945", Fixed(May, 8)),
+            ("Ascension", Easter(39)),
+            ("Lundi de Pentecôte", Easter(50)),
+            ("Fête nationale", Fixed(July, 14)),
+            ("Assomption", Fixed(August, 15)),
+            ("Toussaint", Fixed(November, 1)),
+            ("Armistice 1918", Fixed(November, 11)),
+            ("Noël", Fixed(December, 25)),
+        ],
+        "GB" => &[
+            ("New Year's Day", Fixed(January, 1)),
+            ("Good Friday", Easter(-2)),
+            ("Easter Monday", Easter(1)),
+            ("Early May Bank Holiday", NthWeekday(May, 1, Monday)),
+            ("Spring Bank Holiday", NthWeekday(May, -1, Monday)),
+            ("Summer Bank Holiday", NthWeekday(August, -1, Monday)),
+            ("Christmas Day", Fixed(December, 25)),
+            ("Boxing Day", Fixed(December, 26)),
+        ],
+        "US" => &[
+            ("New Year's Day", Fixed(January, 1)),
+            ("Martin Luther King Jr. Day", NthWeekday(January, 3, Monday)),
+            ("Presidents' Day", NthWeekday(February, 3, Monday)),
+            ("Memorial Day", NthWeekday(May, -1, Monday)),
+            ("Juneteenth", Fixed(June, 19)),
+            ("Independence Day", Fixed(July, 4)),
+            ("Labor Day", NthWeekday(September, 1, Monday)),
+            ("Columbus Day", NthWeekday(October, 2, Monday)),
+            ("Veterans Day", Fixed(November, 11)),
+            ("Thanksgiving", NthWeekday(November, 4, Thursday)),
+            ("Christmas Day", Fixed(December, 25)),
+        ],
+        _ => return None,
+    };
+    Some(rules)
+}
+
+/// Fails unless holiday rules exist for `country`
+pub fn check_country(country: &str) -> Result<()> {
+    if rules(&country.to_ascii_uppercase()).is_none() {
+        bail!(
+            "no holidays known for country {:?}, holidays_country can be one of {}",
+            country,
+            COUNTRIES.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Returns the holidays of `country` in `year` with their names, in order
+pub fn holidays(country: &str, year: i32) -> Result<Vec<(Date, &'static str)>> {
+    check_country(country)?;
+    let rules = rules(&country.to_ascii_uppercase()).unwrap_or_default();
+    let mut holidays = rules
+        .iter()
+        .map(|&(name, rule)| Ok((rule.date(year)?, name)))
+        .collect::<Result<Vec<_>>>()?;
+    holidays.sort();
+    Ok(holidays)
+}
+
+impl Rule {
+    fn date(self, year: i32) -> time::Result<Date> {
+        use Rule::*;
+
+        Ok(match self {
+            Fixed(month, day) => Date::from_calendar_date(year, month, day)?,
+            Easter(offset) => easter_sunday(year)? + offset.days(),
+            NthWeekday(month, n, weekday) => nth_weekday(year, month, n, weekday)?,
+            WeekdayBefore(month, day, weekday) => {
+                let before = Date::from_calendar_date(year, month, day)? - 1.days();
+                let back = (before.weekday().number_days_from_monday() as i64
+                    - weekday.number_days_from_monday() as i64)
+                    .rem_euclid(7);
+                before - back.days()
+            }
+        })
+    }
+}
+
+/// Returns the nth `weekday` of a month, counting from its end if `n` is negative, e.g. -1 for the last Sunday
+pub fn nth_weekday(year: i32, month: Month, n: i8, weekday: Weekday) -> time::Result<Date> {
+    Ok(if n < 0 {
+        let last =
+            Date::from_calendar_date(year, month, time::util::days_in_year_month(year, month))?;
+        let back = (last.weekday().number_days_from_monday() as i64
+            - weekday.number_days_from_monday() as i64)
+            .rem_euclid(7);
+        last - (back + 7 * (-n as i64 - 1)).days()
+    } else {
+        let first = Date::from_calendar_date(year, month, 1)?;
+        let ahead = (weekday.number_days_from_monday() as i64
+            - first.weekday().number_days_from_monday() as i64)
+            .rem_euclid(7);
+        first + (ahead + 7 * (n as i64 - 1)).days()
+    })
+}
+
+/// Computes Easter Sunday of the Gregorian calendar with the anonymous Gregorian algorithm
+fn easter_sunday(year: i32) -> time::Result<Date> {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    Ok(Date::from_calendar_date(
+        year,
+        Month::try_from(month as u8)?,
+        day as u8,
+    )?)
+}
@@ -0,0 +1,178 @@
+//! A lightweight well-formedness check for the rendered HTML pages.
+//!
+//! This is not a full HTML validator, it tracks the open elements of a page to find unclosed tags, stray closing
+//! tags, and improperly nested elements, which usually come from broken templates or unsanitized descriptions.
+
+use color_eyre::eyre::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Elements which never have content or a closing tag
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose closing tag may be omitted
+const OPTIONAL_CLOSE_ELEMENTS: [&str; 17] = [
+    "html", "head", "body", "p", "li", "dt", "dd", "tr", "td", "th", "thead", "tbody", "tfoot",
+    "option", "optgroup", "colgroup", "caption",
+];
+
+/// Elements whose content is not parsed as HTML
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// A problem found in a page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlProblem {
+    /// The line the problem was detected on, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for HtmlProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Checks every HTML file in `files`, returning the problems found per file
+pub fn check_files<'p, I>(files: I) -> Result<Vec<(PathBuf, Vec<HtmlProblem>)>>
+where
+    I: IntoIterator<Item = &'p Path>,
+{
+    let mut results = Vec::new();
+    for file in files {
+        if file.extension().and_then(|e| e.to_str()) != Some("html") {
+            continue;
+        }
+        let html = fs::read_to_string(file).context(format!("could not read {:?}", file))?;
+        let problems = check_html(&html);
+        if !problems.is_empty() {
+            results.push((file.to_path_buf(), problems));
+        }
+    }
+    Ok(results)
+}
+
+/// Checks that the elements of a page are properly closed and nested
+pub fn check_html(html: &str) -> Vec<HtmlProblem> {
+    let mut problems = Vec::new();
+    // open elements with the line they were opened on
+    let mut open: Vec<(String, usize)> = Vec::new();
+    let mut rest = html;
+    let line_of = |rest: &str| html[..html.len() - rest.len()].lines().count().max(1);
+
+    while let Some(tag_start) = rest.find('<') {
+        rest = &rest[tag_start..];
+        let line = line_of(rest);
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = match comment.find("-->") {
+                Some(end) => &comment[end + 3..],
+                None => {
+                    problems.push(problem(line, "unterminated comment".to_string()));
+                    ""
+                }
+            };
+            continue;
+        }
+
+        let tag_end = match rest.find('>') {
+            Some(end) => end,
+            None => {
+                problems.push(problem(line, "unterminated tag".to_string()));
+                break;
+            }
+        };
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        if tag.starts_with('!') || tag.starts_with('?') {
+            // doctype or processing instruction
+            continue;
+        }
+
+        if let Some(closing) = tag.strip_prefix('/') {
+            let name = closing.trim().to_ascii_lowercase();
+            close_element(&mut open, &name, line, &mut problems);
+            continue;
+        }
+
+        let name = tag_name(tag);
+        if name.is_empty() {
+            // a literal "<" in text, browsers treat it as text as well
+            continue;
+        }
+        if VOID_ELEMENTS.contains(&name.as_str()) || tag.trim_end().ends_with('/') {
+            continue;
+        }
+        if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+            let closing = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&closing) {
+                Some(end) => &rest[end..],
+                None => {
+                    problems.push(problem(line, format!("<{}> is never closed", name)));
+                    ""
+                }
+            };
+            open.push((name, line));
+            continue;
+        }
+        if name == "p" && open.last().map(|(n, _)| n == "p").unwrap_or(false) {
+            // a new paragraph implicitly closes the previous one
+            open.pop();
+        }
+        open.push((name, line));
+    }
+
+    for (name, line) in open {
+        if !OPTIONAL_CLOSE_ELEMENTS.contains(&name.as_str()) {
+            problems.push(problem(line, format!("<{}> is never closed", name)));
+        }
+    }
+
+    problems
+}
+
+/// Pops the element matching a closing tag, reporting any non-optional elements left open inside of it
+fn close_element(
+    open: &mut Vec<(String, usize)>,
+    name: &str,
+    line: usize,
+    problems: &mut Vec<HtmlProblem>,
+) {
+    match open.iter().rposition(|(open_name, _)| open_name == name) {
+        Some(position) => {
+            for (inner_name, inner_line) in open.drain(position + 1..) {
+                if !OPTIONAL_CLOSE_ELEMENTS.contains(&inner_name.as_str()) {
+                    problems.push(problem(
+                        line,
+                        format!(
+                            "</{}> closes <{}> opened on line {} which is still open",
+                            name, inner_name, inner_line
+                        ),
+                    ));
+                }
+            }
+            open.pop();
+        }
+        None => problems.push(problem(
+            line,
+            format!("</{}> does not match any open element", name),
+        )),
+    }
+}
+
+/// Returns the lower case element name of an opening tag
+fn tag_name(tag: &str) -> String {
+    tag.chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+fn problem(line: usize, message: String) -> HtmlProblem {
+    HtmlProblem { line, message }
+}
@@ -0,0 +1,101 @@
+//! Scaffolds a new site, so a first build works without knowing which templates statical expects.
+
+use color_eyre::eyre::{Context, Result};
+use std::fs;
+use std::path::Path;
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::config::Config;
+use crate::sample::sample_calendar;
+
+/// The default templates, written into `templates/`
+const TEMPLATES: [(&str, &str); 15] = [
+    ("year.html", include_str!("../templates/year.html")),
+    ("month.html", include_str!("../templates/month.html")),
+    ("week.html", include_str!("../templates/week.html")),
+    ("day.html", include_str!("../templates/day.html")),
+    ("event.html", include_str!("../templates/event.html")),
+    ("series.html", include_str!("../templates/series.html")),
+    (
+        "countdown.html",
+        include_str!("../templates/countdown.html"),
+    ),
+    ("agenda.html", include_str!("../templates/agenda.html")),
+    ("tracks.html", include_str!("../templates/tracks.html")),
+    (
+        "week_locations.html",
+        include_str!("../templates/week_locations.html"),
+    ),
+    (
+        "day_event.html",
+        include_str!("../templates/day_event.html"),
+    ),
+    (
+        "pagination.html",
+        include_str!("../templates/pagination.html"),
+    ),
+    ("views.html", include_str!("../templates/views.html")),
+    (
+        "mini_calendar.html",
+        include_str!("../templates/mini_calendar.html"),
+    ),
+    (
+        "featured_events.html",
+        include_str!("../templates/featured_events.html"),
+    ),
+];
+
+/// The default stylesheet, written into `public/`
+const STYLESHEET: &str = include_str!("../public/statical.css");
+
+/// The subdirectories of the output directory, one per view
+const OUTPUT_DIRS: [&str; 5] = ["month", "week", "day", "agenda", "styles"];
+
+/// Writes a starter config, the default templates and stylesheet, a sample calendar, and the output directories
+/// into `dir`
+///
+/// Files which already exist are kept, so running it again only adds what is missing.
+pub fn init(dir: &Path) -> Result<()> {
+    let config = Config {
+        calendar_files: vec!["calendar.ics".into()],
+        copy_stylesheet_to_output: true,
+        ..Config::default()
+    };
+    write_new(
+        &dir.join("statical.toml"),
+        &toml_edit::easy::to_string_pretty(&config)?,
+    )?;
+    write_new(
+        &dir.join("calendar.ics"),
+        &sample_calendar(OffsetDateTime::now_utc().date()),
+    )?;
+    for (name, template) in TEMPLATES {
+        write_new(&dir.join(&config.templates_dir).join(name), template)?;
+    }
+    write_new(&dir.join(&config.copy_stylesheet_from), STYLESHEET)?;
+    for output_dir in OUTPUT_DIRS {
+        let path = dir.join(&config.output_dir).join(output_dir);
+        fs::create_dir_all(&path).context(format!("could not create {:?}", path))?;
+    }
+
+    info!(
+        "Run `statical build` in {:?} to render the sample calendar into {:?}, then replace calendar.ics with your own",
+        dir, config.output_dir
+    );
+    Ok(())
+}
+
+/// Writes a file unless it exists, creating its directory
+fn write_new(path: &Path, contents: &str) -> Result<()> {
+    if path.exists() {
+        info!("Keeping existing {:?}", path);
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!("could not create {:?}", parent))?;
+    }
+    fs::write(path, contents).context(format!("could not write {:?}", path))?;
+    info!("Created {:?}", path);
+    Ok(())
+}
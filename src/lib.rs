@@ -4,14 +4,126 @@
 //!
 //! It reads a collection of `*.ics` files or calendar feeds and creates a collection of `html` files containing all of the events found in the source files and feeds.
 
+pub mod build;
+pub mod changes;
+pub mod clean;
 pub mod config;
+pub mod daemon;
+pub mod deploy;
+pub mod diff;
+pub mod dry_run;
+pub mod enrichment;
+pub mod feed;
+pub mod free_busy;
+pub mod git;
+pub mod holidays;
+pub mod html_check;
+pub mod init;
+pub mod link_check;
+pub mod log;
+pub mod manifest;
 pub mod model;
+pub mod moon;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+pub mod notes;
 pub mod options;
+pub mod report;
+pub mod sample;
+pub mod schedule;
+pub mod serve;
+pub mod site_writer;
+pub mod source_check;
+pub mod sqlite;
+pub mod sun;
+pub mod template_check;
+pub mod upload;
 
 #[cfg(test)]
+#[allow(unused_imports)]
 mod tests {
     use indoc::indoc;
     use pretty_assertions::{assert_eq, assert_ne};
 
     use super::*;
+
+    #[test]
+    fn unfold_keeps_whitespace_next_to_folds() {
+        let input = "SUMMARY:Weekly sync with \r\n the team\r\nLOCATION:Room\r\n\t 4 \r\n";
+        assert_eq!(
+            model::ics::unfold(input),
+            vec!["SUMMARY:Weekly sync with the team", "LOCATION:Room 4"]
+        );
+    }
+
+    #[test]
+    fn floating_times_use_the_calendar_or_display_timezone() {
+        let ics = |header: &str| {
+            format!(
+                "BEGIN:VCALENDAR\r\n{}BEGIN:VEVENT\r\nUID:a\r\nSUMMARY:A\r\nDTSTART:20260715T090000\r\n\
+                 DTEND:20260715T100000\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+                header
+            )
+        };
+        let paris = time_tz::timezones::get_by_name("Europe/Paris").unwrap();
+        let start_hour_utc = |ics: String| {
+            let (calendars, _) = model::calendar::Calendar::parse_calendars(
+                ics.as_bytes(),
+                "test",
+                &mut model::arena::StringInterner::new(),
+                paris,
+            )
+            .unwrap();
+            calendars[0].events()[0]
+                .start()
+                .to_offset(time::UtcOffset::UTC)
+                .hour()
+        };
+        assert_eq!(start_hour_utc(ics("")), 7);
+        assert_eq!(
+            start_hour_utc(ics("X-WR-TIMEZONE:America/New_York\r\n")),
+            13
+        );
+    }
+
+    #[test]
+    fn init_scaffold_has_no_broken_links() {
+        let dir = std::env::temp_dir().join(format!("statical-init-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        init::init(&dir).unwrap();
+        let mut config: config::Config =
+            toml_edit::easy::from_str(&std::fs::read_to_string(dir.join("statical.toml")).unwrap())
+                .unwrap();
+        for path in [
+            &mut config.templates_dir,
+            &mut config.output_dir,
+            &mut config.copy_stylesheet_from,
+        ] {
+            *path = dir.join(&path).to_string_lossy().into_owned();
+        }
+        let source = model::calendar_collection::SourceData {
+            source: "calendar.ics".into(),
+            ics: Some(std::fs::read_to_string(dir.join("calendar.ics")).unwrap()),
+            stale: false,
+        };
+        let options = build::BuildOptions {
+            check_links: true,
+            ..Default::default()
+        };
+        // the root index embeds the default view, which links from another directory than the view's own pages
+        let result = ["month", "week", "day"].into_iter().try_for_each(|view| {
+            let config = config::Config {
+                default_calendar_view: view.into(),
+                render_event_pages: true,
+                ..config.clone()
+            };
+            let collection = model::calendar_collection::CalendarCollection::from_sources(
+                vec![source.clone()],
+                &config,
+            )?;
+            build::build_site(&collection, &config, options).map(|_| ())
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+        result.unwrap();
+    }
 }
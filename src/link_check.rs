@@ -0,0 +1,92 @@
+//! Verifies that the links between generated pages point at pages which were written.
+
+use color_eyre::eyre::{Context, Result};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// A link from a generated page to a file which was not generated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// The page containing the link, relative to the output directory
+    pub page: PathBuf,
+    /// The link as it appears in the page
+    pub target: String,
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} links to missing {:?}", self.page, self.target)
+    }
+}
+
+/// Checks the anchor links of every HTML page in `files`
+///
+/// `files` are the files written by the build, relative to `output_dir`. Links to other sites, empty links, and
+/// fragment-only links are ignored. Links to a directory must have an `index.html` in that directory.
+pub fn check_links(output_dir: &Path, files: &BTreeSet<PathBuf>) -> Result<Vec<BrokenLink>> {
+    let anchor = Regex::new(r#"<a\s[^>]*href="([^"]*)""#)?;
+    let mut broken_links = Vec::new();
+
+    for page in files {
+        if page.extension().and_then(|e| e.to_str()) != Some("html") {
+            continue;
+        }
+        let path = output_dir.join(page);
+        let html = fs::read_to_string(&path).context(format!("could not read {:?}", path))?;
+
+        for captures in anchor.captures_iter(&html) {
+            let target = unescape(&captures[1]);
+            if let Some(resolved) = resolve_link(page, &target) {
+                let exists =
+                    files.contains(&resolved) || files.contains(&resolved.join("index.html"));
+                if !exists {
+                    broken_links.push(BrokenLink {
+                        page: page.clone(),
+                        target,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(broken_links)
+}
+
+/// Resolves a link found in `page` to a path relative to the output directory
+///
+/// Returns `None` for links which do not point into the generated site.
+fn resolve_link(page: &Path, target: &str) -> Option<PathBuf> {
+    let target = target.split(['#', '?']).next().unwrap_or_default();
+    if target.is_empty() || target.starts_with("//") || target.contains(':') {
+        return None;
+    }
+
+    let joined = match target.strip_prefix('/') {
+        Some(absolute) => PathBuf::from(absolute),
+        None => page.parent().unwrap_or_else(|| Path::new("")).join(target),
+    };
+
+    // normalize the path without touching the file system
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(part) => resolved.push(part),
+            _ => {}
+        }
+    }
+    Some(resolved)
+}
+
+/// Undoes the escaping Tera applies to attribute values
+fn unescape(link: &str) -> String {
+    link.replace("&#x2F;", "/")
+        .replace("&#x27;", "'")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,94 @@
+//! Human readable logging, and structured log events for log collectors like Loki or Elastic
+//!
+//! The human readable output goes through `tracing` to stdout: warnings and a terse summary by default, each written
+//! page with `-v`, and each event with `-vv`. `--quiet` leaves only the warnings.
+//!
+//! With `--log-format json`, every phase of a build (reading a source, parsing it, rendering a view, finishing the
+//! build) is written to stderr as a single JSON line with a timestamp, the phase, and its counts and durations. The
+//! human readable output on stdout is not affected.
+
+use clap::ArgEnum;
+use serde_json::{json, Map, Value};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::level_filters::LevelFilter;
+
+use crate::model::calendar_collection::SourceData;
+
+/// How log events are written
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Only the human readable output
+    #[default]
+    Text,
+    /// JSON lines on stderr in addition to the human readable output
+    Json,
+}
+
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// Sets the format and the level of the human readable output for the rest of the program run, later calls have no
+/// effect
+pub fn init(format: LogFormat, level: LevelFilter) {
+    FORMAT.get_or_init(|| format);
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .without_time()
+        .with_writer(std::io::stdout)
+        .try_init()
+        .ok();
+}
+
+/// The level of the human readable output for the `-v`/`-vv` and `--quiet` flags
+pub fn level(verbose: u8, quiet: bool) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::WARN,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    }
+}
+
+/// Returns the format set by `init()`, text if it was not called
+pub fn format() -> LogFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Writes a log event for `phase` with the given fields, which must be a JSON object
+pub fn event(phase: &str, fields: Value) {
+    if format() != LogFormat::Json {
+        return;
+    }
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        json!(OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default()),
+    );
+    line.insert("phase".to_string(), json!(phase));
+    if let Value::Object(fields) = fields {
+        line.extend(fields);
+    }
+    eprintln!("{}", Value::Object(line));
+}
+
+/// Writes the `read_source` event for a source which was read since `started`
+pub fn source_read(data: &SourceData, started: Instant) {
+    event(
+        "read_source",
+        json!({
+            "source": data.source,
+            "found": data.ics.is_some(),
+            "bytes": data.ics.as_ref().map_or(0, String::len),
+            "duration_ms": millis(started.elapsed()),
+        }),
+    );
+}
+
+/// Converts a duration to fractional milliseconds for the `duration_ms` fields
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -1,46 +1,71 @@
 use clap::StructOpt;
-use color_eyre::eyre::{self};
-use statical::{model::calendar_collection::CalendarCollection, options::Opt};
-use std::io::{Read, Write};
-
-mod options;
+use color_eyre::eyre;
+use serde_json::json;
+use statical::{
+    build::{self, BuildOptions},
+    clean, daemon, deploy, diff, dry_run, init, log,
+    model::calendar_collection::CalendarCollection,
+    options::{self, Command, Opt},
+    serve, source_check, template_check,
+};
+use std::path::Path;
 
 fn main() -> eyre::Result<()> {
     let args = Opt::parse();
     color_eyre::install()?;
+    log::init(args.log_format, log::level(args.verbose, args.quiet));
+
+    let result = run(args);
+    if let Err(error) = &result {
+        log::event("error", json!({ "message": format!("{:#}", error) }));
+    }
+    result
+}
 
-    let config = if let Ok(mut config_file) = std::fs::File::open(&args.config) {
-        let mut config_raw = String::new();
-        config_file.read_to_string(&mut config_raw)?;
-        toml_edit::easy::from_str(&config_raw)?
-    } else {
-        let config = Default::default();
-        if let Ok(mut config_file) = std::fs::File::create(&args.config) {
-            if let Ok(config_raw) = toml_edit::easy::to_string_pretty(&config) {
-                config_file.write_all(config_raw.as_bytes()).ok();
-            }
+fn run(mut args: Opt) -> eyre::Result<()> {
+    // before loading the config, which would write the default config file
+    if let Some(Command::Init { dir }) = &args.command {
+        return init::init(Path::new(dir));
+    }
+    if let Some(Command::Diff { old, new, json }) = &args.command {
+        return diff::diff(old, new, *json);
+    }
+    if let Some(Command::Completions { shell }) = &args.command {
+        options::print_completions(*shell);
+        return Ok(());
+    }
+    let config = args.load_config()?;
+
+    let deploy = matches!(args.command, Some(Command::Deploy));
+    let serve_address = match &args.command {
+        Some(Command::CheckTemplates) => return template_check::check_templates(&config),
+        Some(Command::Daemon) => return daemon::run(args, config),
+        Some(Command::Check) => return source_check::check_sources(args, &config),
+        Some(Command::Clean) => return clean::clean(&config),
+        Some(Command::Serve { listen }) => Some(listen.clone()),
+        Some(Command::Build | Command::Deploy) | None => None,
+        Some(Command::Init { .. } | Command::Diff { .. } | Command::Completions { .. }) => {
+            unreachable!("handled before loading the config")
         }
-        config
     };
 
+    let options = BuildOptions {
+        resume: args.resume,
+        check_links: args.check_links,
+    };
+    let dry_run = args.dry_run;
     let calendar_collection = CalendarCollection::new(args, &config)?;
-
-    calendar_collection.setup_output_dir()?;
-
-    if config.render_month {
-        calendar_collection.create_month_pages()?;
-    }
-
-    if config.render_week {
-        calendar_collection.create_week_pages()?;
+    if let Some(format) = dry_run {
+        return dry_run::dry_run(calendar_collection, &config, format);
     }
+    build::build_site(&calendar_collection, &config, options)?;
 
-    if config.render_day {
-        calendar_collection.create_day_pages()?;
+    if deploy {
+        deploy::deploy(Path::new(&config.output_dir), &config)?;
     }
 
-    if config.render_agenda {
-        calendar_collection.create_agenda_pages()?;
+    if let Some(serve_address) = serve_address {
+        serve::run(Path::new(&config.output_dir), &serve_address)?;
     }
 
     Ok(())
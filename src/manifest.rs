@@ -0,0 +1,98 @@
+use color_eyre::eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+
+use crate::changes::EventChange;
+
+/// Name of the manifest file written into the output directory
+pub const MANIFEST_FILE_NAME: &str = ".statical-manifest.json";
+
+/// A record of what a build has written to the output directory
+///
+/// The manifest is saved after every view, so a failed build can be resumed with the views it already completed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Manifest {
+    /// The views (month, week, day, agenda) whose pages were all written successfully
+    pub completed_views: BTreeSet<String>,
+    /// The files written by the build, relative to the output directory
+    pub files: BTreeSet<PathBuf>,
+    /// The path of each period page (e.g. `week/2024-32`), relative to the output directory
+    ///
+    /// This is used to detect pages which were renamed between builds.
+    pub pages: BTreeMap<String, PathBuf>,
+    /// The event instances of the build, keyed by their instance id
+    ///
+    /// This is used to compare the events of two builds, see `diff`.
+    pub events: BTreeMap<String, EventRecord>,
+    /// The latest changes to upcoming events, newest first, see `changes::detect_changes()`
+    pub changes: Vec<EventChange>,
+    /// The SHA-256 of every file as it was last uploaded, see `upload::upload_site()`
+    pub uploaded: BTreeMap<PathBuf, String>,
+    /// The SHA-256 of every file as it was last deployed over SFTP, see `deploy::deploy()`
+    pub deployed: BTreeMap<PathBuf, String>,
+}
+
+/// What a manifest remembers about an event instance
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EventRecord {
+    pub uid: Option<String>,
+    pub summary: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: OffsetDateTime,
+    pub location: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// The `SEQUENCE` of the event
+    #[serde(default)]
+    pub sequence: u32,
+    /// Whether the instance belongs to a recurring event
+    #[serde(default)]
+    pub recurring: bool,
+    /// Whether the event lasts whole days, its start and end are then midnight in their offset
+    #[serde(default)]
+    pub all_day: bool,
+}
+
+impl Manifest {
+    /// Returns the location of the manifest within an output directory
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(MANIFEST_FILE_NAME)
+    }
+
+    /// Reads the manifest from an output directory, returns `None` if there is none yet
+    pub fn load(output_dir: &Path) -> Result<Option<Manifest>> {
+        let path = Self::path(output_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let raw = fs::read_to_string(&path).context(format!("could not read {:?}", path))?;
+        let manifest =
+            serde_json::from_str(&raw).context(format!("could not parse manifest {:?}", path))?;
+        Ok(Some(manifest))
+    }
+
+    /// Writes the manifest into an output directory
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let path = Self::path(output_dir);
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .context(format!("could not write manifest {:?}", path))
+    }
+
+    /// Adds written files to the manifest, storing them relative to the output directory
+    pub fn record_files<I>(&mut self, output_dir: &Path, files: I)
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        self.files.extend(files.into_iter().map(|file| {
+            file.strip_prefix(output_dir)
+                .map(Path::to_path_buf)
+                .unwrap_or(file)
+        }));
+    }
+}
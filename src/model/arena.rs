@@ -0,0 +1,95 @@
+//! Compact storage for large collections of events.
+//!
+//! Expanding recurring events can produce hundreds of thousands of instances which mostly repeat the same strings.
+//! Strings are interned so every instance shares them, and the instances are stored in one contiguous arena which
+//! the month, week, and day maps index into by `EventId`.
+
+use std::collections::HashSet;
+use std::ops::Index;
+use std::rc::Rc;
+
+use super::event::Event;
+
+/// A shared, immutable string
+pub type IStr = Rc<str>;
+
+/// Hands out one shared copy of each distinct string
+#[derive(Debug, Default)]
+pub struct StringInterner {
+    strings: HashSet<IStr>,
+}
+
+impl StringInterner {
+    pub fn new() -> StringInterner {
+        StringInterner::default()
+    }
+
+    /// Returns the shared copy of `value`, adding it if it has not been seen yet
+    pub fn intern(&mut self, value: &str) -> IStr {
+        if let Some(existing) = self.strings.get(value) {
+            return existing.clone();
+        }
+        let interned: IStr = Rc::from(value);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    /// Returns the number of distinct strings
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+/// The index of an event within an `EventArena`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventId(u32);
+
+/// Contiguous storage of every event instance in a collection
+#[derive(Debug, Default)]
+pub struct EventArena {
+    events: Vec<Event>,
+}
+
+impl EventArena {
+    pub fn new() -> EventArena {
+        EventArena::default()
+    }
+
+    /// Moves an event into the arena and returns its id
+    pub fn push(&mut self, event: Event) -> EventId {
+        let id =
+            EventId(u32::try_from(self.events.len()).expect("more events than an arena can index"));
+        self.events.push(event);
+        id
+    }
+
+    /// Looks up several events at once, in the order of `ids`
+    pub fn resolve<'s>(&'s self, ids: &'s [EventId]) -> impl Iterator<Item = &'s Event> + 's {
+        ids.iter().map(move |id| &self[*id])
+    }
+
+    /// Iterates over all events in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = &Event> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl Index<EventId> for EventArena {
+    type Output = Event;
+
+    fn index(&self, id: EventId) -> &Event {
+        &self.events[id.0 as usize]
+    }
+}
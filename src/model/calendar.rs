@@ -1,40 +1,60 @@
 use chrono::TimeZone;
 use chrono_tz::UTC;
-use color_eyre::eyre::Result;
-use ical::parser::ical::component::IcalCalendar;
-use ical::IcalParser;
-use rrule::DateFilter;
-use std::collections::HashSet;
+use color_eyre::eyre::{Context, Result};
+use rrule::WithError;
+use std::collections::{HashMap, HashSet};
 use std::io::BufRead;
 use std::rc::Rc;
 use time::ext::NumericalDuration;
 use time::util::days_in_year_month;
-use time::OffsetDateTime;
+use time::{Date, OffsetDateTime};
+use time_tz::{OffsetDateTimeExt, Tz};
+use tracing::trace;
 
-use crate::model::event::Event;
+use crate::config::{Blackout, BlackoutMode, Config};
+use crate::model::event::{Event, EventStatus, ParticipationStatus};
 
-use super::event::UnparsedProperties;
+use super::arena::{IStr, StringInterner};
+use super::event::{EventIdentity, UnparsedProperties};
+use super::ics::{self, unescape_text, Component};
+use super::timezone::TimeZones;
 
 #[derive(Debug)]
 pub struct Calendar {
-    name: Option<String>,
-    description: Option<String>,
+    /// The file path or url this calendar was read from
+    source: String,
+    name: Option<IStr>,
+    description: Option<IStr>,
     start: OffsetDateTime,
     end: OffsetDateTime,
-    events: Vec<Rc<Event>>,
-    recurring_events: Vec<Rc<Event>>,
+    events: Vec<Event>,
+    recurring_events: Vec<Event>,
+    /// Number of events pushed into this calendar, recurring events count once
+    parsed_events: usize,
+    /// Number of instances created by expanding recurring events
+    expanded_events: usize,
+    /// Problems encountered while parsing, one per skipped event
+    warnings: Vec<String>,
+    /// Limits the calendar ran into, the events over the limits are dropped, and recurrences which ended early
+    limit_warnings: Vec<String>,
 }
 
 impl Calendar {
-    pub fn new(calendar: &IcalCalendar) -> Result<Calendar> {
+    pub fn new(
+        calendar: &Component,
+        source: &str,
+        interner: &mut StringInterner,
+    ) -> Result<Calendar> {
         // eprintln!("Parsing calendar: {:#?}", calendar);
         let mut name = None;
         let mut description = None;
 
         for property in &calendar.properties {
-            match property.name.as_str() {
-                "X-WR-CALNAME" => name = property.value.clone(),
-                "X-WR-CALDESC" => description = property.value.clone(),
+            match property.name {
+                "X-WR-CALNAME" => name = property.value.map(|v| interner.intern(&unescape_text(v))),
+                "X-WR-CALDESC" => {
+                    description = property.value.map(|v| interner.intern(&unescape_text(v)))
+                }
                 _ => {
                     // TODO collect the unparsed properties
                     // eprintln!("  Ignoring {}: {:?}", property.name, property.value);
@@ -49,21 +69,53 @@ impl Calendar {
 
         // build the new calendar
         Ok(Calendar {
+            source: source.to_string(),
             name,
             description,
             start: now,
             end: now.saturating_add((days_in_year_month(year, month) as i64).days()),
             events: Vec::new(),
             recurring_events: Vec::new(),
+            parsed_events: 0,
+            expanded_events: 0,
+            warnings: Vec::new(),
+            limit_warnings: Vec::new(),
         })
     }
 
-    pub fn push(&mut self, event: Rc<Event>) {
+    /// Creates a calendar of events which statical generates itself, e.g. holidays
+    pub fn generated(source: &str, name: &str, events: Vec<Event>) -> Calendar {
+        let now = OffsetDateTime::now_utc();
+        let mut calendar = Calendar {
+            source: source.to_string(),
+            name: Some(name.into()),
+            description: None,
+            start: now,
+            end: now,
+            events: Vec::new(),
+            recurring_events: Vec::new(),
+            parsed_events: 0,
+            expanded_events: 0,
+            warnings: Vec::new(),
+            limit_warnings: Vec::new(),
+        };
+        for event in events {
+            calendar.push(event);
+        }
+        calendar
+    }
+
+    pub fn push(&mut self, event: Event) {
         // collect calendar start and end dates, we need this for rrule expansion
         self.start = self.start.min(event.start());
         self.end = self.end.max(event.end());
+        // occurrences from `RDATE` may come after every other event
+        if let Some(last_extra_date) = event.extra_dates().iter().max() {
+            self.end = self.end.max(*last_extra_date);
+        }
+        self.parsed_events += 1;
 
-        if event.rrule().is_some() {
+        if event.recurs() {
             // add event to recurring_events
             // TODO might want to look at any recurrence termination dates and set calendar end to that
             self.recurring_events.push(event)
@@ -73,64 +125,345 @@ impl Calendar {
         }
     }
 
-    pub fn expand_recurrences(&mut self, cal_start: OffsetDateTime, cal_end: OffsetDateTime) {
+    /// Adds the instances of the recurring events between `cal_start` and `cal_end` to the events
+    ///
+    /// Each recurring event yields at most `max_instances` instances (0 for no limit), the remaining ones are dropped
+    /// with a warning. Occurrences listed in `EXDATE` are left out and those in `RDATE` are added. A rule which fails
+    /// part way keeps the instances before the failure and those of `RDATE`, also with a warning.
+    pub fn expand_recurrences(
+        &mut self,
+        cal_start: OffsetDateTime,
+        cal_end: OffsetDateTime,
+        max_instances: u16,
+    ) {
         // we need to convert from the time-rs library to chrono for RRule's sake
         let repeat_start = UTC.timestamp(cal_start.unix_timestamp(), 0);
         let repeat_end = UTC.timestamp(cal_end.unix_timestamp(), 0);
 
-        let mut new_events: Vec<Rc<Event>> = Vec::new();
+        let mut new_events: Vec<Event> = Vec::new();
 
-        for event in self.recurring_events() {
+        for event in &self.recurring_events {
             // TODO might want to make this a map based on UID
-            println!("Event with rrule found: {:#?}", event);
-            if let Ok(recurrence_datetimes) = event
-                .rrule()
-                .unwrap()
-                // setting inclusive to true since we have moved recurring events into a separate vec
-                .all_between(repeat_start, repeat_end, true)
-            {
-                // add event to groups
-                println!("{:#?}", recurrence_datetimes);
-                for recurrence_time in recurrence_datetimes {
-                    // we have to convert the DateTime<Tz> back into an OffsetDateTime
-                    let new_start =
-                        OffsetDateTime::from_unix_timestamp(recurrence_time.timestamp())
-                            .expect("could not build timestamp from recurrence time");
-                    // TODO might want to push directly into the events vec and skip some of the checks in Calendar.push()
-                    new_events.push(Rc::new(event.duplicate_with_date(new_start)));
+            trace!("Event with rrule found: {:#?}", event);
+            let mut starts: Vec<OffsetDateTime> = match event.rrule() {
+                Some(rrule) => {
+                    // iterating rather than using `all_between()`, whose limit cannot be lifted
+                    let mut recurrences = rrule.into_iter();
+                    let mut recurrence_datetimes = Vec::new();
+                    for recurrence_time in recurrences
+                        .by_ref()
+                        .skip_while(|time| *time < repeat_start)
+                        .take_while(|time| *time <= repeat_end)
+                    {
+                        if max_instances != 0
+                            && recurrence_datetimes.len() >= max_instances as usize
+                        {
+                            self.limit_warnings.push(format!(
+                                "recurring event \"{}\" was cut off after {} instances",
+                                event.summary(),
+                                max_instances
+                            ));
+                            break;
+                        }
+                        recurrence_datetimes.push(recurrence_time);
+                    }
+                    // the instances before the error and those of `RDATE` are kept
+                    if let Some(error) = recurrences.get_err() {
+                        self.limit_warnings.push(format!(
+                            "recurring event \"{}\" stopped recurring early: {}",
+                            event.summary(),
+                            error
+                        ));
+                    }
+                    trace!("{:#?}", recurrence_datetimes);
+                    recurrence_datetimes
+                        .into_iter()
+                        // we have to convert the DateTime<Tz> back into an OffsetDateTime
+                        .map(|recurrence_time| {
+                            OffsetDateTime::from_unix_timestamp(recurrence_time.timestamp())
+                                .expect("could not build timestamp from recurrence time")
+                        })
+                        .collect()
                 }
+                // recurring through `RDATE` alone, the event itself is the first occurrence
+                None => vec![event.start()],
+            };
+            starts.extend(event.extra_dates());
+            starts.retain(|start| {
+                (cal_start..=cal_end).contains(start) && !event.is_excluded(*start)
+            });
+            starts.sort();
+            starts.dedup();
+
+            // add event to groups
+            for (new_start, index) in starts.into_iter().zip(0..) {
+                // TODO might want to push directly into the events vec and skip some of the checks in Calendar.push()
+                new_events.push(event.duplicate_with_date(new_start, index));
             }
         }
 
         // add new events to events in calendar
         // this extra step was necessary due to mutability rules in Rust and iterators
+        self.expanded_events += new_events.len();
         self.events.extend(new_events);
     }
 
+    /// Keeps the `max_events` events closest to `now` (0 for no limit), returning the number of dropped events
+    ///
+    /// This keeps a misbehaving feed from ballooning the site, the drop is recorded as a warning.
+    pub fn limit_events(&mut self, max_events: usize, now: OffsetDateTime) -> usize {
+        if max_events == 0 || self.events.len() <= max_events {
+            return 0;
+        }
+        self.events
+            .sort_by_key(|event| (event.start() - now).unsigned_abs());
+        let dropped = self.events.split_off(max_events).len();
+        self.events.sort_by_key(|event| event.start());
+        self.limit_warnings.push(format!(
+            "dropped {} events, the source has more than {} events",
+            dropped, max_events
+        ));
+        dropped
+    }
+
+    /// Removes non-recurring events which do not overlap the given range, returning the number of removed events
+    ///
+    /// Each removed event is recorded as a warning since it usually indicates a typo in the source calendar.
+    pub fn remove_events_outside(&mut self, start: OffsetDateTime, end: OffsetDateTime) -> usize {
+        let before = self.events.len();
+        let warnings = &mut self.warnings;
+        self.events.retain(|event| {
+            let in_range = event.end() >= start && event.start() <= end;
+            if !in_range {
+                warnings.push(format!(
+                    "skipped outlier event \"{}\" on {}, it is outside of the generation range",
+                    event.summary(),
+                    event.start().date()
+                ));
+            }
+            in_range
+        });
+        before - self.events.len()
+    }
+
+    /// Removes the events `attendee` declined or did not answer, unless configured to show them
+    ///
+    /// Returns the number of removed events. Recurring events are removed before they are expanded.
+    pub fn remove_unwanted_invitations(&mut self, config: &Config) -> usize {
+        if config.attendee_email.is_empty() {
+            return 0;
+        }
+        let wanted = |event: &Event| match event.participation(&config.attendee_email) {
+            Some(ParticipationStatus::Declined) => config.show_declined_events,
+            Some(ParticipationStatus::NeedsAction) => config.show_unanswered_events,
+            _ => true,
+        };
+        let before = self.events.len() + self.recurring_events.len();
+        self.events.retain(wanted);
+        self.recurring_events.retain(wanted);
+        before - self.events.len() - self.recurring_events.len()
+    }
+
+    /// Replaces the occurrences of recurring events by their modified instances, returning the number replaced
+    ///
+    /// A modified instance has the UID of its recurring event and the original start of the occurrence as its
+    /// `RECURRENCE-ID`, it may move the occurrence or change its summary or location. It takes over the recurrence of
+    /// the occurrence, so it is still shown as recurring. Call this after `expand_recurrences()`.
+    pub fn apply_overrides(&mut self) -> usize {
+        let overridden: HashSet<(String, OffsetDateTime)> = self
+            .events
+            .iter()
+            .filter_map(|event| Some((event.uid()?.to_string(), event.recurrence_id()?)))
+            .collect();
+        if overridden.is_empty() {
+            return 0;
+        }
+
+        let mut replaced = HashMap::new();
+        self.events.retain(|event| {
+            let Some(uid) = event.uid().filter(|_| event.recurrence_id().is_none()) else {
+                return true;
+            };
+            let key = (uid.to_string(), event.start());
+            if !overridden.contains(&key) {
+                return true;
+            }
+            replaced.insert(key, event.recurrence().cloned());
+            false
+        });
+        for event in &mut self.events {
+            let (Some(uid), Some(recurrence_id)) = (event.uid(), event.recurrence_id()) else {
+                continue;
+            };
+            if let Some(Some(recurrence)) = replaced.get(&(uid.to_string(), recurrence_id)) {
+                event.set_recurrence(recurrence.clone());
+            }
+        }
+        replaced.len()
+    }
+
+    /// Sets the first occurrence after `now` on the instances of each recurring event, see `Event::context()`
+    ///
+    /// The occurrences are taken from the instances, so dates left out by `EXDATE`, added by `RDATE`, moved by a
+    /// modified instance, or cancelled are accounted for. Call this after `apply_overrides()`.
+    pub fn mark_next_occurrences(&mut self, now: OffsetDateTime) {
+        let series_key = |event: &Event| {
+            let recurrence = event.recurrence()?;
+            Some((event.uid().map(String::from), recurrence.series_start()))
+        };
+        let mut next_occurrences: HashMap<(Option<String>, OffsetDateTime), OffsetDateTime> =
+            HashMap::new();
+        for event in &self.events {
+            if event.start() <= now || event.status() == EventStatus::Cancelled {
+                continue;
+            }
+            if let Some(key) = series_key(event) {
+                let next = next_occurrences.entry(key).or_insert(event.start());
+                *next = (*next).min(event.start());
+            }
+        }
+        for event in &mut self.events {
+            if let Some(key) = series_key(event) {
+                event.set_next_occurrence(next_occurrences.get(&key).copied());
+            }
+        }
+    }
+
+    /// Moves the all-day events to the midnights of `tz`, see `Event::pin_all_day()`
+    ///
+    /// Call this after `expand_recurrences()` so the instances are pinned too.
+    pub fn pin_all_day_events(&mut self, tz: &Tz) {
+        for event in &mut self.events {
+            event.pin_all_day(tz);
+        }
+    }
+
+    /// Removes the events and instances whose `STATUS` is `CANCELLED`, returning the number of removed events
+    ///
+    /// Call this after `apply_overrides()`, so a modified instance can cancel a single occurrence.
+    pub fn hide_cancelled(&mut self) -> usize {
+        let before = self.events.len();
+        self.events
+            .retain(|event| event.status() != EventStatus::Cancelled);
+        before - self.events.len()
+    }
+
+    /// Hides or suspends the events overlapping the blackouts, returning the number of hidden events
+    ///
+    /// Blackouts are given with their first and last day in `tz`. Call this after `expand_recurrences()` so only
+    /// the instances during the blackout are affected.
+    pub fn apply_blackouts(&mut self, blackouts: &[(Date, Date, &Blackout)], tz: &Tz) -> usize {
+        let before = self.events.len();
+        self.events.retain_mut(|event| {
+            let first_day = event.start().to_timezone(tz).date();
+            // the end is exclusive, an event ending at midnight does not take place on the next day
+            let last_day = (event.end() - 1.nanoseconds())
+                .max(event.start())
+                .to_timezone(tz)
+                .date();
+            let blackout = blackouts
+                .iter()
+                .find(|(start, end, _)| first_day <= *end && last_day >= *start);
+            match blackout {
+                Some((_, _, blackout)) if blackout.mode == BlackoutMode::Hide => false,
+                Some((_, _, blackout)) => {
+                    event.suspend(Rc::from(blackout.name.as_str()));
+                    true
+                }
+                None => true,
+            }
+        });
+        before - self.events.len()
+    }
+
+    /// Removes events whose identity has already been seen, returning the number of removed events
+    ///
+    /// The `seen` set is shared between calendars so duplicates across feeds are collapsed as well.
+    pub fn dedup_events(&mut self, seen: &mut HashSet<EventIdentity>) -> usize {
+        let before = self.events.len();
+        self.events.retain(|event| seen.insert(event.identity()));
+        before - self.events.len()
+    }
+
+    /// Moves the events out of the calendar, leaving it without any events
+    ///
+    /// Recurring events are dropped, their instances have been added to the events by `expand_recurrences()`.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.recurring_events.clear();
+        std::mem::take(&mut self.events)
+    }
+
     /// Parse calendar data from ICS
     ///
     /// The ICS data can be either a file or a url. Anything that implements BufRead such as a File or String::as_bytes().
-    pub fn parse_calendars<B>(buf: B) -> Result<(Vec<Calendar>, UnparsedProperties)>
+    /// Events which cannot be parsed are skipped and recorded as warnings on their calendar. Pass the same `interner`
+    /// for every source so text repeated across sources is only stored once. Floating times, without `Z` or `TZID`,
+    /// are in the `X-WR-TIMEZONE` of their calendar, or else in `floating_time_zone`.
+    ///
+    /// The whole buffer is read into memory first so the properties can borrow from it while the events are built,
+    /// only the strings kept by the events are copied.
+    pub fn parse_calendars<B>(
+        mut buf: B,
+        source: &str,
+        interner: &mut StringInterner,
+        floating_time_zone: &'static Tz,
+    ) -> Result<(Vec<Calendar>, UnparsedProperties)>
     where
         B: BufRead,
     {
         let mut calendars = Vec::new();
-        let reader = IcalParser::new(buf);
         let mut unparsed_properties: UnparsedProperties = HashSet::new();
 
-        for calendar in reader.flatten() {
-            let mut new_calendar = Calendar::new(&calendar)?;
-            for event in calendar.events {
-                let (new_event, event_unparsed_properties) = Event::new(event)?;
-                unparsed_properties.extend(event_unparsed_properties.into_iter());
-                let rc_event = Rc::new(new_event);
-                new_calendar.push(rc_event);
+        let mut input = String::new();
+        buf.read_to_string(&mut input)
+            .context(format!("could not read {}", source))?;
+        let lines = ics::unfold(&input);
+        let components = ics::parse_components(&lines, source);
+
+        for calendar in components
+            .iter()
+            .filter(|component| component.name.eq_ignore_ascii_case("VCALENDAR"))
+        {
+            let mut new_calendar = Calendar::new(calendar, source, interner)?;
+            let time_zones = TimeZones::from_calendar(calendar, floating_time_zone);
+            for event in calendar.components_named("VEVENT") {
+                match Event::new(event, &time_zones, interner, &mut unparsed_properties) {
+                    Ok(new_event) => new_calendar.push(new_event),
+                    Err(error) => new_calendar
+                        .warnings
+                        .push(format!("skipped event: {:#}", error)),
+                }
+            }
+            for free_busy in calendar.components_named("VFREEBUSY") {
+                match Event::from_free_busy(free_busy, interner) {
+                    Ok(blocks) => blocks
+                        .into_iter()
+                        .for_each(|block| new_calendar.push(block)),
+                    Err(error) => new_calendar
+                        .warnings
+                        .push(format!("skipped free/busy: {:#}", error)),
+                }
             }
             calendars.push(new_calendar);
         }
         Ok((calendars, unparsed_properties))
     }
 
+    #[must_use]
+    pub fn source(&self) -> &str {
+        self.source.as_ref()
+    }
+
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    #[must_use]
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
     #[must_use]
     pub fn start(&self) -> OffsetDateTime {
         self.start
@@ -142,12 +475,93 @@ impl Calendar {
     }
 
     #[must_use]
-    pub fn events(&self) -> &[Rc<Event>] {
+    pub fn events(&self) -> &[Event] {
         self.events.as_ref()
     }
 
     #[must_use]
-    pub fn recurring_events(&self) -> &[Rc<Event>] {
+    pub fn recurring_events(&self) -> &[Event] {
         self.recurring_events.as_ref()
     }
+
+    #[must_use]
+    pub fn parsed_events(&self) -> usize {
+        self.parsed_events
+    }
+
+    #[must_use]
+    pub fn expanded_events(&self) -> usize {
+        self.expanded_events
+    }
+
+    /// Returns the limits the calendar ran into, see `expand_recurrences()` and `limit_events()`
+    #[must_use]
+    pub fn limit_warnings(&self) -> &[String] {
+        self.limit_warnings.as_ref()
+    }
+
+    /// Returns the warnings raised while parsing, there is one per skipped event
+    #[must_use]
+    pub fn warnings(&self) -> &[String] {
+        self.warnings.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+    use time::macros::datetime;
+    use time_tz::timezones::db::UTC;
+
+    use super::*;
+
+    #[test]
+    fn next_occurrence_skips_excluded_and_cancelled_dates() {
+        let ics = indoc! {"
+            BEGIN:VCALENDAR
+            BEGIN:VEVENT
+            UID:weekly
+            SUMMARY:Weekly
+            DTSTART:20260105T100000Z
+            DTEND:20260105T110000Z
+            RRULE:FREQ=WEEKLY;COUNT=6
+            EXDATE:20260119T100000Z
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:weekly
+            RECURRENCE-ID:20260126T100000Z
+            SUMMARY:Weekly
+            STATUS:CANCELLED
+            DTSTART:20260126T100000Z
+            DTEND:20260126T110000Z
+            END:VEVENT
+            END:VCALENDAR
+        "};
+        let (mut calendars, _) =
+            Calendar::parse_calendars(ics.as_bytes(), "test", &mut StringInterner::new(), UTC)
+                .unwrap();
+        let calendar = &mut calendars[0];
+        calendar.expand_recurrences(
+            datetime!(2026-01-01 0:00 UTC),
+            datetime!(2026-03-01 0:00 UTC),
+            0,
+        );
+        calendar.apply_overrides();
+        calendar.mark_next_occurrences(datetime!(2026-01-13 0:00 UTC));
+
+        let next_occurrences: HashSet<_> = calendar
+            .events()
+            .iter()
+            .map(|event| {
+                serde_json::to_value(event.context(UTC, &Config::default())).unwrap()
+                    ["next_occurrence_timestamp"]
+                    .as_i64()
+            })
+            .collect();
+        assert_eq!(
+            next_occurrences,
+            HashSet::from([Some(datetime!(2026-02-02 10:00 UTC).unix_timestamp())])
+        );
+    }
 }
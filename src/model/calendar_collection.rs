@@ -1,24 +1,49 @@
 use color_eyre::eyre::{self, bail, eyre, Context as EyreContext, Result};
 use dedup_iter::DedupAdapter;
-use std::collections::{BTreeMap, HashSet};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::io::Write;
-use std::ops::Range;
+use std::ops::{RangeBounds, RangeInclusive};
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
-use std::{fs::File, io::BufReader};
+use std::time::Instant;
 use tera::{Context, Tera};
 use time::ext::NumericalDuration;
+use time::macros::format_description;
 use time::util::days_in_year_month;
 use time::OffsetDateTime;
-use time::{macros::format_description, Date, Month as MonthName};
+use time::{Date, Duration, Time};
 use time_tz::{OffsetDateTimeExt, TimeZone, Tz};
-
-use super::event::{Event, UnparsedProperties};
+use tracing::{debug, info, trace, warn};
+
+use super::arena::{EventArena, EventId, StringInterner};
+use super::event::{Event, EventContext, UnparsedProperties};
+use crate::build;
+use crate::changes::{self, EventChange};
+use crate::config::{Config, EventLinks, MINUTES_PER_DAY};
+use crate::enrichment::{self, DayEnricher};
+use crate::feed::{self, FeedEntry, FeedFilter, FeedSlice};
+use crate::free_busy::{self, BusyPeriod};
+use crate::holidays;
+use crate::log;
+use crate::manifest::EventRecord;
 use crate::model::calendar::Calendar;
-use crate::model::day::DayContext;
+use crate::model::day::{DayContext, DaySection, LocationDayContext, TrackContext};
 use crate::model::event::{WeekNum, Year};
+use crate::model::ics::escape_text;
+use crate::model::tabular;
+use crate::model::timeline::TimelineContext;
+use crate::model::timezone::start_of_day;
+use crate::model::year::MiniMonthContext;
+use crate::moon::MoonPhase;
+use crate::notes::{load_announcement, load_notes};
 use crate::options::Opt;
+use crate::report::{FieldDifference, PhaseTiming, SourceSummary, UidConflict};
+use crate::sample::sample_calendar;
+use crate::site_writer::{FilesystemWriter, MemoryWriter, SiteWriter};
+use crate::sqlite;
+use crate::sun::{self, Daylight};
 
 /// Type alias representing a specific month in time
 type Month = (Year, u8);
@@ -27,63 +52,456 @@ type Week = (Year, WeekNum);
 /// Type alias representing a specific day in time
 type Day = Date;
 
+/// A BTreeMap of Vecs grouped by specific years
+type YearMap = BTreeMap<Year, Vec<EventId>>;
 /// A BTreeMap of Vecs grouped by specific months
 type MonthMap = BTreeMap<Month, WeekMapList>;
 type WeekMapList = BTreeMap<WeekNum, WeekMap>;
 /// A BTreeMap of Vecs grouped by specific weeks
-type WeekMap = BTreeMap<Week, Vec<Rc<Event>>>;
+type WeekMap = BTreeMap<Week, Vec<EventId>>;
 /// A BTreeMap of Vecs grouped by specific days
-type DayMap = BTreeMap<Day, Vec<Rc<Event>>>;
+type DayMap = BTreeMap<Day, Vec<EventId>>;
+
+/// The events of each day of a week, keyed by the days from Monday
+type WeekDayMap<'e> = BTreeMap<u8, Vec<&'e Event>>;
+
+/// Rendered files with their contents, see `CalendarCollection::take_deferred_files()`
+pub type RenderedFiles = Vec<(PathBuf, Vec<u8>)>;
+
+/// One of the `CalendarCollection::create_*_pages` functions
+pub type ViewRenderer<'a> = fn(&CalendarCollection<'a>) -> eyre::Result<()>;
+
+/// The raw ICS data of a calendar source
+#[derive(Debug, Clone, Hash)]
+pub struct SourceData {
+    /// The file path or url the data was read from
+    pub source: String,
+    /// The ICS data, `None` if the source does not exist
+    pub ics: Option<String>,
+    /// Whether the data is a cached copy because fetching the source failed, see `Config::source_cache_dir`
+    pub stale: bool,
+}
+
+/// The source name of the generated sample calendar
+const SAMPLE_SOURCE_NAME: &str = "sample data";
+/// The source name of the events defined in the config
+const VIRTUAL_SOURCE_NAME: &str = "config";
+
+/// Where the data of a calendar comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceLocation {
+    File(PathBuf),
+    Url(String),
+    /// A shell command printing the calendar, to bridge systems without an ICS export
+    Command(String),
+    /// The generated sample calendar
+    Sample,
+}
+
+impl SourceLocation {
+    /// Returns the sources requested on the command line, in the order they are read
+    pub fn from_args(args: &Opt) -> Vec<SourceLocation> {
+        let files = args
+            .file
+            .iter()
+            .flatten()
+            .cloned()
+            .map(SourceLocation::File);
+        let urls = args.url.iter().flatten().cloned().map(SourceLocation::Url);
+        let commands = args
+            .exec
+            .iter()
+            .flatten()
+            .cloned()
+            .map(SourceLocation::Command);
+        let sample = args.sample_data.then_some(SourceLocation::Sample);
+        files.chain(urls).chain(commands).chain(sample).collect()
+    }
+
+    /// The name of the source as used in summaries and logs
+    pub fn name(&self) -> String {
+        match self {
+            SourceLocation::File(file) => file.to_string_lossy().to_string(),
+            SourceLocation::Url(url) => url.clone(),
+            SourceLocation::Command(command) => command.clone(),
+            SourceLocation::Sample => SAMPLE_SOURCE_NAME.to_string(),
+        }
+    }
+
+    /// Reads the data of the source, a missing file is not an error
+    pub fn read(&self) -> Result<SourceData> {
+        match self {
+            SourceLocation::File(file) => {
+                let ics = if file.exists() {
+                    Some(fs::read_to_string(file).wrap_err(format!("could not read {:?}", file))?)
+                } else {
+                    None
+                };
+                Ok(SourceData {
+                    source: self.name(),
+                    ics,
+                    stale: false,
+                })
+            }
+            SourceLocation::Url(url) => Ok(SourceData {
+                source: url.clone(),
+                ics: Some(
+                    ureq::get(url)
+                        .call()
+                        .wrap_err(format!("could not fetch {}", url))?
+                        .into_string()?,
+                ),
+                stale: false,
+            }),
+            SourceLocation::Command(command) => Ok(SourceData {
+                source: command.clone(),
+                ics: Some(run_source_command(command)?),
+                stale: false,
+            }),
+            SourceLocation::Sample => Ok(sample_source()),
+        }
+    }
+
+    /// Reads the data of the source, keeping a copy of urls in `cache_dir` to fall back to when fetching fails
+    ///
+    /// Without a `cache_dir`, this is the same as `read()`.
+    pub fn read_with_cache(&self, cache_dir: &str) -> Result<SourceData> {
+        match (self, self.read()) {
+            (SourceLocation::Url(url), Ok(data)) if !cache_dir.is_empty() => {
+                store_cached_source(cache_dir, url, data.ics.as_deref().unwrap_or_default());
+                Ok(data)
+            }
+            (SourceLocation::Url(url), Err(error)) if !cache_dir.is_empty() => {
+                load_cached_source(cache_dir, url).ok_or(error)
+            }
+            (_, result) => result,
+        }
+    }
+}
+
+/// Runs the command of a source through the shell and returns what it printed, failing if the command fails
+pub fn run_source_command(command: &str) -> Result<String> {
+    let output = std::process::Command::new("sh")
+        .args(["-c", command])
+        .output()
+        .wrap_err(format!("could not run {:?}", command))?;
+    if !output.status.success() {
+        bail!(
+            "{:?} failed with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).wrap_err(format!("{:?} did not print UTF-8", command))
+}
+
+/// Where the copy of a url is kept within `cache_dir`
+fn cached_source_path(cache_dir: &str, url: &str) -> PathBuf {
+    PathBuf::from(cache_dir).join(format!("{}.ics", feed::slug(url)))
+}
+
+/// Keeps a copy of the data fetched from a url, failing to do so only costs the fallback
+pub fn store_cached_source(cache_dir: &str, url: &str, ics: &str) {
+    let path = cached_source_path(cache_dir, url);
+    let stored = fs::create_dir_all(cache_dir).and_then(|_| fs::write(&path, ics));
+    if let Err(error) = stored {
+        warn!("Could not cache {} in {:?}: {}", url, path, error);
+    }
+}
+
+/// Returns the cached copy of a url which could not be fetched, marked as stale
+pub fn load_cached_source(cache_dir: &str, url: &str) -> Option<SourceData> {
+    let path = cached_source_path(cache_dir, url);
+    let ics = fs::read_to_string(&path).ok()?;
+    warn!("Could not fetch {}, using the cached copy {:?}", url, path);
+    Some(SourceData {
+        source: url.to_string(),
+        ics: Some(ics),
+        stale: true,
+    })
+}
+
+/// Returns the events defined in the config as a source, `None` if there are none
+///
+/// The events are written as ICS so they are parsed, expanded, and reported like the events of any other source.
+pub fn virtual_source(config: &Config) -> Result<Option<SourceData>> {
+    if config.virtual_events.is_empty() {
+        return Ok(None);
+    }
+    let mut ics =
+        String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//statical//config//EN\r\n");
+    for (index, event) in config.virtual_events.iter().enumerate() {
+        let date = Date::parse(&event.date, format_description!("[year]-[month]-[day]")).wrap_err(
+            format!(
+                "virtual event {:?} needs a date like 2024-05-14",
+                event.summary
+            ),
+        )?;
+        let time = |time: &str| {
+            Time::parse(time, format_description!("[hour]:[minute]")).wrap_err(format!(
+                "virtual event {:?} needs times like 09:00",
+                event.summary
+            ))
+        };
+        let (start, end) = (time(&event.start_time)?, time(&event.end_time)?);
+        let local = |time: Time| {
+            format!(
+                "TZID={}:{}",
+                config.display_timezone,
+                date.with_time(time)
+                    .format(format_description!(
+                        "[year][month][day]T[hour][minute][second]"
+                    ))
+                    .unwrap_or_default()
+            )
+        };
+
+        ics.push_str("BEGIN:VEVENT\r\n");
+        ics.push_str(&format!("UID:statical-config-{}\r\n", index));
+        ics.push_str(&format!("SUMMARY:{}\r\n", escape_text(&event.summary)));
+        if let Some(description) = &event.description {
+            ics.push_str(&format!("DESCRIPTION:{}\r\n", escape_text(description)));
+        }
+        if let Some(location) = &event.location {
+            ics.push_str(&format!("LOCATION:{}\r\n", escape_text(location)));
+        }
+        if !event.categories.is_empty() {
+            let categories: Vec<_> = event.categories.iter().map(|c| escape_text(c)).collect();
+            ics.push_str(&format!("CATEGORIES:{}\r\n", categories.join(",")));
+        }
+        ics.push_str(&format!("DTSTART;{}\r\n", local(start)));
+        ics.push_str(&format!("DTEND;{}\r\n", local(end)));
+        if !event.rrule.is_empty() {
+            ics.push_str(&format!(
+                "RRULE:{}\r\n",
+                event.rrule.trim_start_matches("RRULE:")
+            ));
+        }
+        ics.push_str("END:VEVENT\r\n");
+    }
+    ics.push_str("END:VCALENDAR\r\n");
 
-type WeekDayMap = BTreeMap<u8, Vec<Rc<Event>>>;
+    Ok(Some(SourceData {
+        source: VIRTUAL_SOURCE_NAME.to_string(),
+        ics: Some(ics),
+        stale: false,
+    }))
+}
+
+/// Reads the calendar files, urls, commands, and sample data requested on the command line
+///
+/// Urls which cannot be fetched are read from `cache_dir` if they were cached before, see `read_with_cache()`.
+pub fn read_sources(args: &Opt, cache_dir: &str) -> Result<Vec<SourceData>> {
+    SourceLocation::from_args(args)
+        .iter()
+        .map(|location| {
+            let started = Instant::now();
+            let data = location.read_with_cache(cache_dir)?;
+            log::source_read(&data, started);
+            Ok(data)
+        })
+        .collect()
+}
+
+/// Returns the generated sample calendar as a source
+pub fn sample_source() -> SourceData {
+    SourceData {
+        source: SAMPLE_SOURCE_NAME.to_string(),
+        ics: Some(sample_calendar(OffsetDateTime::now_utc().date())),
+        stale: false,
+    }
+}
 
 pub struct CalendarCollection<'a> {
     calendars: Vec<Calendar>,
+    /// Every event instance of the calendars, the interval maps refer to these by id
+    events: EventArena,
     display_tz: &'a Tz,
     /// The current date and time of the program run in UTC
     current_date_time: OffsetDateTime,
 
+    years: YearMap,
     months: MonthMap,
     weeks: WeekMap,
     days: DayMap,
     tera: Tera,
-    config: &'a crate::config::Config,
+    config: &'a Config,
+    /// Every file written by the `create_*_pages` functions
+    written_files: RefCell<BTreeSet<PathBuf>>,
+    /// Fetch and parse statistics for each source, in the order they were read
+    source_summaries: Vec<SourceSummary>,
+    /// Events which several sources publish with the same UID but different content
+    uid_conflicts: Vec<UidConflict>,
+    /// How long the phases of building the collection took
+    timings: Vec<PhaseTiming>,
+    /// The path of every period page written, keyed by view and period (e.g. `week/2024-32`)
+    pages: RefCell<BTreeMap<String, PathBuf>>,
+    /// The rendered notes of each day, see `Config::notes_dir`
+    notes: BTreeMap<Date, String>,
+    /// The name of the public holiday of each day, see `Config::holidays_country`
+    holidays: BTreeMap<Date, String>,
+    /// The data attached to each day by the day enrichers, keyed by their name
+    day_extra: BTreeMap<Date, BTreeMap<String, serde_json::Value>>,
+    /// The instances of each UID shared by several instances, in order of their start
+    series: BTreeMap<String, Vec<EventId>>,
+    /// The rendered announcement shown on every page, if one is active
+    announcement: Option<String>,
+    /// Where rendered files are written to, see `set_writer()`
+    writer: RefCell<Box<dyn SiteWriter + 'a>>,
+    /// Rendered files which are left for the caller to write, see `defer_writes()`
+    deferred_files: Option<RefCell<RenderedFiles>>,
+    /// Whether the events are estimated to need more than `memory_budget_mb`
+    over_memory_budget: bool,
+    /// Whether files are only recorded as written, see `set_dry_run()`
+    dry_run: bool,
+    /// The number of events on each written page or feed
+    event_counts: RefCell<BTreeMap<PathBuf, usize>>,
+    /// The events of each calendar with the slug of its name, for the calendar feeds
+    calendar_events: Vec<(RangeInclusive<EventId>, String)>,
+    /// The feeds to write, the feed of all events first
+    feed_slices: Vec<FeedSlice>,
+    /// The latest `LAST-MODIFIED` of any event
+    last_modified: Option<OffsetDateTime>,
+    /// Whether a source could not be fetched and its cached copy was used instead
+    data_stale: bool,
 }
 
 impl<'a> CalendarCollection<'a> {
-    pub fn new(
-        args: Opt,
-        config: &'a crate::config::Config,
-    ) -> eyre::Result<CalendarCollection<'a>> {
+    pub fn new(args: Opt, config: &'a Config) -> eyre::Result<CalendarCollection<'a>> {
         // fail as fast as possible without wasting time on the expensive operations below
-        let time_zone = time_tz::timezones::get_by_name(&config.display_timezone)
-            .ok_or_else(|| eyre!("unknown timezone"))?;
+        validate_config(config)?;
+
+        let fetch_started = Instant::now();
+        let sources = read_sources(&args, &config.source_cache_dir)?;
+        let fetch_timing = PhaseTiming::since("fetch", fetch_started);
+        let mut collection = Self::from_sources(sources, config)?;
+        collection.add_earlier_timing(fetch_timing);
+        Ok(collection)
+    }
 
+    /// Parses the data read from the calendar sources and builds the collection from it
+    ///
+    /// The events defined in the config are added as another source.
+    pub fn from_sources(
+        mut sources: Vec<SourceData>,
+        config: &'a Config,
+    ) -> eyre::Result<CalendarCollection<'a>> {
+        let parse_started = Instant::now();
+        sources.extend(virtual_source(config)?);
         let mut calendars = Vec::new();
         let mut unparsed_properties: UnparsedProperties = HashSet::new();
 
-        if let Some(files) = args.file {
-            for file in files {
-                if file.exists() {
-                    let buf = BufReader::new(File::open(file)?);
+        let mut source_summaries = Vec::new();
+        let mut interner = StringInterner::new();
+        // times without a zone are read in the display timezone, unless their calendar names another
+        let time_zone = time_tz::timezones::get_by_name(&config.display_timezone)
+            .ok_or_else(|| unknown_timezone(&config.display_timezone))?;
+
+        let mut data_stale = false;
+        for SourceData { source, ics, stale } in sources {
+            let mut summary = SourceSummary {
+                source: source.clone(),
+                ..Default::default()
+            };
+            if stale {
+                data_stale = true;
+                summary
+                    .warnings
+                    .push("could not be fetched, used the cached copy".to_string());
+            }
+            match ics {
+                Some(mut ics) => {
+                    if tabular::is_csv(&source, config) {
+                        let (csv_ics, warnings) = tabular::csv_to_ics(&ics, config)
+                            .wrap_err(format!("could not read {} as CSV", source))?;
+                        summary.warnings.extend(warnings);
+                        ics = csv_ics;
+                    }
                     let (parsed_calendars, calendar_unparsed_properties) =
-                        &mut Calendar::parse_calendars(buf)?;
-                    unparsed_properties.extend(calendar_unparsed_properties.clone().into_iter());
+                        &mut Calendar::parse_calendars(
+                            ics.as_bytes(),
+                            &source,
+                            &mut interner,
+                            time_zone,
+                        )?;
+                    unparsed_properties.extend(calendar_unparsed_properties.clone());
                     calendars.append(parsed_calendars);
                 }
+                None => summary.warnings.push("file does not exist".to_string()),
             }
-        };
+            source_summaries.push(summary);
+        }
 
-        if let Some(urls) = args.url {
-            for url in urls {
-                let ics_string = ureq::get(&url).call()?.into_string()?;
-                let (parsed_calendars, calendar_unparsed_properties) =
-                    &mut Calendar::parse_calendars(ics_string.as_bytes())?;
-                unparsed_properties.extend(calendar_unparsed_properties.clone().into_iter());
-                calendars.append(parsed_calendars);
-            }
+        debug!("Interned {} distinct strings", interner.len());
+
+        debug!(
+            "The following {} properties were present but have not been parsed:",
+            unparsed_properties.len()
+        );
+        for property in unparsed_properties {
+            debug!("  {}", property);
+        }
+
+        let parse_timing = PhaseTiming::since("parse", parse_started);
+        let mut collection = Self::from_calendars(calendars, source_summaries, config)?;
+        collection.add_earlier_timing(parse_timing);
+        collection.data_stale = data_stale;
+        Ok(collection)
+    }
+
+    /// Builds the collection from calendars which have already been parsed
+    ///
+    /// This limits the calendars to the generation range, expands their recurring events, and groups the events into
+    /// months, weeks, and days. `source_summaries` are completed with the statistics of the calendars.
+    pub fn from_calendars(
+        mut calendars: Vec<Calendar>,
+        mut source_summaries: Vec<SourceSummary>,
+        config: &'a Config,
+    ) -> eyre::Result<CalendarCollection<'a>> {
+        let time_zone = validate_config(config)?;
+
+        // drop events that would stretch the collection over decades of empty pages
+        let now = OffsetDateTime::now_utc();
+        let midnight = |day: Date| start_of_day(day, time_zone);
+        let (range_start, range_end) =
+            config.generation_range(now.to_timezone(time_zone).date())?;
+        let range_start = range_start.map(midnight).transpose()?;
+        // the ranges are inclusive, so the range ends with the last second of its last day
+        let range_end = range_end
+            .map(|day| midnight(day.next_day().unwrap_or(day)).map(|end| end - 1.seconds()))
+            .transpose()?;
+        let earliest_start =
+            range_start.unwrap_or_else(|| now - (config.max_days_in_past as i64).days());
+        let latest_end =
+            range_end.unwrap_or_else(|| now + (config.max_days_in_future as i64).days());
+        let outlier_count: usize = calendars
+            .iter_mut()
+            .map(|calendar| calendar.remove_events_outside(earliest_start, latest_end))
+            .sum();
+        if outlier_count > 0 {
+            info!(
+                "Skipped {} events outside of {} - {}",
+                outlier_count,
+                earliest_start.date(),
+                latest_end.date()
+            );
         }
 
+        let invitation_count: usize = calendars
+            .iter_mut()
+            .map(|calendar| calendar.remove_unwanted_invitations(config))
+            .sum();
+        if invitation_count > 0 {
+            info!(
+                "Hid {} events {} declined or did not answer",
+                invitation_count, config.attendee_email
+            );
+        }
+
+        // compare before expanding, so a conflict is reported once and not for every instance
+        let uid_conflicts = find_uid_conflicts(&calendars);
+
         // get start and end date for entire collection
         let cal_start: OffsetDateTime = calendars
             .iter()
@@ -96,68 +514,298 @@ impl<'a> CalendarCollection<'a> {
             .reduce(|max_end, end| max_end.max(end))
             // TODO consider a better approach to finding the correct number of days
             .unwrap_or_else(|| OffsetDateTime::now_utc() + 30.days());
+        // recurring events may still start or end outside of the sane range, an explicit range is used as it is
+        let cal_start = range_start.unwrap_or_else(|| cal_start.max(earliest_start));
+        let cal_end = range_end.unwrap_or_else(|| cal_end.min(latest_end));
 
         // add events to maps
+        let mut years = YearMap::new();
         let mut months = MonthMap::new();
         let mut weeks = WeekMap::new();
         let mut days = DayMap::new();
 
         // expand recurring events
+        let expand_started = Instant::now();
         for calendar in calendars.iter_mut() {
-            calendar.expand_recurrences(cal_start, cal_end);
+            calendar.expand_recurrences(cal_start, cal_end, config.max_recurrence_instances);
+            calendar.apply_overrides();
+            calendar.pin_all_day_events(time_zone);
+            calendar.mark_next_occurrences(now);
+        }
+        let mut timings = vec![PhaseTiming::since("expand", expand_started)];
+
+        if config.hide_cancelled_events {
+            let cancelled_count: usize = calendars.iter_mut().map(Calendar::hide_cancelled).sum();
+            if cancelled_count > 0 {
+                info!("Hid {} cancelled events", cancelled_count);
+            }
+        }
+
+        let blackouts = config
+            .blackouts
+            .iter()
+            .map(|blackout| blackout.dates().map(|(start, end)| (start, end, blackout)))
+            .collect::<Result<Vec<_>>>()?;
+        if !blackouts.is_empty() {
+            let hidden_count: usize = calendars
+                .iter_mut()
+                .map(|calendar| calendar.apply_blackouts(&blackouts, time_zone))
+                .sum();
+            info!("Hid {} events during blackouts", hidden_count);
+        }
+
+        // collapse identical instances, some feeds ship both the RRULE and its pre-expanded copies
+        let mut seen_events = HashSet::new();
+        let duplicate_count: usize = calendars
+            .iter_mut()
+            .map(|calendar| calendar.dedup_events(&mut seen_events))
+            .sum();
+        if duplicate_count > 0 {
+            info!("Removed {} duplicate event instances", duplicate_count);
+        }
+
+        let limited_count: usize = calendars
+            .iter_mut()
+            .map(|calendar| calendar.limit_events(config.max_events_per_source, now))
+            .sum();
+        if limited_count > 0 {
+            info!(
+                "Dropped {} events over the limit of {} events per source",
+                limited_count, config.max_events_per_source
+            );
+        }
+
+        for summary in source_summaries.iter_mut() {
+            summarize_source(summary, &calendars, time_zone);
+        }
+
+        if !config.deadline_rules.is_empty() {
+            let deadlines: Vec<Event> = calendars
+                .iter()
+                .flat_map(Calendar::events)
+                .flat_map(|event| {
+                    config
+                        .deadline_rules
+                        .iter()
+                        .filter_map(|rule| event.deadline(rule, time_zone))
+                })
+                .collect();
+            info!(
+                "Derived {} deadlines from the deadline rules",
+                deadlines.len()
+            );
+            calendars.push(Calendar::generated("deadlines", "Deadlines", deadlines));
         }
 
-        // add events to interval maps
-        for calendar in &calendars {
-            for event in calendar.events() {
-                months
-                    .entry((event.year(), event.start().month() as u8))
-                    .or_insert(WeekMapList::new())
-                    .entry(event.week())
-                    .or_insert(WeekMap::new())
-                    .entry((event.year(), event.week()))
-                    .or_insert(Vec::new())
-                    .push(event.clone());
+        let holidays = if config.holidays_country.is_empty() {
+            BTreeMap::new()
+        } else {
+            let (first_day, last_day) = (
+                cal_start.to_timezone(time_zone).date(),
+                cal_end.to_timezone(time_zone).date(),
+            );
+            let mut holidays = BTreeMap::new();
+            for year in first_day.year()..=last_day.year() {
+                for (date, name) in holidays::holidays(&config.holidays_country, year)? {
+                    if (first_day..=last_day).contains(&date) {
+                        holidays.insert(date, name.to_string());
+                    }
+                }
+            }
+            if config.holiday_events {
+                let holiday_events = holidays
+                    .iter()
+                    .map(|(date, name)| {
+                        Event::holiday(name, &config.holidays_country, *date, time_zone)
+                    })
+                    .collect::<Result<_>>()?;
+                calendars.push(Calendar::generated("holidays", "Holidays", holiday_events));
+            }
+            holidays
+        };
+
+        // move the events into the arena and add their ids to interval maps
+        let group_started = Instant::now();
+        let mut events = EventArena::new();
+        let mut calendar_events = Vec::new();
+        let mut calendar_names = BTreeMap::new();
+        let mut series: BTreeMap<String, Vec<EventId>> = BTreeMap::new();
+        for calendar in calendars.iter_mut() {
+            let name = calendar_name(calendar);
+            let slug = feed::slug(&name);
+            let mut first_and_last = None;
+            for event in calendar.take_events() {
+                let event_days: Vec<Date> = event.days(time_zone).collect();
+                let uid = event.uid().map(String::from);
+                let id = events.push(event);
+                if let Some(uid) = uid {
+                    series.entry(uid).or_default().push(id);
+                }
+                first_and_last = Some((first_and_last.map_or(id, |(first, _)| first), id));
+
+                // a multi-day event is filed under every period it overlaps, once per period
+                let push_once = |ids: &mut Vec<EventId>| {
+                    if ids.last() != Some(&id) {
+                        ids.push(id);
+                    }
+                };
+                for date in event_days {
+                    let (year, month) = (date.year(), date.month() as u8);
+                    let week = iso_week(date);
+
+                    push_once(years.entry(year).or_default());
+
+                    push_once(
+                        months
+                            .entry((year, month))
+                            .or_default()
+                            .entry(week.1)
+                            .or_default()
+                            .entry(week)
+                            .or_default(),
+                    );
 
-                weeks
-                    .entry((event.year(), event.week()))
-                    .or_insert(Vec::new())
-                    .push(event.clone());
+                    push_once(weeks.entry(week).or_default());
 
-                days.entry(event.start().date())
-                    .or_insert(Vec::new())
-                    .push(event.clone());
+                    push_once(days.entry(date).or_default());
+                }
+            }
+            if let Some((first, last)) = first_and_last {
+                calendar_events.push((first..=last, slug.clone()));
+                calendar_names.entry(slug).or_insert(name);
             }
         }
+        // all-day events go first on their days, the order of the others stays that of their calendars
+        for ids in weeks.values_mut().chain(days.values_mut()) {
+            ids.sort_by_key(|id| !events[*id].is_all_day());
+        }
+        series.retain(|_, instances| instances.len() > 1);
+        for instances in series.values_mut() {
+            instances.sort_by_key(|id| events[*id].start());
+        }
+        if config.render_empty_periods {
+            fill_empty_periods(&mut years, &mut months, &mut weeks, &mut days);
+        }
+        let feed_slices = feed_slices(config, &events, calendar_names);
+        let last_modified = events.iter().filter_map(Event::last_modified).max();
+
+        timings.push(PhaseTiming::since("group", group_started));
+
+        let estimated_memory: usize = events.iter().map(Event::estimated_memory).sum();
+        let over_memory_budget =
+            config.memory_budget_mb > 0 && estimated_memory > config.memory_budget_mb * 1024 * 1024;
+        if over_memory_budget {
+            info!(
+                "The events need about {:.1} MiB, more than the memory budget of {} MiB, writing pages right away",
+                estimated_memory as f64 / 1024.0 / 1024.0,
+                config.memory_budget_mb
+            );
+        }
 
-        // print unparsed properties
-        // TODO should probably put this behind a flag
-        println!(
-            "The following {} properties were present but have not been parsed:",
-            unparsed_properties.len()
-        );
-        for property in unparsed_properties {
-            println!("  {}", property);
+        // a failing enrichment, like an unreachable weather service, leaves its data out instead of failing the build
+        let mut day_extra = BTreeMap::new();
+        for enricher in enrichment::configured_enrichers(config) {
+            if let Err(error) = enrich_days(&mut day_extra, &days, enricher.as_ref()) {
+                warn!("Skipping day enrichment {:?}: {:#}", enricher.name(), error);
+            }
         }
 
+        // the same time as the generated range, so the pages agree on what is upcoming
+        let current_date_time = now.to_timezone(time_zone);
         Ok(CalendarCollection {
             calendars,
+            events,
             display_tz: time_zone,
-            current_date_time: OffsetDateTime::now_utc().to_timezone(time_zone),
+            current_date_time,
+            years,
             months,
             weeks,
             days,
-            tera: Tera::new("templates/**/*.html")?,
+            tera: Tera::new(&config.template_glob())?,
             config,
+            written_files: RefCell::new(BTreeSet::new()),
+            source_summaries,
+            uid_conflicts,
+            timings,
+            pages: RefCell::new(BTreeMap::new()),
+            notes: load_notes(Path::new(&config.notes_dir))?,
+            holidays,
+            day_extra,
+            series,
+            announcement: load_announcement(config, current_date_time.date())?,
+            writer: RefCell::new(Box::new(FilesystemWriter::new(&config.output_dir))),
+            deferred_files: None,
+            over_memory_budget,
+            dry_run: false,
+            event_counts: RefCell::new(BTreeMap::new()),
+            calendar_events,
+            feed_slices,
+            last_modified,
+            data_stale: false,
         })
     }
 
+    /// Get the fetch and parse statistics of each calendar source.
+    #[must_use]
+    pub fn source_summaries(&self) -> &[SourceSummary] {
+        self.source_summaries.as_ref()
+    }
+
+    /// Returns how long the phases of building the collection took, in the order they ran
+    #[must_use]
+    pub fn timings(&self) -> &[PhaseTiming] {
+        self.timings.as_ref()
+    }
+
+    /// Records the timing of a phase which ran before the collection was built, e.g. fetching the sources
+    pub fn add_earlier_timing(&mut self, timing: PhaseTiming) {
+        self.timings.insert(0, timing);
+    }
+
+    /// Returns the events which several sources publish with the same UID but different content
+    #[must_use]
+    pub fn uid_conflicts(&self) -> &[UidConflict] {
+        self.uid_conflicts.as_ref()
+    }
+
     /// Get a reference to the calendar collection's calendars.
+    ///
+    /// Their events have been moved into the collection, see `events()`.
     #[must_use]
     pub fn calendars(&self) -> &[Calendar] {
         self.calendars.as_ref()
     }
 
+    /// Get a reference to the event instances of all calendars.
+    #[must_use]
+    pub fn events(&self) -> &EventArena {
+        &self.events
+    }
+
+    /// Returns what the manifest records about each event instance, keyed by instance id
+    #[must_use]
+    pub fn event_records(&self) -> BTreeMap<String, EventRecord> {
+        self.events
+            .iter()
+            .map(|event| {
+                (
+                    event.instance_id(),
+                    EventRecord {
+                        uid: event.uid().map(String::from),
+                        summary: event.summary().to_string(),
+                        start: event.start(),
+                        end: event.end(),
+                        location: event.location().map(String::from),
+                        description: event.description().map(String::from),
+                        sequence: event.sequence(),
+                        recurring: event.is_recurring(),
+                        all_day: event.is_all_day(),
+                    },
+                )
+            })
+            .collect()
+    }
+
     /// Get a reference to the calendar collection's tera.
     #[must_use]
     pub fn tera(&self) -> &Tera {
@@ -165,7 +813,9 @@ impl<'a> CalendarCollection<'a> {
     }
 
     pub fn render(&self, template_name: &str, context: &tera::Context) -> eyre::Result<String> {
-        Ok(self.tera.render(template_name, context)?)
+        Ok(self
+            .tera
+            .render(template_name, &self.page_context(context))?)
     }
 
     pub fn render_to(
@@ -174,367 +824,814 @@ impl<'a> CalendarCollection<'a> {
         context: &tera::Context,
         write: impl Write,
     ) -> eyre::Result<()> {
-        Ok(self.tera.render_to(template_name, context, write)?)
+        Ok(self
+            .tera
+            .render_to(template_name, &self.page_context(context), write)?)
     }
 
-    /// Takes a base dir and subdir, creates the subdirectory if it does not exist
-    fn create_subdir(
-        base_output_dir: &Path,
-        subdir_name: &str,
-    ) -> Result<PathBuf, color_eyre::Report> {
-        let output_dir = base_output_dir.join(subdir_name);
-        if !output_dir.exists() {
-            fs::create_dir(&output_dir).context(format!(
-                "could not create {} dir in {:?}",
-                subdir_name, base_output_dir
-            ))?;
-        }
-        Ok(output_dir)
+    /// Adds the values shared by every page, like the announcement, to a context
+    fn page_context(&self, context: &tera::Context) -> tera::Context {
+        let mut context = context.clone();
+        context.insert("announcement", &self.announcement);
+        context.insert("build_timestamp", &self.current_date_time.unix_timestamp());
+        context.insert(
+            "last_modified_timestamp",
+            &self.last_modified.map(OffsetDateTime::unix_timestamp),
+        );
+        context.insert("data_stale", &self.data_stale);
+        context
     }
 
-    pub fn setup_output_dir(&self) -> Result<()> {
-        let output_dir = &PathBuf::from(&self.config.output_dir);
-
-        // make the output dir if it doesn't exist
-        fs::create_dir_all(output_dir)
-            .context(format!("could not create output dir: {:?}", output_dir))?;
-
-        let styles_dir = Self::create_subdir(output_dir, "styles")?;
+    /// Renders a template into a file and records it as written
+    ///
+    /// The template is rendered completely before the file is touched, so a failing template does not clobber the
+    /// page written by a previous build.
+    fn write_template(
+        &self,
+        template_name: &str,
+        context: &tera::Context,
+        path: &Path,
+    ) -> eyre::Result<()> {
+        let page = self
+            .render(template_name, context)
+            .wrap_err(format!("could not render {} for {:?}", template_name, path))?;
+        self.write_file(path, page)
+    }
 
-        if self.config.copy_stylesheet_to_output {
-            let stylesheet_destination = styles_dir.join(PathBuf::from("style.css"));
-            let source_stylesheet = &&self.config.copy_stylesheet_from;
-            fs::copy(source_stylesheet, &stylesheet_destination).context(format!(
-                "could not copy stylesheet {:?} to destination: {:?}",
-                source_stylesheet, stylesheet_destination
-            ))?;
+    /// Writes the top level `index.html` as a redirect, unless the default view writes it
+    ///
+    /// The month, week, and day views write their current page as `index.html` when they are the
+    /// `default_calendar_view`. For any other view visitors are sent on to its index page, and if the default view is
+    /// not rendered, to the index page of another view which is.
+    pub fn create_landing_page(&self) -> Result<()> {
+        let view_links = self.view_links(Path::new("index.html"));
+        let default_view = self.config.default_calendar_view.as_str();
+        if matches!(default_view, "month" | "week" | "day") && view_links.contains_key(default_view)
+        {
+            return Ok(());
         }
+        let view = if view_links.contains_key(default_view) {
+            default_view
+        } else {
+            match view_links.keys().next() {
+                Some(view) => view,
+                None => {
+                    warn!("Not writing index.html, no view with an index page is rendered");
+                    return Ok(());
+                }
+            }
+        };
+        debug!("Writing index.html as a redirect to the {} view", view);
+        self.write_redirect(
+            Path::new("index.html"),
+            &PathBuf::from(view).join("index.html"),
+        )
+    }
 
+    /// Writes `today.html`, `this-week.html`, and `this-month.html`, redirects to the pages of the build's day, week,
+    /// and month
+    ///
+    /// Other sites can link to these addresses, which show the present as long as the site is rebuilt regularly. A
+    /// period without events has no page, its alias leads to the index page of the view instead.
+    pub fn create_alias_pages(&self) -> Result<()> {
+        if !self.config.render_alias_pages {
+            return Ok(());
+        }
+        let today = self.current_date_time.date();
+        let (year, month) = (today.year(), today.month() as u8);
+        let (week_year, week) = iso_week(today);
+        let aliases = [
+            (
+                "today.html",
+                "day",
+                self.config.render_day,
+                self.days
+                    .contains_key(&today)
+                    .then(|| self.day_page_path(&today)),
+            ),
+            (
+                "this-week.html",
+                "week",
+                self.config.render_week,
+                self.weeks
+                    .contains_key(&(week_year, week))
+                    .then(|| self.week_page_path(week_year, week)),
+            ),
+            (
+                "this-month.html",
+                "month",
+                self.config.render_month,
+                self.months
+                    .contains_key(&(year, month))
+                    .then(|| self.month_page_path(year, month)),
+            ),
+        ];
+        for (alias, view, rendered, page) in aliases {
+            if !rendered {
+                continue;
+            }
+            let page = page.unwrap_or_else(|| PathBuf::from(view).join("index.html"));
+            debug!("Writing {} as a redirect to {:?}", alias, page);
+            self.write_redirect(Path::new(alias), &page)?;
+        }
         Ok(())
     }
 
-    pub fn create_month_pages(&self) -> Result<()> {
-        let output_dir = Self::create_subdir(&PathBuf::from(&self.config.output_dir), "month")?;
-
-        let mut previous_file_name: Option<String> = None;
-        let mut index_written = false;
-
-        let mut months_iter = self.months.iter().peekable();
-        while let Some(((year, month), weeks)) = months_iter.next() {
-            println!("month: {}", month);
-            let mut week_list = Vec::new();
-
-            // create all weeks in this month
-            let weeks_for_display = iso_weeks_for_month_display(year, month)?;
-            println!("From week {:?}", weeks_for_display);
-            for week_num in weeks_for_display {
-                match weeks.get(&week_num) {
-                    Some(week_map) => {
-                        println!("  Creating week {}, {} {}", week_num, month, year);
-                        for ((y, w), events) in week_map {
-                            let mut week_day_map: WeekDayMap = BTreeMap::new();
-
-                            for event in events {
-                                println!(
-                                    "    event: ({} {} {}) {} {}",
-                                    event.start().weekday(),
-                                    event.year(),
-                                    event.week(),
-                                    event.summary(),
-                                    event.start(),
-                                );
-                                let day_of_week = event.start().weekday().number_days_from_sunday();
-                                week_day_map
-                                    .entry(day_of_week)
-                                    .or_insert(Vec::new())
-                                    .push(event.clone());
-                            }
-
-                            // create week days
-                            let week_dates =
-                                week_day_map.context(year, &week_num, self.display_tz())?;
-                            week_list.push(week_dates);
-                        }
-                    }
-                    None => {
-                        println!("  Inserting blank week {}, {} {}", week_num, month, year);
-                        week_list.push(blank_context(year, &week_num)?);
-                    }
-                }
-            }
+    /// Writes a page which sends visitors on to another page
+    ///
+    /// Both pages are given relative to the output directory.
+    fn write_redirect(&self, from_page: &Path, to_page: &Path) -> eyre::Result<()> {
+        let link = relative_link(from_page, to_page);
+        let page = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n  <meta charset=\"UTF-8\" />\n  \
+             <meta http-equiv=\"refresh\" content=\"0; url={link}\" />\n  \
+             <link rel=\"canonical\" href=\"{link}\" />\n  <title>Redirecting</title>\n</head>\n\
+             <body>\n  <p>This page has moved to <a href=\"{link}\">{link}</a>.</p>\n</body>\n</html>\n",
+            link = link
+        );
+        self.write_file(
+            &PathBuf::from(&self.config.output_dir).join(from_page),
+            page,
+        )
+    }
 
-            let file_name = format!("{}-{}.html", year, month);
-            let next_month = months_iter.peek();
-            let next_file_name = next_month.map(|((next_year, next_month), _events)| {
-                format!("{}-{}.html", next_year, next_month)
-            });
-            let mut template_out_file = output_dir.join(PathBuf::from(&file_name));
+    /// Keeps rendered files in memory instead of writing them, the caller writes them after `take_deferred_files()`
+    ///
+    /// The output directory is still set up by `setup_output_dir()`, only the rendered files are deferred. Over the
+    /// memory budget, the files are written as they are rendered instead of holding every page of the site at once.
+    pub fn defer_writes(&mut self) {
+        if self.over_memory_budget {
+            return;
+        }
+        self.deferred_files = Some(RefCell::new(Vec::new()));
+    }
 
-            let mut context = Context::new();
-            context.insert("stylesheet_path", &self.config.stylesheet_path);
-            context.insert("timezone", self.display_tz.name());
-            context.insert("year", &year);
-            context.insert("month", &month);
-            context.insert("weeks", &week_list);
-            context.insert("previous_file_name", &previous_file_name);
-            context.insert("next_file_name", &next_file_name);
-            println!("Writing template to file: {:?}", template_out_file);
-            self.render_to("month.html", &context, File::create(&template_out_file)?)?;
-
-            // write the index page for the current month
-            if !index_written {
-                if let Some(next_month_num) =
-                    next_month.map(|((_next_year, next_month), _events)| next_month)
-                {
-                    // write the index file if the next month is after the current date
-                    if month_from_u8(*next_month_num)? as u8 > self.current_date_time.month() as u8
-                    {
-                        template_out_file.pop();
-                        template_out_file.push(PathBuf::from("index.html"));
-
-                        println!("Writing template to index file: {:?}", template_out_file);
-                        self.render_to("month.html", &context, File::create(&template_out_file)?)?;
-                        index_written = true;
-
-                        // write the main index as the month view
-                        if self.config.default_calendar_view == "month" {
-                            template_out_file.pop();
-                            template_out_file.pop();
-                            template_out_file.push(PathBuf::from("index.html"));
-                            println!(
-                                "Writing template to main index file: {:?}",
-                                template_out_file
-                            );
-                            self.render_to(
-                                "month.html",
-                                &context,
-                                File::create(template_out_file)?,
-                            )?;
-                        }
-                    }
+    /// Renders the pages without writing anything, files and the view directories are only recorded as written
+    pub fn set_dry_run(&mut self) {
+        self.dry_run = true;
+    }
+
+    /// Writes the site through `writer` instead of into the output directory, e.g. to keep it in memory
+    ///
+    /// Paths handed to the writer are relative to the output directory.
+    pub fn set_writer(&mut self, writer: impl SiteWriter + 'a) {
+        self.writer = RefCell::new(Box::new(writer));
+    }
+
+    /// Renders every enabled view and the configured redirects into memory, keyed by their path relative to the output
+    /// directory
+    ///
+    /// Nothing is written to disk, so web apps can regenerate the calendar on demand and serve it from memory. Without
+    /// a previous build to compare with, the changes feed and calendar and the redirects of renamed pages are left
+    /// out. The writer and deferred writes set before are kept for later renders.
+    pub fn render_site(&mut self) -> Result<HashMap<PathBuf, Vec<u8>>> {
+        let memory_writer = MemoryWriter::new();
+        let writer = std::mem::replace(self.writer.get_mut(), Box::new(memory_writer.clone()));
+        let deferred_files = self.deferred_files.take();
+        let dry_run = std::mem::take(&mut self.dry_run);
+
+        let result = (|| -> Result<()> {
+            self.setup_output_dir()?;
+            for (view, enabled, create_pages) in build::views(self.config) {
+                if enabled {
+                    debug!("Rendering the {} pages into memory", view);
+                    create_pages(self)?;
                 }
             }
-            previous_file_name = Some(file_name);
+            self.create_landing_page()?;
+            self.create_alias_pages()?;
+            self.create_redirect_pages(&BTreeMap::new())
+        })();
+
+        *self.writer.get_mut() = writer;
+        self.deferred_files = deferred_files;
+        self.dry_run = dry_run;
+        result?;
+        Ok(memory_writer.files().into_iter().collect())
+    }
+
+    /// Tells the writer that the last file was written, see `SiteWriter::finish()`
+    pub fn finish_writes(&self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
         }
+        self.writer.borrow_mut().finish()
+    }
 
+    /// Returns the files rendered since the last call, if writes are deferred
+    pub fn take_deferred_files(&self) -> RenderedFiles {
+        self.deferred_files
+            .as_ref()
+            .map(|files| files.take())
+            .unwrap_or_default()
+    }
+
+    /// Writes a file through the writer and records it as written
+    fn write_file(&self, path: &Path, contents: impl Into<Vec<u8>>) -> eyre::Result<()> {
+        let contents = contents.into();
+        if self.dry_run {
+            self.written_files.borrow_mut().insert(path.to_path_buf());
+            return Ok(());
+        }
+        if let Some(deferred_files) = &self.deferred_files {
+            deferred_files
+                .borrow_mut()
+                .push((path.to_path_buf(), contents));
+            self.written_files.borrow_mut().insert(path.to_path_buf());
+            return Ok(());
+        }
+        let relative_path = path.strip_prefix(&self.config.output_dir).unwrap_or(path);
+        self.writer.borrow_mut().write(relative_path, &contents)?;
+        self.written_files.borrow_mut().insert(path.to_path_buf());
         Ok(())
     }
 
-    pub fn create_week_pages(&self) -> Result<()> {
-        let output_dir = Self::create_subdir(&PathBuf::from(&self.config.output_dir), "week")?;
+    /// Returns the files written so far
+    #[must_use]
+    pub fn written_files(&self) -> BTreeSet<PathBuf> {
+        self.written_files.borrow().clone()
+    }
 
-        let mut previous_file_name: Option<String> = None;
-        let mut index_written = false;
+    /// Returns the number of events on each page and feed written so far
+    #[must_use]
+    pub fn event_counts(&self) -> BTreeMap<PathBuf, usize> {
+        self.event_counts.borrow().clone()
+    }
 
-        let mut weeks_iter = self.weeks.iter().peekable();
-        while let Some(((year, week), events)) = weeks_iter.next() {
-            println!("week: {}", week);
+    /// Returns the period pages written so far, keyed by view and period
+    #[must_use]
+    pub fn pages(&self) -> BTreeMap<String, PathBuf> {
+        self.pages.borrow().clone()
+    }
 
-            let mut week_day_map: WeekDayMap = BTreeMap::new();
+    /// Remembers how many events a page or feed shows, see `event_counts()`
+    fn record_event_count(&self, file: &Path, events: usize) {
+        self.event_counts
+            .borrow_mut()
+            .insert(file.to_path_buf(), events);
+    }
 
-            for event in events {
-                println!(
-                    "  event: ({} {} {}) {} {}",
-                    event.start().weekday(),
-                    event.year(),
-                    event.week(),
-                    event.summary(),
-                    event.start(),
-                );
-                let day_of_week = event.start().weekday().number_days_from_sunday();
-                week_day_map
-                    .entry(day_of_week)
-                    .or_insert(Vec::new())
-                    .push(event.clone());
-            }
-            let file_name = format!("{}-{}.html", year, week);
-            let next_week_opt = weeks_iter.peek();
-            let next_file_name = next_week_opt.map(|((next_year, next_week), _events)| {
-                format!("{}-{}.html", next_year, next_week)
-            });
-            let mut template_out_file = output_dir.join(PathBuf::from(&file_name));
+    /// Remembers where the page of a period was written so renames can be detected by the next build
+    fn record_page(&self, view: &str, period: impl std::fmt::Display, page: &Path) {
+        self.pages
+            .borrow_mut()
+            .insert(format!("{}/{}", view, period), page.to_path_buf());
+    }
 
-            // create week days
-            let week_dates = week_day_map.context(year, week, self.display_tz())?;
+    /// Writes the redirects configured in `redirects` and, if enabled, redirects for renamed pages
+    ///
+    /// `previous_pages` are the period pages of the previous build as recorded in its manifest. Redirects never
+    /// replace a page written by this build.
+    pub fn create_redirect_pages(&self, previous_pages: &BTreeMap<String, PathBuf>) -> Result<()> {
+        let output_root = PathBuf::from(&self.config.output_dir);
+        let mut redirects: Vec<(PathBuf, PathBuf)> = self
+            .config
+            .redirects
+            .iter()
+            .map(|(from, to)| (PathBuf::from(from), PathBuf::from(to)))
+            .collect();
 
-            let mut context = Context::new();
-            context.insert("stylesheet_path", &self.config.stylesheet_path);
-            context.insert("timezone", self.display_tz.name());
-            context.insert("year", &year);
-            // handling weeks where the month changes
-            context.insert(
-                "month",
-                &week_dates
-                    .iter()
-                    .map(|d| d.month.clone())
-                    .dedup()
-                    .collect::<Vec<String>>()
-                    .join(" - "),
-            );
-            context.insert("week", &week);
-            context.insert("week_dates", &week_dates);
-            context.insert("previous_file_name", &previous_file_name);
-            context.insert("next_file_name", &next_file_name);
-            println!("Writing template to file: {:?}", template_out_file);
-            self.render_to("week.html", &context, File::create(&template_out_file)?)?;
-
-            // write the index page for the current week
-            // TODO might want to write the index if next_week is None and nothing has been written yet
-            if let Some(next_week) = next_week_opt {
-                if !index_written {
-                    let (next_year, next_week) = next_week.0;
-                    // write the index file if the next month is after the current date
-                    // TODO make sure that the conditional tests are correct, maybe add some tests
-                    if next_year >= &self.current_date_time.year()
-                        && next_week >= &self.current_date_time.iso_week()
-                    {
-                        template_out_file.pop();
-                        template_out_file.push(PathBuf::from("index.html"));
-
-                        println!("Writing template to index file: {:?}", template_out_file);
-                        self.render_to("week.html", &context, File::create(&template_out_file)?)?;
-                        index_written = true;
-
-                        // write the main index as the week view
-                        if self.config.default_calendar_view == "week" {
-                            template_out_file.pop();
-                            template_out_file.pop();
-                            template_out_file.push(PathBuf::from("index.html"));
-                            println!(
-                                "Writing template to main index file: {:?}",
-                                template_out_file
-                            );
-                            self.render_to(
-                                "week.html",
-                                &context,
-                                File::create(template_out_file)?,
-                            )?;
-                        }
-                    }
-                }
-            }
+        if self.config.redirect_renamed_pages {
+            let pages = self.pages.borrow();
+            redirects.extend(previous_pages.iter().filter_map(|(key, previous_page)| {
+                pages
+                    .get(key)
+                    .filter(|page| *page != previous_page)
+                    .map(|page| (previous_page.clone(), page.clone()))
+            }));
+        }
 
-            previous_file_name = Some(file_name);
+        for (from, to) in redirects {
+            if self
+                .written_files
+                .borrow()
+                .contains(&output_root.join(&from))
+            {
+                info!("Not redirecting {:?}, it was written by this build", from);
+                continue;
+            }
+            debug!("Writing redirect from {:?} to {:?}", from, to);
+            self.write_redirect(&from, &to)?;
         }
 
         Ok(())
     }
 
-    pub fn create_day_pages(&self) -> Result<()> {
-        let output_dir = Self::create_subdir(&PathBuf::from(&self.config.output_dir), "day")?;
-
-        let mut previous_file_name: Option<String> = None;
-        let mut index_written = false;
-
-        let mut days_iter = self.days.iter().peekable();
-        while let Some((day, events)) = days_iter.next() {
-            println!("day: {}", day);
-            for event in events {
-                println!(
-                    "  event: ({} {} {}) {} {}",
-                    event.start().weekday(),
-                    event.year(),
-                    event.week(),
-                    event.summary(),
-                    event.start(),
-                );
-            }
-            let file_name = format!(
-                "{}.html",
-                day.format(format_description!("[year]-[month]-[day]"))?
-            );
-            // TODO should we raise the error on format() failing?
-            let next_day_opt = days_iter.peek();
-            let next_file_name = next_day_opt.map(|(next_day, _events)| {
-                next_day
-                    .format(format_description!("[year]-[month]-[day]"))
-                    .map(|file_root| format!("{}.html", file_root))
-                    .ok()
-            });
-
-            let mut template_out_file = output_dir.join(PathBuf::from(&file_name));
+    /// Creates the directory of a view through the writer, unless this is a dry run
+    fn create_view_dir(&self, view: &str) -> Result<()> {
+        if !self.dry_run {
+            self.writer.borrow_mut().create_dir(Path::new(view))?;
+        }
+        Ok(())
+    }
 
-            let mut context = Context::new();
-            context.insert("stylesheet_path", &self.config.stylesheet_path);
-            context.insert("timezone", self.display_tz.name());
-            context.insert("year", &day.year());
-            context.insert("month", &day.month());
-            context.insert("day", &day.day());
-            context.insert("events", events);
-            context.insert("previous_file_name", &previous_file_name);
-            context.insert("next_file_name", &next_file_name);
-            println!("Writing template to file: {:?}", template_out_file);
-            self.render_to("day.html", &context, File::create(&template_out_file)?)?;
-
-            // write the index page for the current week
-            // TODO might want to write the index if next_week is None and nothing has been written yet
-            if let Some(next_week) = next_day_opt {
-                if !index_written {
-                    let next_day = next_week.0;
-                    // write the index file if the next month is after the current date
-                    // TODO make sure that the conditional tests are correct, maybe add some tests
-                    if next_day > &self.current_date_time.date() {
-                        template_out_file.pop();
-                        template_out_file.push(PathBuf::from("index.html"));
-
-                        println!("Writing template to index file: {:?}", template_out_file);
-                        self.render_to("day.html", &context, File::create(&template_out_file)?)?;
-                        index_written = true;
-
-                        // write the main index as the day view
-                        if self.config.default_calendar_view == "day" {
-                            template_out_file.pop();
-                            template_out_file.pop();
-                            template_out_file.push(PathBuf::from("index.html"));
-                            println!(
-                                "Writing template to main index file: {:?}",
-                                template_out_file
-                            );
-                            self.render_to("day.html", &context, File::create(template_out_file)?)?;
-                        }
-                    }
-                }
-            }
+    pub fn setup_output_dir(&self) -> Result<()> {
+        let mut writer = self.writer.borrow_mut();
+        let styles_dir = Path::new("styles");
+        writer.create_dir(styles_dir)?;
 
-            previous_file_name = Some(file_name);
+        if self.config.copy_stylesheet_to_output {
+            let stylesheet_destination = styles_dir.join("style.css");
+            let source_stylesheet = &self.config.copy_stylesheet_from;
+            let stylesheet = fs::read(source_stylesheet)
+                .context(format!("could not read stylesheet {:?}", source_stylesheet))?;
+            writer
+                .write(&stylesheet_destination, &stylesheet)
+                .context(format!(
+                    "could not copy stylesheet {:?} to destination: {:?}",
+                    source_stylesheet, stylesheet_destination
+                ))?;
         }
 
         Ok(())
     }
 
-    pub fn create_agenda_pages(&self) -> Result<()> {
-        let output_dir = Self::create_subdir(&PathBuf::from(&self.config.output_dir), "agenda")?;
+    /// Returns the path of a month page relative to the output directory
+    fn month_page_path(&self, year: Year, month: u8) -> PathBuf {
+        self.styled_month_page_path(year, month, self.config.zero_pad_file_names)
+    }
 
-        let start = if self.config.agenda_start_date.is_empty() {
-            OffsetDateTime::now_utc().date()
+    fn styled_month_page_path(&self, year: Year, month: u8, zero_padded: bool) -> PathBuf {
+        let month = if zero_padded {
+            format!("{:02}", month)
         } else {
-            Date::parse(
-                &self.config.agenda_start_date,
-                &time::format_description::parse("[year]-[month]-[day]").unwrap(),
-            )
-            .expect("Invalid agenda start date in config")
+            month.to_string()
         };
+        self.period_page_path("month", year, &month)
+    }
 
-        let past_events = self
-            .days
-            .range(..start)
-            .flat_map(|(day, events)| {
-                events.iter().map(move |event| {
-                    (
-                        DayContext::new(*day, vec![event.context(self.display_tz)]),
-                        event,
-                    )
-                })
-            })
-            .collect::<Vec<_>>();
-        let mut past_events_iter = past_events
-            .rchunks(self.config.agenda_events_per_page)
-            .zip(1_isize..)
-            .peekable();
-        while let Some((events, page)) = past_events_iter.next() {
-            println!("page: {}", page);
-            for (_day, event) in events {
-                println!(
+    /// Returns the path of a week page relative to the output directory
+    fn week_page_path(&self, year: Year, week: WeekNum) -> PathBuf {
+        self.styled_week_page_path(year, week, self.config.zero_pad_file_names)
+    }
+
+    fn styled_week_page_path(&self, year: Year, week: WeekNum, zero_padded: bool) -> PathBuf {
+        let week = if zero_padded {
+            format!("W{:02}", week)
+        } else {
+            week.to_string()
+        };
+        self.period_page_path("week", year, &week)
+    }
+
+    /// Returns the path of a page within a year, placing it in a year subdirectory if configured
+    fn period_page_path(&self, view: &str, year: Year, period: &str) -> PathBuf {
+        if self.config.group_pages_by_year {
+            PathBuf::from(format!("{}/{}/{}.html", view, year, period))
+        } else {
+            PathBuf::from(format!("{}/{}-{}.html", view, year, period))
+        }
+    }
+
+    /// Writes a redirect from the page's unpadded name if configured
+    fn write_legacy_redirect(&self, legacy_page: &Path, page: &Path) -> Result<()> {
+        if self.config.zero_pad_file_names
+            && self.config.redirect_legacy_file_names
+            && legacy_page != page
+        {
+            self.write_redirect(legacy_page, page)?;
+        }
+        Ok(())
+    }
+
+    /// Attaches the data of `enricher` to the days from the first to the last one with events, see `enrichment`
+    pub fn add_day_enrichment(&mut self, enricher: &dyn DayEnricher) -> Result<()> {
+        enrich_days(&mut self.day_extra, &self.days, enricher)
+    }
+
+    /// Adds the notes, the holiday, the extra data, the sunrise and sunset, and the moon phase of each day
+    fn annotate_days(&self, days: &mut [DayContext]) {
+        for day in days {
+            day.notes = self.notes.get(&day.calendar_date).cloned();
+            day.holiday = self.holidays.get(&day.calendar_date).cloned();
+            day.extra = self
+                .day_extra
+                .get(&day.calendar_date)
+                .cloned()
+                .unwrap_or_default();
+            (day.sunrise, day.sunset) = self.sun_times(day.calendar_date);
+            day.moon_phase = self.moon_phase(day.calendar_date);
+        }
+    }
+
+    /// Returns the phase of the moon on `date` in the display timezone, if `moon_phases` is set
+    fn moon_phase(&self, date: Date) -> Option<MoonPhase> {
+        if !self.config.moon_phases {
+            return None;
+        }
+        Some(MoonPhase::of_day(
+            start_of_day(date, self.display_tz).ok()?,
+            start_of_day(date.next_day().unwrap_or(date), self.display_tz).ok()?,
+        ))
+    }
+
+    /// Returns when the sun rises and sets on `date` in the display timezone, if coordinates are configured
+    ///
+    /// Days on which the sun does not rise or set have neither.
+    fn sun_times(&self, date: Date) -> (Option<String>, Option<String>) {
+        let (Some(latitude), Some(longitude)) = (self.config.latitude, self.config.longitude)
+        else {
+            return (None, None);
+        };
+        match sun::daylight(date, latitude, longitude) {
+            Daylight::RiseAndSet(sunrise, sunset) => {
+                let local_time = |time: OffsetDateTime| {
+                    time.to_timezone(self.display_tz)
+                        .format(format_description!("[hour]:[minute]"))
+                        .ok()
+                };
+                (local_time(sunrise), local_time(sunset))
+            }
+            Daylight::PolarDay | Daylight::PolarNight => (None, None),
+        }
+    }
+
+    /// Returns the weeks from Sunday to Saturday overlapping `start`..`end`, with the events of each day
+    ///
+    /// The leading and trailing days belong to the adjacent months, they show their events too and are marked with
+    /// `adjacent_month` so templates can dim them.
+    fn month_grid(&self, start: Date, end: Date) -> Vec<Vec<DayContext>> {
+        let mut weeks = Vec::new();
+        let mut sunday = start - (start.weekday().number_days_from_sunday() as i64).days();
+        while sunday < end {
+            trace!("  Creating week of {}", sunday);
+            let mut week: Vec<DayContext> = (0..7)
+                .map(|offset| {
+                    let date = sunday + offset.days();
+                    let events = self
+                        .days
+                        .get(&date)
+                        .map(|events| {
+                            self.events
+                                .resolve(events)
+                                .map(|event| event.context_on(date, self.display_tz, self.config))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    let mut day = DayContext::new(date, events);
+                    day.adjacent_month = !(start..end).contains(&date);
+                    day
+                })
+                .collect();
+            self.annotate_days(&mut week);
+            let week_number = (sunday + 1.days()).iso_week();
+            for day in &mut week {
+                day.week_number = Some(week_number);
+            }
+            weeks.push(week);
+            sunday += 7.days();
+        }
+        weeks
+    }
+
+    /// Returns the path of a day page relative to the output directory
+    fn day_page_path(&self, day: &Day) -> PathBuf {
+        self.dated_page_path("day", day)
+    }
+
+    /// Returns the path of a page of `view` showing a single day, relative to the output directory
+    fn dated_page_path(&self, view: &str, day: &Day) -> PathBuf {
+        if self.config.group_pages_by_year {
+            PathBuf::from(format!(
+                "{}/{}/{:02}-{:02}.html",
+                view,
+                day.year(),
+                day.month() as u8,
+                day.day()
+            ))
+        } else {
+            PathBuf::from(format!("{}/{}.html", view, day))
+        }
+    }
+
+    pub fn create_month_pages(&self) -> Result<()> {
+        self.create_view_dir("month")?;
+
+        let mut pages = PeriodPage::link(
+            self.months
+                .iter()
+                .map(|(&(year, month), weeks)| {
+                    ((year, month, weeks), self.month_page_path(year, month))
+                })
+                .collect(),
+        );
+
+        // the index shows the current month, or the last one before it
+        let index_month = index_position(
+            pages.iter().map(|page| (page.period.0, page.period.1)),
+            (
+                self.current_date_time.year(),
+                self.current_date_time.month() as u8,
+            ),
+        );
+        for (index, page) in pages.iter_mut().enumerate() {
+            let (year, month, weeks) = page.period;
+            // an event spanning several weeks of the month is filed under each of them
+            page.events = weeks
+                .values()
+                .flat_map(BTreeMap::values)
+                .flatten()
+                .collect::<HashSet<_>>()
+                .len();
+            page.name = Some(format!("{}-{:02}", year, month));
+            page.legacy_page = Some(self.styled_month_page_path(year, month, false));
+            if index_month == Some(index) {
+                page.index_pages.push(PathBuf::from("month/index.html"));
+                if self.config.default_calendar_view == "month" {
+                    page.index_pages.push(PathBuf::from("index.html"));
+                }
+            }
+        }
+
+        self.render_periods(
+            "month",
+            "month.html",
+            &pages,
+            |&(year, month, weeks), page| {
+                trace!("month: {}", month);
+                let month_start = Date::from_calendar_date(year, month_from_u8(month)?, 1)?;
+                let month_end =
+                    month_start + (days_in_year_month(year, month_from_u8(month)?) as i64).days();
+                let week_list = self.month_grid(month_start, month_end);
+
+                // lay out the multi-day events overlapping this month, which are filed under its weeks
+                let month_events: BTreeSet<EventId> = weeks
+                    .values()
+                    .flat_map(BTreeMap::values)
+                    .flatten()
+                    .copied()
+                    .collect();
+                let timeline = TimelineContext::new(
+                    month_start,
+                    month_end,
+                    month_events.iter().map(|id| &self.events[*id]),
+                    self.display_tz(),
+                    self.config,
+                )?;
+
+                let mut context = Context::new();
+                context.insert("year", &year);
+                context.insert("month", &month);
+                context.insert("weeks", &week_list);
+                context.insert("timeline", &timeline);
+                context.insert("mini_calendar", &self.mini_calendar(month_start, page)?);
+                Ok(context)
+            },
+        )
+    }
+
+    /// Writes a page per year with its twelve months in miniature, linking to the month and day pages
+    pub fn create_year_pages(&self) -> Result<()> {
+        self.create_view_dir("year")?;
+
+        let mut pages = PeriodPage::link(
+            self.years
+                .iter()
+                .map(|(&year, events)| {
+                    ((year, events), PathBuf::from(format!("year/{}.html", year)))
+                })
+                .collect(),
+        );
+
+        // the index shows the current year, or the last one before it
+        let current_year = self.current_date_time.year();
+        let index_year = self
+            .years
+            .range(..=current_year)
+            .next_back()
+            .or_else(|| self.years.iter().next())
+            .map(|(year, _)| *year);
+        for page in pages.iter_mut() {
+            let (year, events) = page.period;
+            page.events = events.len();
+            page.name = Some(year.to_string());
+            if index_year == Some(year) {
+                page.index_pages.push(PathBuf::from("year/index.html"));
+            }
+        }
+
+        self.render_periods("year", "year.html", &pages, |&(year, _), page| {
+            trace!("year: {}", year);
+            let months = (1..=12)
+                .map(|month| {
+                    let first_day = Date::from_calendar_date(year, month_from_u8(month)?, 1)?;
+                    self.mini_calendar(first_day, page)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut context = Context::new();
+            context.insert("year", &year);
+            context.insert("months", &months);
+            Ok(context)
+        })
+    }
+
+    /// Returns the month of `date` in miniature, with links from `page` to the month page and the day pages
+    ///
+    /// The pages of the views get it as `mini_calendar`, so templates can show it in a sidebar.
+    fn mini_calendar(&self, date: Date, page: &Path) -> Result<MiniMonthContext> {
+        let (year, month) = (date.year(), date.month() as u8);
+        let link = (self.config.render_month && self.months.contains_key(&(year, month)))
+            .then(|| self.site_link(page, &self.month_page_path(year, month)));
+        let mini_calendar = MiniMonthContext::new(year, date.month(), link, |date| {
+            let events = self.days.get(&date).map_or(0, Vec::len);
+            let link = (self.config.render_day && events > 0)
+                .then(|| self.site_link(page, &self.day_page_path(&date)));
+            (events, link)
+        })?;
+        Ok(mini_calendar)
+    }
+
+    pub fn create_week_pages(&self) -> Result<()> {
+        self.create_view_dir("week")?;
+
+        let mut pages = PeriodPage::link(
+            self.weeks
+                .iter()
+                .map(|(&(year, week), events)| {
+                    ((year, week, events), self.week_page_path(year, week))
+                })
+                .collect(),
+        );
+
+        // the index shows the current week, or the last one before it
+        let index_week = index_position(
+            pages.iter().map(|page| (page.period.0, page.period.1)),
+            iso_week(self.current_date_time.date()),
+        );
+        for (index, page) in pages.iter_mut().enumerate() {
+            let (year, week, events) = page.period;
+            page.events = events.len();
+            page.name = Some(format!("{}-{:02}", year, week));
+            page.legacy_page = Some(self.styled_week_page_path(year, week, false));
+            if index_week == Some(index) {
+                page.index_pages.push(PathBuf::from("week/index.html"));
+                if self.config.default_calendar_view == "week" {
+                    page.index_pages.push(PathBuf::from("index.html"));
+                }
+            }
+        }
+
+        self.render_periods(
+            "week",
+            "week.html",
+            &pages,
+            |&(year, week, events), page| {
+                trace!("week: {}", week);
+
+                let week_dates = self.week_dates(year, week, events, page)?;
+
+                // lay out the multi-day events overlapping this week, clipped to it
+                let week_start = first_day_of_week(&year, &week)?;
+                let timeline = TimelineContext::new(
+                    week_start,
+                    week_start + 7.days(),
+                    self.events.resolve(events),
+                    self.display_tz(),
+                    self.config,
+                )?;
+
+                let mut context = Context::new();
+                context.insert("year", &year);
+                // handling weeks where the month changes
+                context.insert(
+                    "month",
+                    &week_dates
+                        .iter()
+                        .map(|d| d.month.clone())
+                        .dedup()
+                        .collect::<Vec<String>>()
+                        .join(" - "),
+                );
+                context.insert("week", &week);
+                context.insert("week_dates", &week_dates);
+                context.insert("timeline", &timeline);
+                // the month of the week's Monday, which its ISO number counts from
+                context.insert("mini_calendar", &self.mini_calendar(week_start, page)?);
+                context.insert("slot_minutes", &self.config.time_slot_minutes);
+                context.insert("slots_per_day", &self.config.slots_per_day());
+                Ok(context)
+            },
+        )
+    }
+
+    /// Returns the days of a week with their events, each event on every day of the week it spans
+    fn week_dates(
+        &self,
+        year: Year,
+        week: WeekNum,
+        events: &[EventId],
+        page: &Path,
+    ) -> Result<Vec<DayContext>> {
+        let mut week_day_map: WeekDayMap = BTreeMap::new();
+
+        for event in self.events.resolve(events) {
+            trace!(
+                "  event: ({} {} {}) {} {}",
+                event.start().weekday(),
+                event.year(),
+                event.week(),
+                event.summary(),
+                event.start(),
+            );
+            for date in event
+                .days(self.display_tz)
+                .filter(|date| iso_week(*date) == (year, week))
+            {
+                let day_of_week = date.weekday().number_days_from_monday();
+                week_day_map.entry(day_of_week).or_default().push(event);
+            }
+        }
+
+        // create week days
+        let mut week_dates = week_day_map.context(&year, &week, self.display_tz(), self.config)?;
+        self.annotate_days(&mut week_dates);
+        for day in &mut week_dates {
+            self.link_event_pages(page, &mut day.events);
+        }
+        Ok(week_dates)
+    }
+
+    /// Writes a variant of every week page with the events of each day grouped by location, e.g. for venues which
+    /// print a schedule per building
+    pub fn create_week_location_pages(&self) -> Result<()> {
+        self.create_view_dir("locations")?;
+
+        let mut pages = PeriodPage::link(
+            self.weeks
+                .iter()
+                .map(|(&(year, week), events)| {
+                    let week_name = format!("W{:02}", week);
+                    (
+                        (year, week, events),
+                        self.period_page_path("locations", year, &week_name),
+                    )
+                })
+                .collect(),
+        );
+        let index_week = index_position(
+            pages.iter().map(|page| (page.period.0, page.period.1)),
+            iso_week(self.current_date_time.date()),
+        );
+        for (index, page) in pages.iter_mut().enumerate() {
+            let (year, week, events) = page.period;
+            page.events = events.len();
+            page.name = Some(format!("{}-{:02}", year, week));
+            if index_week == Some(index) {
+                page.index_pages.push(PathBuf::from("locations/index.html"));
+            }
+        }
+
+        self.render_periods(
+            "locations",
+            "week_locations.html",
+            &pages,
+            |&(year, week, events), page| {
+                let week_dates: Vec<LocationDayContext> = self
+                    .week_dates(year, week, events, page)?
+                    .into_iter()
+                    .map(|day| LocationDayContext::group(day, self.config))
+                    .collect();
+
+                let mut context = Context::new();
+                context.insert("year", &year);
+                context.insert("week", &week);
+                context.insert("week_dates", &week_dates);
+                Ok(context)
+            },
+        )
+    }
+
+    pub fn create_day_pages(&self) -> Result<()> {
+        self.create_view_dir("day")?;
+
+        let mut pages = PeriodPage::link(
+            self.days
+                .iter()
+                .map(|(day, events)| ((*day, events), self.day_page_path(day)))
+                .collect(),
+        );
+
+        // the index shows the current day, or the last one before it
+        let index_day = index_position(
+            pages.iter().map(|page| page.period.0),
+            self.current_date_time.date(),
+        );
+        for (index, page) in pages.iter_mut().enumerate() {
+            page.events = page.period.1.len();
+            page.name = Some(page.period.0.to_string());
+            if index_day == Some(index) {
+                page.index_pages.push(PathBuf::from("day/index.html"));
+                if self.config.default_calendar_view == "day" {
+                    page.index_pages.push(PathBuf::from("index.html"));
+                }
+            }
+        }
+
+        self.render_periods("day", "day.html", &pages, |&(day, events), day_page| {
+            trace!("day: {}", day);
+            for event in self.events.resolve(events) {
+                trace!(
                     "  event: ({} {} {}) {} {}",
                     event.start().weekday(),
                     event.year(),
@@ -543,65 +1640,615 @@ impl<'a> CalendarCollection<'a> {
                     event.start(),
                 );
             }
-            let file_name = format!("{}.html", -page);
-            let previous_file_name = past_events_iter
-                .peek()
-                .map(|(_, previous_page)| format!("{}.html", -previous_page));
-            let next_file_name = format!("{}.html", 1 - page);
-
-            let template_out_file = output_dir.join(PathBuf::from(&file_name));
 
             let mut context = Context::new();
-            context.insert("stylesheet_path", &self.config.stylesheet_path);
-            context.insert("timezone", self.display_tz.name());
-            context.insert("page", &page);
-            context.insert("events", events);
-            context.insert("previous_file_name", &previous_file_name);
-            context.insert("next_file_name", &next_file_name);
-            println!("Writing template to file: {:?}", template_out_file);
-            self.render_to("agenda.html", &context, File::create(template_out_file)?)?;
+            context.insert("year", &day.year());
+            context.insert("month", &day.month());
+            context.insert("day", &day.day());
+            context.insert("mini_calendar", &self.mini_calendar(day, day_page)?);
+            let mut day_events: Vec<EventContext> = self
+                .events
+                .resolve(events)
+                .map(|e| e.context_on(day, self.display_tz, self.config))
+                .collect();
+            self.link_event_pages(day_page, &mut day_events);
+            context.insert("events", &day_events);
+            let threshold = self.config.day_split_threshold;
+            let mut sections = if threshold > 0 && events.len() > threshold {
+                DaySection::split(
+                    day,
+                    self.events.resolve(events),
+                    self.display_tz,
+                    self.config,
+                )
+            } else {
+                Vec::new()
+            };
+            for section in &mut sections {
+                self.link_event_pages(day_page, &mut section.events);
+            }
+            context.insert("sections", &sections);
+            context.insert("notes", &self.notes.get(&day));
+            context.insert("holiday", &self.holidays.get(&day));
+            context.insert("extra", &self.day_extra.get(&day));
+            let (sunrise, sunset) = self.sun_times(day);
+            context.insert("sunrise", &sunrise);
+            context.insert("sunset", &sunset);
+            context.insert("moon_phase", &self.moon_phase(day));
+            context.insert("slot_minutes", &self.config.time_slot_minutes);
+            context.insert("slots_per_day", &self.config.slots_per_day());
+            Ok(context)
+        })
+    }
+
+    /// Writes a detail page per event instance, with links to its day, week, and month pages
+    ///
+    /// The pages are ordered by start, so the pagination steps through the events one by one.
+    pub fn create_event_pages(&self) -> Result<()> {
+        self.create_view_dir("event")?;
+
+        let mut pages = PeriodPage::link(
+            self.days
+                .iter()
+                .flat_map(|(day, events)| {
+                    self.events
+                        .resolve(events)
+                        .filter(|event| event.starts_on(*day, self.display_tz))
+                        .map(move |event| ((*day, event), event.detail_page_path()))
+                })
+                .collect(),
+        );
+        for page in pages.iter_mut() {
+            page.events = 1;
+            page.name = Some(page.period.1.instance_id());
+        }
+
+        self.render_periods(
+            "event",
+            "event.html",
+            &pages,
+            |&(day, event), event_page| {
+                trace!("event: {} {}", event.summary(), event.start());
+                // the periods of the day the event is filed under, in the display timezone
+                let year = day.year();
+                let (week_year, week) = iso_week(day);
+                let link = |rendered: bool, page: PathBuf| {
+                    rendered.then(|| self.site_link(event_page, &page))
+                };
+
+                let mut event_context = event.context(self.display_tz, self.config);
+                self.link_event_pages(event_page, std::slice::from_mut(&mut event_context));
+
+                let mut context = Context::new();
+                context.insert("event", &event_context);
+                context.insert("date", &day.to_string());
+                context.insert("year", &year);
+                context.insert("month", &(day.month() as u8));
+                context.insert("day", &day.day());
+                context.insert(
+                    "day_link",
+                    &link(self.config.render_day, self.day_page_path(&day)),
+                );
+                context.insert(
+                    "week_link",
+                    &link(
+                        self.config.render_week,
+                        self.week_page_path(week_year, week),
+                    ),
+                );
+                context.insert(
+                    "month_link",
+                    &link(
+                        self.config.render_month,
+                        self.month_page_path(year, day.month() as u8),
+                    ),
+                );
+                Ok(context)
+            },
+        )
+    }
+
+    /// Writes a countdown page per instance of a featured event, see `Event::is_featured()`
+    ///
+    /// The time left is counted at the build, so the pages are as current as the site. The pages of past instances
+    /// are kept and say the event has started, so links to them keep working.
+    pub fn create_countdown_pages(&self) -> Result<()> {
+        self.create_view_dir("countdown")?;
+
+        let mut pages = PeriodPage::link(
+            self.featured()
+                .into_iter()
+                .map(|(day, event)| ((day, event), event.countdown_page_path()))
+                .collect(),
+        );
+        for page in pages.iter_mut() {
+            page.events = 1;
+            page.name = Some(page.period.1.instance_id());
         }
 
-        let future_events = self
+        self.render_periods(
+            "countdown",
+            "countdown.html",
+            &pages,
+            |&(day, event), countdown_page| {
+                trace!("countdown: {} {}", event.summary(), event.start());
+                let left = (event.start() - self.current_date_time).max(Duration::ZERO);
+
+                let mut context = Context::new();
+                let mut event_context = event.context(self.display_tz, self.config);
+                self.link_event_pages(countdown_page, std::slice::from_mut(&mut event_context));
+                context.insert("event", &event_context);
+                context.insert("date", &day.to_string());
+                context.insert("start_timestamp", &event.start().unix_timestamp());
+                context.insert("started", &(left == Duration::ZERO));
+                context.insert("days_left", &left.whole_days());
+                context.insert("hours_left", &(left.whole_hours() % 24));
+                context.insert("minutes_left", &(left.whole_minutes() % 60));
+                context.insert(
+                    "day_link",
+                    &self
+                        .config
+                        .render_day
+                        .then(|| self.site_link(countdown_page, &self.day_page_path(&day))),
+                );
+                Ok(context)
+            },
+        )
+    }
+
+    /// Returns the instances of featured events with their days, in order
+    fn featured(&self) -> Vec<(Day, &Event)> {
+        self.events_of_days(..)
+            .into_iter()
+            .filter(|(_, event)| event.is_featured(self.config))
+            .collect()
+    }
+
+    /// Returns the featured events which have not ended yet, with links from `page` to their countdown pages
+    ///
+    /// The index pages get them as `featured_events`, so templates can highlight what is coming up.
+    fn featured_events(&self, page: &Path) -> Vec<FeaturedEvent> {
+        if !self.config.has_featured_events() {
+            return Vec::new();
+        }
+        self.featured()
+            .into_iter()
+            .filter(|(_, event)| event.end() > self.current_date_time)
+            .map(|(day, event)| FeaturedEvent {
+                event: event.context(self.display_tz, self.config),
+                date: day.to_string(),
+                countdown_link: self.site_link(page, &event.countdown_page_path()),
+                days_left: (event.start() - self.current_date_time).whole_days().max(0),
+            })
+            .collect()
+    }
+
+    /// Links the events shown on `from_page` to their detail and series pages, if those are rendered
+    fn link_event_pages(&self, from_page: &Path, events: &mut [EventContext]) {
+        for event in events {
+            if self.config.render_event_pages {
+                let link = self.site_link(from_page, &event.detail_page_path());
+                event.set_detail_link(link);
+            }
+            if self.config.render_series_pages {
+                if let Some(series_page) = event.uid().and_then(|uid| self.series_page_path(uid)) {
+                    event.set_series_link(self.site_link(from_page, &series_page));
+                }
+            }
+        }
+    }
+
+    /// Returns the page of the series with `uid`, if several instances share it
+    ///
+    /// The page is named after the summary of the first instance, so a renamed occurrence does not move it.
+    fn series_page_path(&self, uid: &str) -> Option<PathBuf> {
+        let first = *self.series.get(uid)?.first()?;
+        self.events[first].series_page_path()
+    }
+
+    /// Writes a page per series of instances sharing a UID, with the description once and the list of its dates
+    ///
+    /// The upcoming dates are listed with links to their day pages, the past ones are only counted. The series are
+    /// ordered by their first instance.
+    pub fn create_series_pages(&self) -> Result<()> {
+        self.create_view_dir("series")?;
+
+        let mut series: Vec<(&str, &[EventId])> = self
+            .series
+            .iter()
+            .map(|(uid, instances)| (uid.as_str(), instances.as_slice()))
+            .collect();
+        series.sort_by_key(|(_, instances)| instances.first().map(|id| self.events[*id].start()));
+        let mut pages = PeriodPage::link(
+            series
+                .into_iter()
+                .filter_map(|(uid, instances)| {
+                    Some(((uid, instances), self.series_page_path(uid)?))
+                })
+                .collect(),
+        );
+        for page in pages.iter_mut() {
+            page.events = page.period.1.len();
+            page.name = Some(page.period.0.to_string());
+        }
+
+        self.render_periods(
+            "series",
+            "series.html",
+            &pages,
+            |&(uid, instances), series_page| {
+                trace!("series: {} with {} instances", uid, instances.len());
+                let (past, upcoming): (Vec<&Event>, Vec<&Event>) = self
+                    .events
+                    .resolve(instances)
+                    .partition(|event| event.end() <= self.current_date_time);
+                // the next instance represents the series, or the last one once it is over
+                let event = upcoming
+                    .first()
+                    .or(past.last())
+                    .ok_or_else(|| eyre!("series {} has no instances", uid))?;
+                let mut event_context = event.context(self.display_tz, self.config);
+                self.link_event_pages(series_page, std::slice::from_mut(&mut event_context));
+                let dates: Vec<DayContext> = upcoming
+                    .iter()
+                    .map(|event| {
+                        let day = event.start().to_timezone(self.display_tz).date();
+                        let mut date = self.agenda_entry(day, event, series_page);
+                        self.link_event_pages(series_page, &mut date.events);
+                        date
+                    })
+                    .collect();
+
+                let mut context = Context::new();
+                context.insert("event", &event_context);
+                context.insert("uid", uid);
+                context.insert("upcoming", &dates);
+                context.insert("past_count", &past.len());
+                Ok(context)
+            },
+        )
+    }
+
+    /// Writes a page per day showing the events in parallel tracks, like a conference program
+    ///
+    /// The index shows the first day from today on, or the last day if all of them are in the past.
+    pub fn create_track_pages(&self) -> Result<()> {
+        self.create_view_dir("tracks")?;
+
+        let index_day = self
             .days
-            .range(start..)
-            .flat_map(|(day, events)| {
-                events.iter().map(move |event| {
-                    (
-                        DayContext::new(*day, vec![event.context(self.display_tz)]),
-                        event,
+            .range(self.current_date_time.date()..)
+            .next()
+            .or_else(|| self.days.iter().next_back())
+            .map(|(day, _)| *day);
+
+        let mut pages = PeriodPage::link(
+            self.days
+                .iter()
+                .map(|(day, events)| ((*day, events), self.dated_page_path("tracks", day)))
+                .collect(),
+        );
+        for page in pages.iter_mut() {
+            page.events = page.period.1.len();
+            page.name = Some(page.period.0.to_string());
+            if index_day == Some(page.period.0) {
+                page.index_pages.push(PathBuf::from("tracks/index.html"));
+            }
+        }
+
+        self.render_periods("tracks", "tracks.html", &pages, |&(day, events), _| {
+            let tracks = TrackContext::assign(
+                day,
+                self.events.resolve(events),
+                self.display_tz,
+                self.config,
+            );
+
+            let mut context = Context::new();
+            context.insert("year", &day.year());
+            context.insert("month", &day.month());
+            context.insert("day", &day.day());
+            context.insert("tracks", &tracks);
+            context.insert("notes", &self.notes.get(&day));
+            context.insert("holiday", &self.holidays.get(&day));
+            context.insert("extra", &self.day_extra.get(&day));
+            let (sunrise, sunset) = self.sun_times(day);
+            context.insert("sunrise", &sunrise);
+            context.insert("sunset", &sunset);
+            context.insert("moon_phase", &self.moon_phase(day));
+            context.insert("slot_minutes", &self.config.time_slot_minutes);
+            context.insert("slots_per_day", &self.config.slots_per_day());
+            Ok(context)
+        })
+    }
+
+    /// Writes Atom and JSON feeds of the upcoming events into the output directory
+    ///
+    /// Besides the feed of all events, a feed is written for each category and calendar if enabled.
+    pub fn create_feed_pages(&self) -> Result<()> {
+        for slice in &self.feed_slices {
+            if slice.filter != FeedFilter::Changes {
+                self.write_feed(slice)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the Atom and JSON feeds of the changes to upcoming events, see `changes::detect_changes()`
+    pub fn create_changes_feed(&self, changes: &[EventChange]) -> Result<()> {
+        let Some(slice) = self
+            .feed_slices
+            .iter()
+            .find(|slice| slice.filter == FeedFilter::Changes)
+        else {
+            return Ok(());
+        };
+        let output_root = PathBuf::from(&self.config.output_dir);
+        let atom_page = slice.atom_page();
+        let json_page = slice.json_page();
+        let entries: Vec<FeedEntry> = changes
+            .iter()
+            .map(|change| {
+                let day = change.event.start.to_timezone(self.display_tz).date();
+                let link = if self.days.contains_key(&day) {
+                    format!(
+                        "{}#event-{}",
+                        self.site_link(&atom_page, &self.day_page_path(&day)),
+                        change.id
                     )
-                })
+                } else {
+                    self.site_link(&atom_page, Path::new("index.html"))
+                };
+                FeedEntry {
+                    id: format!(
+                        "{}:{}:{}",
+                        change.id,
+                        change.kind.label().to_lowercase(),
+                        change.detected.unix_timestamp()
+                    ),
+                    title: format!("{}: {}", change.kind.label(), change.event.summary),
+                    description: change.previous_start.map(|previous_start| {
+                        format!(
+                            "Previously {}",
+                            previous_start
+                                .to_timezone(self.display_tz)
+                                .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+                                .unwrap_or_default()
+                        )
+                    }),
+                    location: change.event.location.clone(),
+                    link,
+                    start: change.event.start,
+                    end: change.event.end,
+                    updated: change.detected,
+                    sequence: 0,
+                }
             })
-            .collect::<Vec<_>>();
-        if future_events.is_empty() {
-            println!("page: 0");
-            let previous_file_name = if past_events.is_empty() {
-                None
-            } else {
-                Some("-1.html")
-            };
+            .collect();
 
-            let template_out_file = &output_dir.join(PathBuf::from("0.html"));
+        self.record_event_count(&output_root.join(&atom_page), entries.len());
+        self.record_event_count(&output_root.join(&json_page), entries.len());
+        let atom_url = self.site_url(&atom_page);
+        let json_url = self.site_url(&json_page);
+        debug!("Writing the changes feed with {} changes", entries.len());
+        self.write_file(
+            &output_root.join(&atom_page),
+            feed::atom(&slice.title, &slice.urn(), atom_url.as_deref(), &entries),
+        )?;
+        self.write_file(
+            &output_root.join(&json_page),
+            feed::json_feed(&slice.title, json_url.as_deref(), &entries)?,
+        )
+    }
 
-            let mut context = Context::new();
-            context.insert("stylesheet_path", &self.config.stylesheet_path);
-            context.insert("timezone", self.display_tz.name());
-            context.insert("page", &0);
-            context.insert("events", &future_events);
-            context.insert("previous_file_name", &previous_file_name);
-            context.insert("next_file_name", &None::<&str>);
-            println!("Writing template to file: {:?}", template_out_file);
-            self.render_to("agenda.html", &context, File::create(template_out_file)?)?;
+    /// Writes the calendar of the changes found by the build, see `changes::changes_ics()`
+    pub fn create_changes_ics(&self, changes: &[EventChange]) -> Result<()> {
+        let path = PathBuf::from(&self.config.output_dir).join(changes::CHANGES_ICS_FILE_NAME);
+        self.record_event_count(&path, changes.len());
+        debug!(
+            "Writing the changes calendar with {} changes",
+            changes.len()
+        );
+        self.write_file(&path, changes::changes_ics(changes, self.current_date_time))
+    }
+
+    /// Writes the Atom and JSON feed of a slice of the upcoming events
+    fn write_feed(&self, slice: &FeedSlice) -> Result<()> {
+        let output_root = PathBuf::from(&self.config.output_dir);
+        let atom_page = slice.atom_page();
+        let json_page = slice.json_page();
+        let entries: Vec<FeedEntry> = self
+            .days
+            .range(self.current_date_time.date()..)
+            .flat_map(|(day, ids)| ids.iter().map(move |id| (day, *id)))
+            // multi-day events are filed under every day they cover, but are one entry
+            .filter(|(day, id)| self.events[*id].starts_on(**day, self.display_tz))
+            .filter(|(_, id)| self.in_feed(&slice.filter, *id))
+            .take(self.config.feed_max_entries)
+            .map(|(day, id)| {
+                let event = &self.events[id];
+                FeedEntry {
+                    id: event.instance_id(),
+                    title: event.summary().to_string(),
+                    description: event.description().map(String::from),
+                    location: event.location().map(String::from),
+                    link: self.event_link(&atom_page, day, event),
+                    start: event.start(),
+                    end: event.end(),
+                    updated: event.last_modified().unwrap_or_else(|| event.start()),
+                    sequence: event.sequence(),
+                }
+            })
+            .collect();
+
+        self.record_event_count(&output_root.join(&atom_page), entries.len());
+        self.record_event_count(&output_root.join(&json_page), entries.len());
+        let atom_url = self.site_url(&atom_page);
+        let json_url = self.site_url(&json_page);
+        debug!(
+            "Writing feeds {:?} with {} events",
+            atom_page.with_extension(""),
+            entries.len()
+        );
+        self.write_file(
+            &output_root.join(&atom_page),
+            feed::atom(&slice.title, &slice.urn(), atom_url.as_deref(), &entries),
+        )?;
+        self.write_file(
+            &output_root.join(&json_page),
+            feed::json_feed(&slice.title, json_url.as_deref(), &entries)?,
+        )
+    }
+
+    /// Whether an event belongs into a feed
+    fn in_feed(&self, filter: &FeedFilter, id: EventId) -> bool {
+        match filter {
+            FeedFilter::All => true,
+            FeedFilter::Category(slug) => self.events[id]
+                .categories()
+                .iter()
+                .any(|category| feed::slug(category) == *slug),
+            FeedFilter::Calendar(slug) => self
+                .calendar_events
+                .iter()
+                .any(|(ids, calendar)| ids.contains(&id) && calendar == slug),
+            FeedFilter::Changes => false,
+        }
+    }
+
+    /// Writes `freebusy.ics` with the busy times from the start of today for `free_busy_days` days
+    pub fn create_free_busy_pages(&self) -> Result<()> {
+        let start = self.current_date_time.replace_time(Time::MIDNIGHT);
+        let end = start + Duration::days(self.config.free_busy_days.into());
+        let periods = self
+            .events
+            .iter()
+            .map(|event| BusyPeriod {
+                start: event.start(),
+                end: event.end(),
+                status: event.busy_status(),
+            })
+            .collect();
+        let periods = free_busy::merge_periods(periods, start, end);
+
+        debug!("Writing free/busy with {} busy periods", periods.len());
+        self.write_file(
+            &PathBuf::from(&self.config.output_dir).join(free_busy::FILE_NAME),
+            free_busy::vfreebusy(start, end, &periods),
+        )
+    }
+
+    /// Writes `events.sqlite` with every event instance, see `sqlite::SCHEMA`
+    pub fn create_sqlite_pages(&self) -> Result<()> {
+        debug!(
+            "Writing {} events into the SQLite export",
+            self.events.len()
+        );
+        self.write_file(
+            &PathBuf::from(&self.config.output_dir).join(sqlite::FILE_NAME),
+            sqlite::events_database(self.events.iter())?,
+        )
+    }
+
+    /// Returns a link from `from_page` to the event, its `URL` or its day page depending on `Config::event_links`
+    fn event_link(&self, from_page: &Path, day: &Day, event: &Event) -> String {
+        match (self.config.event_links, event.url()) {
+            (EventLinks::Url, Some(url)) => url.to_string(),
+            _ => self.event_page_link(from_page, day, event),
+        }
+    }
+
+    /// Returns a link from `from_page` to the event on its day page
+    fn event_page_link(&self, from_page: &Path, day: &Day, event: &Event) -> String {
+        format!(
+            "{}#{}",
+            self.site_link(from_page, &self.day_page_path(day)),
+            event.anchor()
+        )
+    }
+
+    /// Returns the public address of a page, if `site_url` is configured
+    fn site_url(&self, page: &Path) -> Option<String> {
+        let site_url = self.config.site_url.trim_end_matches('/');
+        (!site_url.is_empty()).then(|| {
+            format!(
+                "{}/{}",
+                site_url,
+                relative_link(Path::new("index.html"), page)
+            )
+        })
+    }
+
+    /// Returns a link from `from_page` to `to_page`, absolute if `site_url` is configured
+    fn site_link(&self, from_page: &Path, to_page: &Path) -> String {
+        self.site_url(to_page)
+            .unwrap_or_else(|| relative_link(from_page, to_page))
+    }
+
+    pub fn create_agenda_pages(&self) -> Result<()> {
+        self.create_view_dir("agenda")?;
+        // every agenda page is in the same directory, so links from one work from all of them
+        let agenda_page = Path::new("agenda/index.html");
+
+        // without a start date, the agenda starts with the events which have not ended at build time
+        let start = self.config.agenda_start()?;
+
+        let (past_events, mut future_events): (Vec<_>, Vec<_>) = self
+            .events_of_days(..)
+            .into_iter()
+            .partition(|(day, event)| match start {
+                Some(start) => *day < start,
+                None => event.end() <= self.current_date_time,
+            });
+        if self.config.agenda_max_events > 0 {
+            future_events.truncate(self.config.agenda_max_events);
+        }
+
+        // the past pages count down from -1, the upcoming ones up from 0, which is also the index
+        let per_page = self.config.agenda_events_per_page;
+        let mut periods: Vec<(isize, &[(Date, &Event)])> = past_events
+            .rchunks(per_page)
+            .zip(1_isize..)
+            .map(|(events, page)| (-page, events))
+            .collect();
+        periods.reverse();
+        if future_events.is_empty() {
+            periods.push((0, &[]));
         } else {
-            let mut future_events_iter = future_events
-                .rchunks(self.config.agenda_events_per_page)
-                .zip(0..)
-                .peekable();
-            while let Some((events, page)) = future_events_iter.next() {
-                println!("page: {}", page);
+            periods.extend(
+                future_events
+                    .chunks(per_page)
+                    .zip(0..)
+                    .map(|(events, page)| (page, events)),
+            );
+        }
+
+        let mut pages = PeriodPage::link(
+            periods
+                .into_iter()
+                .map(|(page, events)| {
+                    (
+                        (page, events),
+                        PathBuf::from(format!("agenda/{}.html", page)),
+                    )
+                })
+                .collect(),
+        );
+        for page in pages.iter_mut() {
+            page.events = page.period.1.len();
+            if page.period.0 == 0 {
+                page.index_pages.push(agenda_page.to_path_buf());
+            }
+        }
+
+        self.render_periods(
+            "agenda",
+            "agenda.html",
+            &pages,
+            |&(page, events), agenda_page| {
+                trace!("page: {}", page);
                 for (_day, event) in events {
-                    println!(
+                    trace!(
                         "  event: ({} {} {}) {} {}",
                         event.start().weekday(),
                         event.year(),
@@ -610,87 +2257,600 @@ impl<'a> CalendarCollection<'a> {
                         event.start(),
                     );
                 }
-                let file_name = format!("{}.html", page);
-                let previous_file_name = if page == 0 && past_events.is_empty() {
-                    None
-                } else {
-                    Some(format!("{}.html", page - 1))
-                };
-                let next_file_name = future_events_iter
-                    .peek()
-                    .map(|(_, next_page)| format!("{}.html", next_page));
-
-                let template_out_file = output_dir.join(PathBuf::from(&file_name));
 
                 let mut context = Context::new();
-                context.insert("stylesheet_path", &self.config.stylesheet_path);
-                context.insert("timezone", self.display_tz.name());
                 context.insert("page", &page);
-                context.insert("events", events);
-                context.insert("previous_file_name", &previous_file_name);
-                context.insert("next_file_name", &next_file_name);
-                println!("Writing template to file: {:?}", template_out_file);
-                self.render_to("agenda.html", &context, File::create(template_out_file)?)?;
+                context.insert(
+                    "events",
+                    &events
+                        .iter()
+                        .map(|(day, event)| (self.agenda_entry(*day, event, agenda_page), *event))
+                        .collect::<Vec<_>>(),
+                );
+                Ok(context)
+            },
+        )
+    }
+
+    /// Returns the events starting on the days in `days` with their day, in order
+    fn events_of_days(&self, days: impl RangeBounds<Day>) -> Vec<(Day, &Event)> {
+        self.days
+            .range(days)
+            .flat_map(|(day, events)| self.events.resolve(events).map(move |event| (*day, event)))
+            .filter(|(day, event)| event.starts_on(*day, self.display_tz))
+            .collect()
+    }
+
+    /// The context of an event in the agenda, a day with just that event
+    fn agenda_entry(&self, day: Date, event: &Event, from_page: &Path) -> DayContext {
+        let mut entry = DayContext::new(
+            day,
+            vec![event.context(self.display_tz, self.config).with_link(
+                self.event_link(from_page, &day, event),
+                self.event_page_link(from_page, &day, event),
+            )],
+        );
+        entry.holiday = self.holidays.get(&day).cloned();
+        entry
+    }
+
+    /// Renders the pages of a view one period at a time
+    ///
+    /// `contextualize` prepares the view's part of the template context of a period for the page it is written to, the
+    /// page of the period or one of its index pages, which may be in another directory, so links must be made relative
+    /// to that page. The contexts are released before the next period is prepared, so only the contexts of a single
+    /// period are held at once.
+    fn render_periods<T>(
+        &self,
+        view: &str,
+        template_name: &str,
+        pages: &[PeriodPage<T>],
+        contextualize: impl Fn(&T, &Path) -> Result<Context>,
+    ) -> Result<()> {
+        let output_root = PathBuf::from(&self.config.output_dir);
+        for page in pages {
+            let mut context = contextualize(&page.period, &page.page)?;
+            let json_page = if self.config.render_period_json {
+                let json_page = page.page.with_extension("json");
+                let json = serde_json::to_string_pretty(&context.clone().into_json())?;
+                self.write_file(&output_root.join(&json_page), json)?;
+                Some(json_page)
+            } else {
+                None
+            };
+            self.insert_page_context(&mut context, &page.page, page, &json_page);
+            let template_out_file = output_root.join(&page.page);
+            self.record_event_count(&template_out_file, page.events);
+            debug!("Writing template to file: {:?}", template_out_file);
+            self.write_template(template_name, &context, &template_out_file)?;
+            if let Some(legacy_page) = &page.legacy_page {
+                self.write_legacy_redirect(legacy_page, &page.page)?;
+            }
+            if let Some(name) = &page.name {
+                self.record_page(view, name, &page.page);
             }
-        }
 
+            for index_page in &page.index_pages {
+                let index_out_file = output_root.join(index_page);
+                self.record_event_count(&index_out_file, page.events);
+                let mut context = contextualize(&page.period, index_page)?;
+                self.insert_page_context(&mut context, index_page, page, &json_page);
+                context.insert("featured_events", &self.featured_events(index_page));
+                debug!("Writing template to index file: {:?}", index_out_file);
+                self.write_template(template_name, &context, &index_out_file)?;
+            }
+        }
         Ok(())
     }
 
+    /// Inserts the parts of the context every page of a view shares, with links relative to `page`
+    fn insert_page_context<T>(
+        &self,
+        context: &mut Context,
+        page: &Path,
+        period_page: &PeriodPage<T>,
+        json_page: &Option<PathBuf>,
+    ) {
+        context.insert("stylesheet_path", &self.config.stylesheet_path);
+        context.insert("timezone", self.display_tz.name());
+        self.insert_site_links(context, page);
+        insert_navigation(context, page, period_page, json_page);
+    }
+
+    /// Inserts the links from `page` to the views as `view_links` and to the feeds as `feeds` and `feed_links`
+    fn insert_site_links(&self, context: &mut Context, page: &Path) {
+        context.insert("view_links", &self.view_links(page));
+        let feed_slices = if self.config.render_feed {
+            &self.feed_slices[..]
+        } else {
+            &[]
+        };
+        let feeds: Vec<FeedLink> = feed_slices
+            .iter()
+            .map(|slice| FeedLink {
+                title: slice.title.clone(),
+                atom: relative_link(page, &slice.atom_page()),
+                json: relative_link(page, &slice.json_page()),
+            })
+            .collect();
+        context.insert("feeds", &feeds);
+        let feed_links: Vec<AlternateLink> = feed_slices
+            .iter()
+            .flat_map(|slice| {
+                [
+                    (feed::ATOM_CONTENT_TYPE, slice.atom_page()),
+                    (feed::JSON_CONTENT_TYPE, slice.json_page()),
+                ]
+                .map(|(content_type, feed_page)| AlternateLink {
+                    title: slice.title.clone(),
+                    content_type,
+                    href: self.site_link(page, &feed_page),
+                })
+            })
+            .collect();
+        context.insert("feed_links", &feed_links);
+    }
+
+    /// Returns links from `page` to the index page of every rendered view, keyed by view
+    fn view_links(&self, page: &Path) -> BTreeMap<&'static str, String> {
+        [
+            ("year", self.config.render_year),
+            ("month", self.config.render_month),
+            ("week", self.config.render_week),
+            ("day", self.config.render_day),
+            ("agenda", self.config.render_agenda),
+            ("tracks", self.config.render_tracks),
+            ("locations", self.config.render_week_by_location),
+        ]
+        .into_iter()
+        .filter(|(_, rendered)| *rendered)
+        .map(|(view, _)| {
+            let index_page = PathBuf::from(view).join("index.html");
+            (view, relative_link(page, &index_page))
+        })
+        .collect()
+    }
+
     #[must_use]
     pub fn display_tz(&self) -> &Tz {
         self.display_tz
     }
 }
 
-/// Return the range of iso weeks this month covers
-fn iso_weeks_for_month_display(year: &i32, month: &u8) -> Result<Range<u8>> {
-    let first_day = first_sunday_of_view(*year, month_from_u8(*month)?)?;
-    let first_week = first_day.iso_week();
-    let days_in_month = days_in_year_month(*year, month_from_u8(*month)?);
-    let last_day = Date::from_calendar_date(*year, month_from_u8(*month)?, days_in_month)?;
-    let last_week = last_day.iso_week();
-    Ok(first_week..last_week)
+/// A link to a feed in the template context
+#[derive(Debug, Serialize)]
+struct FeedLink {
+    title: String,
+    /// The Atom feed, relative to the page
+    atom: String,
+    /// The JSON feed, relative to the page
+    json: String,
 }
 
-/// Return the first Sunday that should appear in a calendar view, even if that date is in the previous month
-fn first_sunday_of_view(year: Year, month: MonthName) -> Result<Date> {
-    let first_day_of_month = Date::from_calendar_date(year, month, 1)?;
-    let days_from_sunday = first_day_of_month.weekday().number_days_from_sunday();
-    let first_day_of_view = first_day_of_month - (days_from_sunday as i64).days();
-    Ok(first_day_of_view)
+/// An upcoming featured event on an index page
+#[derive(Debug, Serialize)]
+struct FeaturedEvent {
+    event: EventContext,
+    date: String,
+    /// The countdown page, absolute if `site_url` is configured and relative to the page otherwise
+    countdown_link: String,
+    /// The whole days until the event starts, 0 once it has
+    days_left: i64,
 }
 
-/// Return the first Sunday of the week, even if that week is in the previous month
-fn first_sunday_of_week(year: &i32, week: &u8) -> Result<Date, color_eyre::Report> {
-    let first_sunday_of_month = Date::from_iso_week_date(*year, *week, time::Weekday::Sunday)?;
-    let first_sunday_of_view = first_sunday_of_view(*year, first_sunday_of_month.month())?;
-    let sunday =
-        if (first_sunday_of_month.to_julian_day() - first_sunday_of_view.to_julian_day()) >= 7 {
-            first_sunday_of_month
-        } else {
-            first_sunday_of_view
-        };
-    Ok(sunday)
+/// A feed for a `<link rel="alternate">` tag, so browsers and feed readers discover it
+#[derive(Debug, Serialize)]
+struct AlternateLink {
+    title: String,
+    #[serde(rename = "type")]
+    content_type: &'static str,
+    /// The feed, absolute if `site_url` is configured and relative to the page otherwise
+    href: String,
+}
+
+/// A page of a view showing one period, e.g. a month, a day, or a page of the agenda
+struct PeriodPage<T> {
+    /// What the page shows, e.g. the period and its events
+    period: T,
+    /// The page, relative to the output directory
+    page: PathBuf,
+    previous_page: Option<PathBuf>,
+    next_page: Option<PathBuf>,
+    /// The first page of the view, `None` on the first page itself
+    first_page: Option<PathBuf>,
+    /// The last page of the view, `None` on the last page itself
+    last_page: Option<PathBuf>,
+    /// The name the page is recorded under for redirects, see `CalendarCollection::record_page()`
+    name: Option<String>,
+    /// The unpadded page of older builds, see `CalendarCollection::write_legacy_redirect()`
+    legacy_page: Option<PathBuf>,
+    /// Other pages showing the same period, e.g. `month/index.html`
+    index_pages: Vec<PathBuf>,
+    /// The number of events shown on the page
+    events: usize,
+}
+
+impl<T> PeriodPage<T> {
+    /// Links each page to its neighbours, the pages are given in navigation order
+    fn link(pages: Vec<(T, PathBuf)>) -> Vec<PeriodPage<T>> {
+        let paths: Vec<PathBuf> = pages.iter().map(|(_, page)| page.clone()).collect();
+        pages
+            .into_iter()
+            .enumerate()
+            .map(|(index, (period, page))| PeriodPage {
+                period,
+                page,
+                previous_page: index.checked_sub(1).map(|previous| paths[previous].clone()),
+                next_page: paths.get(index + 1).cloned(),
+                first_page: (index > 0).then(|| paths[0].clone()),
+                last_page: (index + 1 < paths.len()).then(|| paths[paths.len() - 1].clone()),
+                name: None,
+                legacy_page: None,
+                index_pages: Vec::new(),
+                events: 0,
+            })
+            .collect()
+    }
+}
+
+/// Adds the periods without events between the first and the last day with events to the maps
+///
+/// Their pages are written with empty event lists, so the navigation between pages steps through every period.
+fn fill_empty_periods(
+    years: &mut YearMap,
+    months: &mut MonthMap,
+    weeks: &mut WeekMap,
+    days: &mut DayMap,
+) {
+    let (Some(&first), Some(&last)) = (days.keys().next(), days.keys().next_back()) else {
+        return;
+    };
+    let mut date = first;
+    while date <= last {
+        years.entry(date.year()).or_default();
+        months.entry((date.year(), date.month() as u8)).or_default();
+        weeks.entry(iso_week(date)).or_default();
+        days.entry(date).or_default();
+        match date.next_day() {
+            Some(next) => date = next,
+            None => break,
+        }
+    }
+}
+
+/// Returns the position of the page an index shows, the one of `current` or else the last one before it
+///
+/// If every page comes after `current`, the index shows the first page.
+fn index_position<K: Ord>(keys: impl Iterator<Item = K>, current: K) -> Option<usize> {
+    let mut position = None;
+    for (index, key) in keys.enumerate() {
+        if position.is_some() && key > current {
+            break;
+        }
+        position = Some(index);
+    }
+    position
+}
+
+/// The name of a calendar for its feed, its `X-WR-CALNAME` or else the file name of its source
+fn calendar_name(calendar: &Calendar) -> String {
+    calendar.name().map(String::from).unwrap_or_else(|| {
+        Path::new(calendar.source())
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| calendar.source().to_string())
+    })
+}
+
+/// The feeds to write, all events first, then each calendar and category
+///
+/// `calendar_names` are the names of the calendars with events keyed by their slug
+fn feed_slices(
+    config: &Config,
+    events: &EventArena,
+    calendar_names: BTreeMap<String, String>,
+) -> Vec<FeedSlice> {
+    let mut slices = vec![FeedSlice {
+        title: config.feed_title.clone(),
+        filter: FeedFilter::All,
+    }];
+    let slice = |name: &str, filter: FeedFilter| FeedSlice {
+        title: format!("{}: {}", config.feed_title, name),
+        filter,
+    };
+    if config.render_changes_feed {
+        slices.push(slice("changes", FeedFilter::Changes));
+    }
+    if config.render_calendar_feeds {
+        slices.extend(
+            calendar_names
+                .into_iter()
+                .filter(|(slug, _)| !slug.is_empty())
+                .map(|(slug, name)| slice(&name, FeedFilter::Calendar(slug))),
+        );
+    }
+    if config.render_category_feeds {
+        let mut categories = BTreeMap::new();
+        for category in events.iter().flat_map(Event::categories) {
+            categories
+                .entry(feed::slug(category))
+                .or_insert_with(|| category.to_string());
+        }
+        slices.extend(
+            categories
+                .into_iter()
+                .filter(|(slug, _)| !slug.is_empty())
+                .map(|(slug, name)| slice(&name, FeedFilter::Category(slug))),
+        );
+    }
+    slices
+}
+
+/// Checks the config values the collection depends on, returning the display timezone
+pub fn validate_config(config: &Config) -> Result<&'static Tz> {
+    let time_zone = time_tz::timezones::get_by_name(&config.display_timezone)
+        .ok_or_else(|| unknown_timezone(&config.display_timezone))?;
+    for blackout in &config.blackouts {
+        blackout.dates()?;
+    }
+    if !config.holidays_country.is_empty() {
+        holidays::check_country(&config.holidays_country)?;
+    }
+    match (config.latitude, config.longitude) {
+        (Some(latitude), Some(longitude)) => {
+            if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                bail!(
+                    "latitude must be within -90 and 90 and longitude within -180 and 180, got {} and {}",
+                    latitude,
+                    longitude
+                );
+            }
+        }
+        (None, None) => {}
+        _ => bail!("sunrise and sunset need both latitude and longitude"),
+    }
+    config.generation_range(OffsetDateTime::now_utc().to_timezone(time_zone).date())?;
+    if config.time_slot_minutes == 0 || !MINUTES_PER_DAY.is_multiple_of(config.time_slot_minutes) {
+        bail!(
+            "time_slot_minutes must evenly divide a day, got {}",
+            config.time_slot_minutes
+        );
+    }
+    config.agenda_start()?;
+    if config.agenda_events_per_page == 0 {
+        bail!("agenda_events_per_page must be at least 1");
+    }
+    Ok(time_zone)
+}
+
+/// The error for a display timezone which is not in the IANA database, listing similar names
+///
+/// Names containing the given name are found with `find_by_name()`, misspelled cities like "Pheonix" by their edit
+/// distance to the last part of each name.
+fn unknown_timezone(name: &str) -> eyre::Report {
+    let city = |name: &str| name.rsplit('/').next().unwrap_or(name).to_lowercase();
+    let wanted = city(name);
+    let max_distance = wanted.chars().count() / 3;
+    let mut suggestions: BTreeSet<&str> = time_tz::timezones::find_by_name(name)
+        .into_iter()
+        .map(|time_zone| time_zone.name())
+        .collect();
+    if !wanted.is_empty() {
+        suggestions.extend(
+            time_tz::timezones::iter()
+                .map(|time_zone| time_zone.name())
+                .filter(|candidate| {
+                    let candidate = city(candidate);
+                    candidate.contains(&wanted)
+                        || edit_distance(&candidate, &wanted) <= max_distance
+                }),
+        );
+    }
+    if suggestions.is_empty() {
+        eyre!(
+            "unknown timezone {:?}, expected an IANA name like \"Europe/Paris\"",
+            name
+        )
+    } else {
+        let suggestions: Vec<_> = suggestions.into_iter().take(10).collect();
+        eyre!(
+            "unknown timezone {:?}, did you mean one of: {}",
+            name,
+            suggestions.join(", ")
+        )
+    }
+}
+
+/// The number of inserted, removed, or replaced characters between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a != *b);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Adds the data of `enricher` for the days from the first to the last one in `days` to `day_extra`
+fn enrich_days(
+    day_extra: &mut BTreeMap<Date, BTreeMap<String, serde_json::Value>>,
+    days: &DayMap,
+    enricher: &dyn DayEnricher,
+) -> Result<()> {
+    let (Some(first), Some(last)) = (days.keys().next(), days.keys().next_back()) else {
+        return Ok(());
+    };
+    for (date, value) in enricher.enrich(*first, *last)? {
+        day_extra
+            .entry(date)
+            .or_default()
+            .insert(enricher.name().to_string(), value);
+    }
+    Ok(())
+}
+
+/// Inserts the previous and next page links, and the link to the JSON data of the page, into a context
+///
+/// All pages are given relative to the output directory, the links are made relative to the page being written.
+fn insert_navigation<T>(
+    context: &mut Context,
+    page: &Path,
+    period_page: &PeriodPage<T>,
+    json_page: &Option<PathBuf>,
+) {
+    let link = |to_page: &Option<PathBuf>| to_page.as_deref().map(|p| relative_link(page, p));
+    context.insert("previous_file_name", &link(&period_page.previous_page));
+    context.insert("next_file_name", &link(&period_page.next_page));
+    context.insert("first_file_name", &link(&period_page.first_page));
+    context.insert("last_file_name", &link(&period_page.last_page));
+    context.insert(
+        "json_file_name",
+        &json_page.as_deref().map(|p| relative_link(page, p)),
+    );
+}
+
+/// Returns a link from one page to another, both relative to the output directory
+fn relative_link(from_page: &Path, to_page: &Path) -> String {
+    let from_dir: Vec<_> = from_page
+        .parent()
+        .map(|dir| dir.components().collect())
+        .unwrap_or_default();
+    let to: Vec<_> = to_page.components().collect();
+    let common = from_dir
+        .iter()
+        .zip(&to)
+        .take_while(|(from, to)| from == to)
+        .count();
+
+    let mut parts = vec!["..".to_string(); from_dir.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy().to_string()),
+    );
+    parts.join("/")
+}
+
+/// Fills in the statistics of a source from the calendars which were read from it
+fn summarize_source(summary: &mut SourceSummary, calendars: &[Calendar], tz: &Tz) {
+    let source_calendars = calendars.iter().filter(|c| c.source() == summary.source);
+
+    let mut first_event: Option<OffsetDateTime> = None;
+    let mut last_event: Option<OffsetDateTime> = None;
+    for calendar in source_calendars {
+        summary.events_parsed += calendar.parsed_events();
+        summary.events_skipped += calendar.warnings().len();
+        summary.recurrences_expanded += calendar.expanded_events();
+        summary.warnings.extend(calendar.warnings().iter().cloned());
+        summary
+            .warnings
+            .extend(calendar.limit_warnings().iter().cloned());
+
+        for event in calendar.events().iter().chain(calendar.recurring_events()) {
+            first_event = Some(first_event.map_or(event.start(), |first| first.min(event.start())));
+            last_event = Some(last_event.map_or(event.end(), |last| last.max(event.end())));
+        }
+    }
+
+    summary.first_event = first_event.map(|start| start.to_timezone(tz).date().to_string());
+    summary.last_event = last_event.map(|end| end.to_timezone(tz).date().to_string());
+    if summary.events_parsed == 0 {
+        summary.warnings.push("no events found".to_string());
+    }
+}
+
+/// Finds the UIDs which several sources publish with different content
+///
+/// Each source is represented by its first event with the UID, preferring the recurring event over its overrides, so
+/// the overrides within a source are not reported.
+fn find_uid_conflicts(calendars: &[Calendar]) -> Vec<UidConflict> {
+    let mut events_by_uid: BTreeMap<&str, BTreeMap<&str, &Event>> = BTreeMap::new();
+    for calendar in calendars {
+        // modified instances share the UID of their recurring event, they are not conflicts
+        let originals = calendar.recurring_events().iter().chain(calendar.events());
+        for event in originals.filter(|event| event.recurrence_id().is_none()) {
+            if let Some(uid) = event.uid().filter(|uid| !uid.is_empty()) {
+                events_by_uid
+                    .entry(uid)
+                    .or_default()
+                    .entry(calendar.source())
+                    .or_insert(event);
+            }
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for (uid, events) in events_by_uid {
+        if events.len() < 2 {
+            continue;
+        }
+        let fields: Vec<_> = events
+            .iter()
+            .map(|(source, event)| (*source, event.content_fields()))
+            .collect();
+        let differences: Vec<FieldDifference> = (0..fields[0].1.len())
+            .filter(|&index| {
+                fields
+                    .iter()
+                    .any(|(_, other)| other[index].1 != fields[0].1[index].1)
+            })
+            .map(|index| FieldDifference {
+                field: fields[0].1[index].0.to_string(),
+                values: fields
+                    .iter()
+                    .map(|(source, values)| (source.to_string(), values[index].1.clone()))
+                    .collect(),
+            })
+            .collect();
+        if !differences.is_empty() {
+            conflicts.push(UidConflict {
+                uid: uid.to_string(),
+                sources: events.keys().map(|source| source.to_string()).collect(),
+                differences,
+            });
+        }
+    }
+    conflicts
+}
+
+/// Returns the ISO week of a date with its ISO week-year, which differs from the calendar year around new year
+fn iso_week(date: Date) -> Week {
+    let (year, week, _) = date.to_iso_week_date();
+    (year, week)
+}
+
+/// Return the Monday starting an ISO week, even if that date is in the previous month or year
+fn first_day_of_week(year: &i32, week: &u8) -> Result<Date> {
+    Ok(Date::from_iso_week_date(
+        *year,
+        *week,
+        time::Weekday::Monday,
+    )?)
 }
 
 /// Generates context objects for the days of a week
 ///
 /// Implementing this as a trait so we can call it on a typedef rather than creating a new struct.
 pub trait WeekContext {
-    fn context(&self, year: &i32, week: &u8, tz: &Tz) -> Result<Vec<DayContext>>;
+    fn context(&self, year: &i32, week: &u8, tz: &Tz, config: &Config) -> Result<Vec<DayContext>>;
 }
 
-impl WeekContext for WeekDayMap {
-    fn context(&self, year: &i32, week: &u8, tz: &Tz) -> Result<Vec<DayContext>> {
-        let sunday = first_sunday_of_week(year, week)?;
+impl WeekContext for WeekDayMap<'_> {
+    fn context(&self, year: &i32, week: &u8, tz: &Tz, config: &Config) -> Result<Vec<DayContext>> {
+        let monday = first_day_of_week(year, week)?;
         let week_dates: Vec<DayContext> = [0_u8, 1_u8, 2_u8, 3_u8, 4_u8, 5_u8, 6_u8]
             .iter()
             .map(|o| {
+                let date = monday + (*o as i64).days();
                 DayContext::new(
-                    sunday + (*o as i64).days(),
+                    date,
                     self.get(o)
-                        .map(|l| l.iter().map(|e| e.context(tz)).collect())
+                        .map(|l| l.iter().map(|e| e.context_on(date, tz, config)).collect())
                         .unwrap_or_default(),
                 )
             })
@@ -699,16 +2859,6 @@ impl WeekContext for WeekDayMap {
     }
 }
 
-/// Generate DayContext Vecs for empty weeks
-fn blank_context(year: &i32, week: &u8) -> Result<Vec<DayContext>> {
-    let sunday = first_sunday_of_week(year, week)?;
-    let week_dates: Vec<DayContext> = [0_u8, 1_u8, 2_u8, 3_u8, 4_u8, 5_u8, 6_u8]
-        .iter()
-        .map(|o| DayContext::new(sunday + (*o as i64).days(), Vec::new()))
-        .collect();
-    Ok(week_dates)
-}
-
 fn month_from_u8(value: u8) -> Result<time::Month> {
     match value {
         1 => Ok(time::Month::January),
@@ -1,15 +1,44 @@
 use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use time::{macros::format_description, Date};
+use time_tz::{OffsetDateTimeExt, Tz};
 
-use super::event::EventContext;
+use super::event::{Event, EventContext};
+use crate::config::{Config, Language};
+use crate::moon::MoonPhase;
 
-#[derive(Debug, Serialize)]
+/// The sections of a split day: their id, their names in English and French, and the hour they start at
+const SECTIONS: [(&str, &str, &str, u8); 3] = [
+    ("morning", "Morning", "Matin", 0),
+    ("afternoon", "Afternoon", "Après-midi", 12),
+    ("evening", "Evening", "Soir", 17),
+];
+
+#[derive(Debug, Clone, Serialize)]
 pub struct DayContext {
     pub(crate) date: String,
     pub(crate) day: u8,
     pub(crate) wday: String,
     pub(crate) month: String,
     pub(crate) events: Vec<EventContext>,
+    /// The rendered notes of the day, see `Config::notes_dir`
+    pub(crate) notes: Option<String>,
+    /// The name of the public holiday on the day, see `Config::holidays_country`
+    pub(crate) holiday: Option<String>,
+    /// The data attached by the day enrichers, keyed by their name, see `Config::day_enrichments`
+    pub(crate) extra: BTreeMap<String, Value>,
+    /// When the sun rises and sets at `Config::latitude` and `Config::longitude`, e.g. "07:42"
+    pub(crate) sunrise: Option<String>,
+    pub(crate) sunset: Option<String>,
+    /// The phase of the moon, see `Config::moon_phases`
+    pub(crate) moon_phase: Option<MoonPhase>,
+    /// The ISO week number of the row of a month grid, the week of its Monday
+    pub(crate) week_number: Option<u8>,
+    /// Whether the day belongs to the month before or after the one of the page, to fill its first and last week
+    pub(crate) adjacent_month: bool,
+    #[serde(skip)]
+    pub(crate) calendar_date: Date,
 }
 
 impl DayContext {
@@ -22,6 +51,155 @@ impl DayContext {
             month: date.month().to_string(),
             wday: date.weekday().to_string(),
             events,
+            notes: None,
+            holiday: None,
+            extra: BTreeMap::new(),
+            sunrise: None,
+            sunset: None,
+            moon_phase: None,
+            week_number: None,
+            adjacent_month: false,
+            calendar_date: date,
+        }
+    }
+}
+
+/// A column of the tracks view, see `Config::track_categories`
+#[derive(Debug, Serialize)]
+pub struct TrackContext {
+    pub(crate) name: String,
+    pub(crate) events: Vec<EventContext>,
+}
+
+impl TrackContext {
+    /// Assigns the events of `day` to tracks, leaving out tracks without events
+    ///
+    /// The configured tracks come first in their order, then other categories by name, and the default track last.
+    pub fn assign<'e>(
+        day: Date,
+        events: impl Iterator<Item = &'e Event>,
+        tz: &Tz,
+        config: &Config,
+    ) -> Vec<TrackContext> {
+        let mut tracks: Vec<(Option<&str>, Vec<&Event>)> = config
+            .track_categories
+            .iter()
+            .map(|track| (Some(track.as_str()), Vec::new()))
+            .collect();
+        let mut default_track = Vec::new();
+        for event in events {
+            match event.track(&config.track_categories) {
+                Some(name) => match tracks.iter_mut().find(|(track, _)| *track == Some(name)) {
+                    Some((_, track_events)) => track_events.push(event),
+                    None => tracks.push((Some(name), vec![event])),
+                },
+                None => default_track.push(event),
+            }
+        }
+        if config.track_categories.is_empty() {
+            tracks.sort_by_key(|(name, _)| *name);
+        }
+        tracks.push((None, default_track));
+        tracks.retain(|(_, events)| !events.is_empty());
+
+        tracks
+            .into_iter()
+            .enumerate()
+            .map(|(index, (name, events))| TrackContext {
+                name: name.unwrap_or(&config.default_track).to_string(),
+                events: events
+                    .into_iter()
+                    .map(|event| event.context_on(day, tz, config).with_track_index(index))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// The events of a day at one location, see `Config::render_week_by_location`
+#[derive(Debug, Serialize)]
+pub struct LocationGroup {
+    pub(crate) name: String,
+    pub(crate) events: Vec<EventContext>,
+}
+
+/// A day of the week by location pages, with its events grouped by location instead of in one list
+#[derive(Debug, Serialize)]
+pub struct LocationDayContext {
+    #[serde(flatten)]
+    pub(crate) day: DayContext,
+    pub(crate) locations: Vec<LocationGroup>,
+}
+
+impl LocationDayContext {
+    /// Moves the events of `day` into groups by location, ordered by name with events without a location last
+    pub fn group(mut day: DayContext, config: &Config) -> LocationDayContext {
+        let mut locations: Vec<(Option<String>, Vec<EventContext>)> = Vec::new();
+        for event in std::mem::take(&mut day.events) {
+            let location = event.location().map(str::to_string);
+            match locations.iter_mut().find(|(name, _)| *name == location) {
+                Some((_, events)) => events.push(event),
+                None => locations.push((location, vec![event])),
+            }
+        }
+        // `None` sorts first, so move it to the end
+        locations.sort_by(|(a, _), (b, _)| a.is_none().cmp(&b.is_none()).then(a.cmp(b)));
+
+        LocationDayContext {
+            day,
+            locations: locations
+                .into_iter()
+                .map(|(name, events)| LocationGroup {
+                    name: name.unwrap_or_else(|| config.default_location.clone()),
+                    events,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A part of a busy day, see `Config::day_split_threshold`
+#[derive(Debug, Serialize)]
+pub struct DaySection {
+    /// Identifies the section within the page, e.g. "morning"
+    pub(crate) id: &'static str,
+    pub(crate) name: &'static str,
+    pub(crate) events: Vec<EventContext>,
+}
+
+impl DaySection {
+    /// Groups the events of `day` into morning, afternoon, and evening by their start, leaving out empty sections
+    ///
+    /// Events which started on an earlier day are part of the morning.
+    pub fn split<'e>(
+        day: Date,
+        events: impl Iterator<Item = &'e Event>,
+        tz: &Tz,
+        config: &Config,
+    ) -> Vec<DaySection> {
+        let mut sections: Vec<DaySection> = SECTIONS
+            .iter()
+            .map(|(id, english, french, _)| DaySection {
+                id,
+                name: match config.language {
+                    Language::En => english,
+                    Language::Fr => french,
+                },
+                events: Vec::new(),
+            })
+            .collect();
+        for event in events {
+            let start = event.start().to_timezone(tz);
+            let hour = if start.date() < day { 0 } else { start.hour() };
+            let section = SECTIONS
+                .iter()
+                .rposition(|(_, _, _, first_hour)| hour >= *first_hour)
+                .unwrap_or_default();
+            sections[section]
+                .events
+                .push(event.context_on(day, tz, config));
         }
+        sections.retain(|section| !section.events.is_empty());
+        sections
     }
 }
@@ -1,42 +1,312 @@
 use color_eyre::eyre::{bail, ContextCompat, Result, WrapErr};
-use ical::parser::ical::component::IcalEvent;
 use regex::Regex;
 use rrule::RRule;
 use serde::Serialize;
-use std::{collections::HashSet, fmt};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::PathBuf,
+    rc::Rc,
+};
 use time::{
     macros::{format_description, offset},
-    Duration, OffsetDateTime, PrimitiveDateTime,
+    Date, Duration, OffsetDateTime, PrimitiveDateTime,
 };
-use time_tz::{timezones::get_by_name, OffsetDateTimeExt, PrimitiveDateTimeExt, Tz};
+use time_tz::{timezones::get_by_name, OffsetDateTimeExt, Tz};
+use tracing::{trace, warn};
 
-const MISSING_SUMMARY: &str = "None";
+use super::arena::{IStr, StringInterner};
+use super::ics::{unescape_text, Component, Property};
+use super::recurrence;
+use super::timezone::{iana_name, start_of_day, TimeZones};
+use crate::config::{
+    Config, DeadlineRule, FieldKind, FieldRule, Language, MapLinks, StyleRule, MINUTES_PER_DAY,
+};
+use crate::feed::slug;
 
 pub type Year = i32;
 pub type WeekNum = u8;
 
 pub type UnparsedProperties = HashSet<String>;
 
+/// The fields which identify an event instance for de-duplication: UID, start, end, and summary
+pub type EventIdentity = (Option<IStr>, OffsetDateTime, OffsetDateTime, IStr);
+
+/// Normalized availability of the attendee during an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BusyStatus {
+    Free,
+    Tentative,
+    #[default]
+    Busy,
+    /// Out of office
+    Oof,
+}
+
+impl BusyStatus {
+    /// Parses the values used by `X-MICROSOFT-CDO-BUSYSTATUS` and `X-MICROSOFT-CDO-INTENDEDSTATUS`
+    fn from_microsoft(value: &str) -> Option<BusyStatus> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "FREE" | "WORKINGELSEWHERE" => Some(BusyStatus::Free),
+            "TENTATIVE" => Some(BusyStatus::Tentative),
+            "BUSY" => Some(BusyStatus::Busy),
+            "OOF" => Some(BusyStatus::Oof),
+            _ => None,
+        }
+    }
+
+    /// Parses the standard `TRANSP` property, transparent events do not block time
+    fn from_transparency(value: &str) -> Option<BusyStatus> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "TRANSPARENT" => Some(BusyStatus::Free),
+            "OPAQUE" => Some(BusyStatus::Busy),
+            _ => None,
+        }
+    }
+}
+
+/// The reply of an attendee to an invitation (`PARTSTAT`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParticipationStatus {
+    /// Not answered yet
+    #[default]
+    NeedsAction,
+    Accepted,
+    Declined,
+    Tentative,
+    Delegated,
+}
+
+impl ParticipationStatus {
+    fn parse(value: &str) -> Option<ParticipationStatus> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "NEEDS-ACTION" => Some(ParticipationStatus::NeedsAction),
+            "ACCEPTED" => Some(ParticipationStatus::Accepted),
+            "DECLINED" => Some(ParticipationStatus::Declined),
+            "TENTATIVE" => Some(ParticipationStatus::Tentative),
+            "DELEGATED" => Some(ParticipationStatus::Delegated),
+            _ => None,
+        }
+    }
+}
+
+/// Whether an event takes place (`STATUS`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventStatus {
+    Tentative,
+    #[default]
+    Confirmed,
+    Cancelled,
+}
+
+impl EventStatus {
+    fn parse(value: &str) -> Option<EventStatus> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "TENTATIVE" => Some(EventStatus::Tentative),
+            "CONFIRMED" => Some(EventStatus::Confirmed),
+            "CANCELLED" => Some(EventStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// A single event or event instance
+///
+/// The text fields are interned, so the instances of a recurring event (and events repeating the same location or
+/// categories) share them instead of holding their own copies.
 #[derive(Debug, Serialize)]
 pub struct Event {
-    summary: Option<String>,
-    description: Option<String>,
+    uid: Option<IStr>,
+    summary: IStr,
+    description: Option<IStr>,
     start: OffsetDateTime,
     duration: Duration,
-    rrule: Option<String>,
-    location: Option<String>,
-    url: Option<String>,
+    rrule: Option<IStr>,
+    /// The IANA time zone of `DTSTART`, the `RRULE` is expanded in it so that occurrences keep their local time and
+    /// weekday across changes to summer time
+    time_zone: Option<IStr>,
+    /// Further occurrences besides those of the `RRULE` (`RDATE`)
+    extra_dates: Rc<[OffsetDateTime]>,
+    /// Occurrences which were cancelled (`EXDATE`)
+    excluded_dates: Rc<[OffsetDateTime]>,
+    location: Option<IStr>,
+    /// The latitude and longitude of the venue (`GEO`)
+    geo: Option<(f64, f64)>,
+    /// The name of the organizer, or their address if the calendar gives no name
+    organizer: Option<IStr>,
+    url: Option<IStr>,
+    /// The price of admission (`X-COST`), e.g. "$10, free for members"
+    price: Option<IStr>,
+    /// Where to buy tickets (`X-TICKET-URL`)
+    ticket_url: Option<IStr>,
+    categories: Rc<[IStr]>,
+    /// The `X-` properties which are not interpreted, for `Config::field_rules`
+    extra_properties: Rc<[(IStr, IStr)]>,
+    busy_status: BusyStatus,
+    /// Whether the event takes place, events without a `STATUS` are confirmed
+    status: EventStatus,
+    /// The addresses of the attendees, lower case and without `mailto:`, with their replies
+    attendees: Rc<[(IStr, ParticipationStatus)]>,
+    /// When the event was last changed in the source calendar (`LAST-MODIFIED`, or `CREATED` if it never changed)
+    last_modified: Option<OffsetDateTime>,
+    /// The revision of the event, incremented by the organizer on significant changes
+    sequence: u32,
+    /// Where the event came from if it is an instance of a recurring event
+    recurrence: Option<Recurrence>,
+    /// The original start of the occurrence this event replaces, if it is a modified instance (`RECURRENCE-ID`)
+    recurrence_id: Option<OffsetDateTime>,
+    /// The name of the blackout the event falls into, see `Config::blackouts`
+    suspended_by: Option<IStr>,
+    /// Whether the event lasts whole days, from a `DTSTART` with a date and no time
+    all_day: bool,
 }
 
-#[derive(Debug, Serialize)]
+/// The recurrence an expanded event instance belongs to
+#[derive(Debug, Clone, Serialize)]
+pub struct Recurrence {
+    /// The `RRULE` of the recurring event
+    rule: IStr,
+    /// The start of the first occurrence, which the rule counts from
+    #[serde(skip)]
+    series_start: OffsetDateTime,
+    /// The time zone the rule is expanded in, see `Event::time_zone`
+    time_zone: Option<IStr>,
+    /// The position of the instance among the occurrences, 0 for the first one
+    index: u32,
+    /// The first occurrence after the build, see `Calendar::mark_next_occurrences()`
+    #[serde(skip)]
+    next_occurrence: Option<OffsetDateTime>,
+}
+
+impl Recurrence {
+    /// The start of the first occurrence, which identifies the series with the UID
+    pub fn series_start(&self) -> OffsetDateTime {
+        self.series_start
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct EventContext {
+    /// The instance id, see `Event::instance_id()`
+    id: String,
+    uid: Option<String>,
+    /// The `id` attribute of the event within a page, see `Event::anchor()`
+    anchor: String,
+    /// The link to the event, see `Config::event_links`, only set by views which link to other pages
+    link: Option<String>,
+    /// A link to the event on its day page, set along with `link`
+    page_link: Option<String>,
+    /// A link to the detail page of the event, only set by views which link to it
+    detail_link: Option<String>,
+    /// A link to the page of the series the event belongs to, only set by views which link to it
+    series_link: Option<String>,
+    location: Option<String>,
+    /// The position of the venue, see `Event::geo()`
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    /// A link to a map of the venue, see `Config::map_links`
+    map_link: Option<String>,
+    organizer: Option<String>,
+    /// The `URL` of the event, e.g. a ticketing page
+    url: Option<String>,
+    /// The price of admission, see `Event::price()`
+    price: Option<String>,
+    /// Where to buy tickets, see `Event::ticket_url()`
+    ticket_url: Option<String>,
+    /// How many people the event admits, see `Event::capacity()`
+    capacity: Option<u32>,
+    /// How many places are still free, see `Event::spots_left()`
+    spots_left: Option<u32>,
+    /// Whether no places are left, see `Event::is_sold_out()`
+    sold_out: bool,
+    /// The fields found by `Config::field_rules`, e.g. `{"audience": ["families"], "age_limit": 18}`
+    fields: BTreeMap<String, Value>,
     summary: String,
     description: String,
     start: String,
     start_timestamp: i64,
     end: String,
     end_timestamp: i64,
+    /// The length of the event, e.g. "1h30m"
     duration: String,
+    /// The length of the event in minutes, for templates formatting it themselves
+    duration_minutes: i64,
+    /// Whether the event lasts whole days, templates show no times for it
+    is_all_day: bool,
+    /// Whether the event starts on the day it is shown on, false for the later days of a multi-day event
+    is_start: bool,
+    /// Whether the event ends on the day it is shown on, false for all but the last day of a multi-day event
+    is_end: bool,
+    slot_start: u16,
+    slot_end: u16,
+    color: Option<String>,
+    icon: Option<String>,
+    busy_status: BusyStatus,
+    /// The categories in the order of the calendar, e.g. `["Music", "Outdoor"]`
+    categories: Vec<String>,
+    /// "confirmed", "tentative", or "cancelled", see `Config::hide_cancelled_events`
+    status: EventStatus,
+    /// Whether the event falls into a blackout and does not take place
+    suspended: bool,
+    /// The name of the blackout, e.g. "Winter break"
+    suspended_by: Option<String>,
+    /// The reply of the configured attendee, see `Config::attendee_email`
+    participation: Option<ParticipationStatus>,
+    /// Whether the event repeats, either as a recurring event or as one of its instances
+    is_recurring: bool,
+    /// The recurrence rule in prose, e.g. "Every 2 weeks on Tuesday"
+    recurrence: Option<String>,
+    /// The position of the instance among the occurrences of its recurring event, 0 for the first one
+    recurrence_index: Option<u32>,
+    /// The track of the event in the tracks view, `None` for the default track
+    track: Option<String>,
+    /// The column of the track within the day, only set by the tracks view
+    track_index: Option<usize>,
+    /// The next occurrence after the build, e.g. "Tue 14 May, 19:00"
+    next_occurrence: Option<String>,
+    next_occurrence_timestamp: Option<i64>,
+}
+
+impl EventContext {
+    /// Sets the link to the event and the link to its day page
+    pub fn with_link(mut self, link: String, page_link: String) -> Self {
+        self.link = Some(link);
+        self.page_link = Some(page_link);
+        self
+    }
+
+    /// Returns the path of the detail page of the event, see `Event::detail_page_path()`
+    pub fn detail_page_path(&self) -> PathBuf {
+        event_page_path("event", &self.summary, &self.id)
+    }
+
+    /// Sets the link to the detail page of the event
+    pub fn set_detail_link(&mut self, detail_link: String) {
+        self.detail_link = Some(detail_link);
+    }
+
+    pub fn uid(&self) -> Option<&str> {
+        self.uid.as_deref()
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// Sets the link to the series page of the event
+    pub fn set_series_link(&mut self, series_link: String) {
+        self.series_link = Some(series_link);
+    }
+
+    /// Sets the column of the event's track
+    pub fn with_track_index(mut self, track_index: usize) -> Self {
+        self.track_index = Some(track_index);
+        self
+    }
 }
 
 impl fmt::Display for Event {
@@ -44,7 +314,7 @@ impl fmt::Display for Event {
         write!(
             f,
             "{} ({} to {} for {})\n{}",
-            self.summary.as_ref().unwrap_or(&"NO SUMMARY".to_string()),
+            self.summary,
             self.start
                 .format(format_description!(
                     "[weekday] [month repr:long] [day], [year] at [hour repr:12]:[minute][period case:lower]"
@@ -52,45 +322,473 @@ impl fmt::Display for Event {
                 .expect("could not format start time"),
             self.end().format(format_description!("[hour repr:12]:[minute][period case:lower]")).expect("could not format end time"),
             self.duration,
-            self.description
-                .as_ref()
-                .unwrap_or(&"NO DESCRIPTION".to_string())
+            self.description.as_deref().unwrap_or("NO DESCRIPTION")
         )
     }
 }
 
 impl Event {
     /// Returns and EventContext suitable for providing values to Tera templates
-    pub fn context(&self, tz: &Tz) -> EventContext {
+    pub fn context(&self, tz: &Tz, config: &Config) -> EventContext {
+        let (slot_start, slot_end) = self.slots(tz, config.time_slot_minutes);
+        let (color, icon) = self.style(&config.style_rules);
+        let next_occurrence = self
+            .recurrence
+            .as_ref()
+            .and_then(|recurrence| recurrence.next_occurrence)
+            .map(|next| next.to_timezone(tz));
         EventContext {
+            id: self.instance_id(),
+            uid: self.uid().map(String::from),
+            anchor: self.anchor(),
+            link: None,
+            page_link: None,
+            detail_link: None,
+            series_link: None,
+            location: self.location().map(String::from),
+            latitude: self.geo.map(|(latitude, _)| latitude),
+            longitude: self.geo.map(|(_, longitude)| longitude),
+            map_link: self.map_link(config),
+            organizer: self.organizer().map(String::from),
+            url: self.url().map(String::from),
+            price: self.price(config.price_regex.as_ref()),
+            ticket_url: self.ticket_url(config.ticket_url_regex.as_ref()),
+            capacity: self.capacity(config),
+            spots_left: self.spots_left(config),
+            sold_out: self.is_sold_out(config),
+            fields: self.fields(&config.field_rules),
             summary: self.summary().into(),
             description: self
                 .description
                 .as_deref()
                 .unwrap_or("NO DESCRIPTION")
                 .into(),
-            start: self
-                .start()
-                .to_timezone(tz)
-                .format(format_description!(
-                    "[hour repr:12 padding:none]:[minute][period case:lower]"
-                ))
-                .unwrap_or_else(|_| "NO START TIME".to_string()),
+            start: clock_time(self.start(), tz).unwrap_or_else(|| "NO START TIME".to_string()),
             start_timestamp: self.start().to_timezone(tz).unix_timestamp(),
-            end: self
-                .end()
-                .to_timezone(tz)
-                .format(format_description!(
-                    "[hour repr:12 padding:none]:[minute][period case:lower]"
-                ))
-                .unwrap_or_else(|_| "NO END TIME".to_string()),
+            end: clock_time(self.end(), tz).unwrap_or_else(|| "NO END TIME".to_string()),
             end_timestamp: self.end().to_timezone(tz).unix_timestamp(),
             duration: self.duration.to_string(),
+            duration_minutes: self.duration.whole_minutes(),
+            is_all_day: self.all_day,
+            is_start: true,
+            is_end: true,
+            slot_start,
+            slot_end,
+            color: color.map(String::from),
+            icon: icon.map(String::from),
+            busy_status: self.busy_status,
+            status: self.status,
+            categories: self.categories.iter().map(|c| c.to_string()).collect(),
+            suspended: self.suspended_by.is_some(),
+            suspended_by: self.suspended_by.as_deref().map(String::from),
+            participation: self.participation(&config.attendee_email),
+            is_recurring: self.is_recurring(),
+            recurrence: self.recurrence_summary(tz, config.language),
+            recurrence_index: self.recurrence_index(),
+            track: self.track(&config.track_categories).map(String::from),
+            track_index: None,
+            next_occurrence: next_occurrence.and_then(|next| {
+                next.format(format_description!(
+                    "[weekday repr:short] [day padding:none] [month repr:short], [hour]:[minute]"
+                ))
+                .ok()
+            }),
+            next_occurrence_timestamp: next_occurrence.map(|next| next.unix_timestamp()),
         }
     }
 
+    /// Returns the context of the event as shown on `day`, one of the days it spans
+    ///
+    /// An event crossing midnight is split into a segment per day: the start, the end, and the time slots are clamped
+    /// to the part of the event on `day`, while the timestamps and the duration stay those of the whole event.
+    pub fn context_on(&self, day: Date, tz: &Tz, config: &Config) -> EventContext {
+        let context = self.context(tz, config);
+        let (Ok(day_start), Ok(day_end)) = (
+            start_of_day(day, tz),
+            start_of_day(day.next_day().unwrap_or(day), tz),
+        ) else {
+            // a day which cannot be placed in `tz` shows the whole event
+            return context;
+        };
+        let segment_start = self.start().max(day_start);
+        let segment_end = self.end().min(day_end);
+        if segment_start >= segment_end {
+            // an instant, or a day the event does not touch
+            return context;
+        }
+
+        let slot_minutes = config.time_slot_minutes;
+        let minutes_of_day = |time: OffsetDateTime| {
+            let local = time.to_timezone(tz);
+            local.hour() as u16 * 60 + local.minute() as u16
+        };
+        let slot_start = minutes_of_day(segment_start) / slot_minutes;
+        let slot_end = if segment_end == day_end {
+            config.slots_per_day()
+        } else {
+            minutes_of_day(segment_end).div_ceil(slot_minutes)
+        };
+        EventContext {
+            start: clock_time(segment_start, tz).unwrap_or(context.start),
+            end: clock_time(segment_end, tz).unwrap_or(context.end),
+            is_start: segment_start == self.start(),
+            is_end: segment_end == self.end(),
+            slot_start,
+            slot_end: slot_end.max(slot_start + 1),
+            ..context
+        }
+    }
+
+    /// Returns the color and icon of the first matching style rules which set them
+    ///
+    /// Color and icon are looked up separately, so one rule can provide the color and a later one the icon. A later
+    /// rule never overrides what an earlier matching rule set.
+    pub fn style<'r>(&self, rules: &'r [StyleRule]) -> (Option<&'r str>, Option<&'r str>) {
+        let mut matching = rules.iter().filter(|rule| self.matches_style(rule));
+        let mut color = None;
+        let mut icon = None;
+        while let Some(rule) = matching
+            .next()
+            .filter(|_| color.is_none() || icon.is_none())
+        {
+            color = color.or(rule.color.as_deref());
+            icon = icon.or(rule.icon.as_deref());
+        }
+        (color, icon)
+    }
+
+    fn matches_style(&self, rule: &StyleRule) -> bool {
+        self.matches(rule.category.as_deref(), rule.summary_regex.as_ref())
+    }
+
+    /// Returns whether the event has `category` (case insensitive) and its summary matches `summary_regex`, a missing
+    /// condition matches every event
+    fn matches(&self, category: Option<&str>, summary_regex: Option<&Regex>) -> bool {
+        let category_matches = category.is_none_or(|category| {
+            self.categories
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(category))
+        });
+        let summary_matches = summary_regex.is_none_or(|regex| regex.is_match(self.summary()));
+        category_matches && summary_matches
+    }
+
+    pub fn categories(&self) -> &[IStr] {
+        self.categories.as_ref()
+    }
+
+    /// Returns the track of the event: the first of `tracks` it has as a category, or its first category if `tracks`
+    /// is empty
+    ///
+    /// Categories are compared case insensitively, the name is returned as given in `tracks`.
+    pub fn track<'t>(&'t self, tracks: &'t [String]) -> Option<&'t str> {
+        if tracks.is_empty() {
+            return self.categories.first().map(|category| &**category);
+        }
+        tracks
+            .iter()
+            .find(|track| {
+                self.categories
+                    .iter()
+                    .any(|category| category.eq_ignore_ascii_case(track))
+            })
+            .map(String::as_str)
+    }
+
+    pub fn busy_status(&self) -> BusyStatus {
+        self.busy_status
+    }
+
+    pub fn status(&self) -> EventStatus {
+        self.status
+    }
+
+    /// Returns the name of the blackout the event falls into, if any
+    pub fn suspended_by(&self) -> Option<&str> {
+        self.suspended_by.as_deref()
+    }
+
+    /// Marks the event as not taking place because of a blackout
+    pub fn suspend(&mut self, blackout: IStr) {
+        self.suspended_by = Some(blackout);
+    }
+
+    /// Returns the reply of the attendee with the given address, `None` if they are not invited
+    pub fn participation(&self, address: &str) -> Option<ParticipationStatus> {
+        let address = address.trim().trim_start_matches("mailto:");
+        self.attendees
+            .iter()
+            .find(|(attendee, _)| attendee.eq_ignore_ascii_case(address))
+            .map(|(_, status)| *status)
+    }
+
+    /// Returns the grid slots (start inclusive, end exclusive) this event covers on its start day
+    ///
+    /// Events continuing past midnight fill the remaining slots of the day and every event covers at least one slot.
+    pub fn slots(&self, tz: &Tz, slot_minutes: u16) -> (u16, u16) {
+        let local_start = self.start().to_timezone(tz);
+        let local_end = self.end().to_timezone(tz);
+        let minutes_of_day = |t: OffsetDateTime| t.hour() as u16 * 60 + t.minute() as u16;
+
+        let slot_start = minutes_of_day(local_start) / slot_minutes;
+        let slot_end = if local_end.date() > local_start.date() {
+            MINUTES_PER_DAY / slot_minutes
+        } else {
+            minutes_of_day(local_end).div_ceil(slot_minutes)
+        };
+        (slot_start, slot_end.max(slot_start + 1))
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Returns the latitude and longitude of the venue
+    pub fn geo(&self) -> Option<(f64, f64)> {
+        self.geo
+    }
+
+    /// Returns the link to a map of the venue configured by `Config::map_links`, if the event has a location or position
+    pub fn map_link(&self, config: &Config) -> Option<String> {
+        let location = self.location().map(str::trim).filter(|l| !l.is_empty());
+        let link = match (config.map_links, self.geo, location) {
+            (MapLinks::None, _, _) | (_, None, None) => return None,
+            (MapLinks::OpenStreetMap, Some(_), _) => {
+                "https://www.openstreetmap.org/?mlat={latitude}&mlon={longitude}#map=17/{latitude}/{longitude}"
+            }
+            (MapLinks::OpenStreetMap, None, Some(_)) => {
+                "https://www.openstreetmap.org/search?query={location}"
+            }
+            (MapLinks::Google, Some(_), _) => {
+                "https://www.google.com/maps/search/?api=1&query={latitude},{longitude}"
+            }
+            (MapLinks::Google, None, Some(_)) => {
+                "https://www.google.com/maps/search/?api=1&query={location}"
+            }
+            (MapLinks::Custom, _, _) => config.map_link_template.as_str(),
+        };
+        let mut link = link.to_string();
+        let values = [
+            (
+                "{latitude}",
+                self.geo.map(|(latitude, _)| latitude.to_string()),
+            ),
+            (
+                "{longitude}",
+                self.geo.map(|(_, longitude)| longitude.to_string()),
+            ),
+            ("{location}", location.map(encode_query)),
+        ];
+        for (placeholder, value) in values {
+            if link.contains(placeholder) {
+                link = link.replace(placeholder, &value?);
+            }
+        }
+        Some(link).filter(|link| !link.is_empty())
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    pub fn organizer(&self) -> Option<&str> {
+        self.organizer.as_deref()
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Returns the price from `X-COST`, or else the part of the description matching `regex`
+    pub fn price(&self, regex: Option<&Regex>) -> Option<String> {
+        self.price
+            .as_deref()
+            .map(String::from)
+            .or_else(|| self.find_in_description(regex))
+    }
+
+    /// Returns the ticket link from `X-TICKET-URL`, or else the part of the description matching `regex`
+    pub fn ticket_url(&self, regex: Option<&Regex>) -> Option<String> {
+        self.ticket_url
+            .as_deref()
+            .map(String::from)
+            .or_else(|| self.find_in_description(regex))
+    }
+
+    /// Returns how many people the event admits, from `Config::capacity_property` or `Config::capacity_regex`
+    pub fn capacity(&self, config: &Config) -> Option<u32> {
+        self.extra_property(&config.capacity_property)
+            .or_else(|| self.find_in_description(config.capacity_regex.as_ref()))
+            .and_then(|text| first_count(&text))
+    }
+
+    /// Returns how many places are still free, from `Config::spots_left_property` or `Config::spots_left_regex`
+    pub fn spots_left(&self, config: &Config) -> Option<u32> {
+        self.extra_property(&config.spots_left_property)
+            .or_else(|| self.find_in_description(config.spots_left_regex.as_ref()))
+            .and_then(|text| first_count(&text))
+    }
+
+    /// Whether no places are left, or the description says so, see `Config::sold_out_regex`
+    pub fn is_sold_out(&self, config: &Config) -> bool {
+        self.spots_left(config) == Some(0)
+            || config
+                .sold_out_regex
+                .as_ref()
+                .zip(self.description())
+                .is_some_and(|(regex, description)| regex.is_match(description))
+    }
+
+    /// Returns the value of an `X-` property, e.g. `X-CAPACITY`
+    fn extra_property(&self, name: &str) -> Option<String> {
+        self.extra_properties
+            .iter()
+            .find(|(property, _)| property.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_string())
+    }
+
+    /// Returns the fields found by `rules`, the first rule finding a value for a field wins
+    pub fn fields(&self, rules: &[FieldRule]) -> BTreeMap<String, Value> {
+        let mut fields = BTreeMap::new();
+        for rule in rules {
+            if fields.contains_key(&rule.field) {
+                continue;
+            }
+            let text = rule
+                .property
+                .as_deref()
+                .and_then(|name| self.extra_property(name))
+                .or_else(|| self.find_in_description(rule.description_regex.as_ref()));
+            if let Some(value) = text.and_then(|text| field_value(&text, rule.kind)) {
+                fields.insert(rule.field.clone(), value);
+            }
+        }
+        fields
+    }
+
+    /// Returns the first capture group of `regex` in the description, or the whole match if it has no groups
+    fn find_in_description(&self, regex: Option<&Regex>) -> Option<String> {
+        let captures = regex?.captures(self.description()?)?;
+        let found = captures.get(1).or_else(|| captures.get(0))?.as_str().trim();
+        (!found.is_empty()).then(|| found.to_string())
+    }
+
+    pub fn last_modified(&self) -> Option<OffsetDateTime> {
+        self.last_modified
+    }
+
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub fn uid(&self) -> Option<&str> {
+        self.uid.as_deref()
+    }
+
+    /// Returns a rough estimate of the bytes used by the event and its template context
+    pub fn estimated_memory(&self) -> usize {
+        // the context holds the text fields again, along with formatted dates, links, and other short strings
+        let text = self.summary.len()
+            + self.description().map_or(0, str::len)
+            + self.location().map_or(0, str::len)
+            + self.url().map_or(0, str::len)
+            + self.price.as_deref().map_or(0, str::len)
+            + self.ticket_url.as_deref().map_or(0, str::len)
+            + self
+                .extra_properties
+                .iter()
+                .map(|(name, value)| name.len() + value.len())
+                .sum::<usize>();
+        std::mem::size_of::<Event>() + std::mem::size_of::<EventContext>() + 2 * text + 256
+    }
+
+    /// Returns the fields which should agree when two sources publish the same UID, with their values as text
+    pub fn content_fields(&self) -> [(&'static str, String); 6] {
+        [
+            ("summary", self.summary.to_string()),
+            ("start", self.start.to_string()),
+            ("end", self.end().to_string()),
+            ("location", self.location().unwrap_or_default().to_string()),
+            (
+                "description",
+                self.description().unwrap_or_default().to_string(),
+            ),
+            (
+                "rrule",
+                self.rrule.as_deref().unwrap_or_default().to_string(),
+            ),
+        ]
+    }
+
+    /// Returns an identifier for this event instance which stays the same across rebuilds
+    ///
+    /// It is derived from the UID and the start of the instance, so each occurrence of a recurring event gets its own
+    /// identifier while edits to the summary or description keep it. Events without a UID fall back to the summary.
+    pub fn instance_id(&self) -> String {
+        let key = format!(
+            "{}\u{1f}{}",
+            self.uid.as_deref().unwrap_or(self.summary()),
+            self.start.unix_timestamp()
+        );
+        format!("{:016x}", stable_hash(key.as_bytes()))
+    }
+
+    /// Returns the fragment identifying the event within a page, e.g. for `day/2024-05-14.html#event-…` links
+    pub fn anchor(&self) -> String {
+        format!("event-{}", self.instance_id())
+    }
+
+    /// Returns the path of the detail page of the event instance, e.g. `event/board-meeting-0123456789abcdef.html`
+    pub fn detail_page_path(&self) -> PathBuf {
+        event_page_path("event", self.summary(), &self.instance_id())
+    }
+
+    /// Returns the path of the page of the series the event belongs to, e.g. `series/board-meeting-0123456789abcdef.html`
+    ///
+    /// The id is derived from the UID alone, so every instance of the series leads to the same page. Events without a
+    /// UID are not part of a series.
+    pub fn series_page_path(&self) -> Option<PathBuf> {
+        let uid = self.uid()?;
+        Some(event_page_path(
+            "series",
+            self.summary(),
+            &format!("{:016x}", stable_hash(uid.as_bytes())),
+        ))
+    }
+
+    /// Returns the path of the countdown page of a featured event instance, e.g.
+    /// `countdown/board-meeting-0123456789abcdef.html`
+    pub fn countdown_page_path(&self) -> PathBuf {
+        event_page_path("countdown", self.summary(), &self.instance_id())
+    }
+
+    /// Returns whether the event is featured, by its UID being in `featured_uids` or by one of its categories being in
+    /// `featured_categories`
+    pub fn is_featured(&self, config: &Config) -> bool {
+        let uid_featured = self
+            .uid()
+            .is_some_and(|uid| config.featured_uids.iter().any(|featured| featured == uid));
+        let category_featured = self.categories.iter().any(|category| {
+            config
+                .featured_categories
+                .iter()
+                .any(|featured| featured.eq_ignore_ascii_case(category))
+        });
+        uid_featured || category_featured
+    }
+
+    /// Returns the identity used to detect duplicate instances across feeds
+    pub fn identity(&self) -> EventIdentity {
+        (
+            self.uid.clone(),
+            self.start(),
+            self.end(),
+            self.summary.clone(),
+        )
+    }
+
     pub fn summary(&self) -> &str {
-        self.summary.as_deref().unwrap_or(MISSING_SUMMARY)
+        self.summary.as_ref()
     }
 
     pub fn start(&self) -> OffsetDateTime {
@@ -101,6 +799,61 @@ impl Event {
         self.start + self.duration
     }
 
+    /// Returns the day of the start and the day of the end in `tz`, the same for events within a day
+    ///
+    /// An event ending at midnight ends on the day before.
+    pub fn first_and_last_day(&self, tz: &Tz) -> (Date, Date) {
+        let last_moment = if self.duration.is_positive() {
+            self.end() - Duration::nanoseconds(1)
+        } else {
+            self.start
+        };
+        (
+            self.start.to_timezone(tz).date(),
+            last_moment.to_timezone(tz).date(),
+        )
+    }
+
+    /// Returns every day the event spans in `tz`, see `first_and_last_day()`
+    pub fn days(&self, tz: &Tz) -> impl Iterator<Item = Date> {
+        let (first_day, last_day) = self.first_and_last_day(tz);
+        std::iter::successors(Some(first_day), move |day| {
+            day.next_day().filter(|next| *next <= last_day)
+        })
+    }
+
+    pub fn is_all_day(&self) -> bool {
+        self.all_day
+    }
+
+    /// Moves an all-day event to the midnights of its days in `tz`, so it covers the same dates wherever it is shown
+    ///
+    /// The dates are taken to the nearest midnight, so an instance of a recurring event which the expansion moved by
+    /// an hour across a change to summer time still keeps its day.
+    pub fn pin_all_day(&mut self, tz: &Tz) {
+        if !self.all_day {
+            return;
+        }
+        let nearest_date =
+            |time: OffsetDateTime| (time.to_timezone(tz) + Duration::hours(12)).date();
+        let days = (self.duration.as_seconds_f64() / 86_400.0).round().max(1.0) as i64;
+        let first_day = nearest_date(self.start);
+        // an event whose days cannot be placed in `tz` keeps its times
+        let (Ok(start), Ok(end)) = (
+            start_of_day(first_day, tz),
+            start_of_day(first_day + Duration::days(days), tz),
+        ) else {
+            return;
+        };
+        self.start = start;
+        self.duration = end - start;
+    }
+
+    /// Returns whether the event starts on `day` in `tz`, as opposed to continuing from an earlier day
+    pub fn starts_on(&self, day: Date, tz: &Tz) -> bool {
+        self.start.to_timezone(tz).date() == day
+    }
+
     pub fn year(&self) -> Year {
         self.start.year()
     }
@@ -113,25 +866,69 @@ impl Event {
         self.start.iso_week()
     }
 
+    /// Whether the event has a recurrence rule or is an instance of an event which has one
+    pub fn is_recurring(&self) -> bool {
+        self.rrule.is_some() || self.recurrence.is_some()
+    }
+
+    /// Returns the original start of the occurrence a modified instance replaces, see `Calendar::apply_overrides()`
+    pub fn recurrence_id(&self) -> Option<OffsetDateTime> {
+        self.recurrence_id
+    }
+
+    /// Returns the recurrence the event is an instance of
+    pub fn recurrence(&self) -> Option<&Recurrence> {
+        self.recurrence.as_ref()
+    }
+
+    /// Sets the first occurrence of the recurrence of an instance after the build, see
+    /// `Calendar::mark_next_occurrences()`
+    pub fn set_next_occurrence(&mut self, next_occurrence: Option<OffsetDateTime>) {
+        if let Some(recurrence) = &mut self.recurrence {
+            recurrence.next_occurrence = next_occurrence;
+        }
+    }
+
+    /// Makes a modified instance part of the recurrence of the occurrence it replaces
+    pub fn set_recurrence(&mut self, recurrence: Recurrence) {
+        self.recurrence = Some(recurrence);
+    }
+
+    /// Returns the position of the instance among the occurrences of its recurring event
+    pub fn recurrence_index(&self) -> Option<u32> {
+        self.recurrence.as_ref().map(|recurrence| recurrence.index)
+    }
+
+    /// Describes the recurrence rule in `language`, e.g. "Every 2 weeks on Tuesday", see `recurrence::describe()`
+    pub fn recurrence_summary(&self, tz: &Tz, language: Language) -> Option<String> {
+        self.rrule
+            .as_deref()
+            .or(self.recurrence.as_ref().map(|recurrence| &*recurrence.rule))
+            .and_then(|rule| recurrence::describe(rule, tz, language))
+    }
+
+    /// Whether the event has further occurrences, from an `RRULE` or `RDATE`
+    pub fn recurs(&self) -> bool {
+        self.rrule().is_some() || !self.extra_dates.is_empty()
+    }
+
+    /// Returns the starts of the occurrences added by `RDATE`
+    pub fn extra_dates(&self) -> &[OffsetDateTime] {
+        &self.extra_dates
+    }
+
+    /// Whether the occurrence starting at `start` was cancelled by an `EXDATE`
+    pub fn is_excluded(&self, start: OffsetDateTime) -> bool {
+        self.excluded_dates.contains(&start)
+    }
+
     pub fn rrule(&self) -> Option<RRule> {
-        println!("Attempting to parse: {:?}", self.rrule);
-        if let Some(rrule_str) = &self.rrule {
-            match format!(
-                "DTSTART:{}\n{}",
-                self.start()
-                    // ensure that DTSTART is provided in UTC
-                    .to_offset(offset!(+0))
-                    .format(format_description!(
-                        "[year][month][day]T[hour][minute][second]Z"
-                    ))
-                    .unwrap(),
-                rrule_str
-            )
-            .parse()
-            {
+        trace!("Attempting to parse: {:?}", self.rrule);
+        if let Some(rrule_str) = self.rrule.as_deref() {
+            match parse_rrule(rrule_str, self.start(), self.time_zone.as_deref()) {
                 Ok(rrule) => Some(rrule),
                 Err(e) => {
-                    println!("Could not parse rrule: {}", e);
+                    warn!("Could not parse rrule: {}", e);
                     None
                 }
             }
@@ -140,28 +937,133 @@ impl Event {
         }
     }
 
-    pub fn new(event: IcalEvent) -> Result<(Event, UnparsedProperties)> {
+    /// Parses an event, sharing its text through `interner` and resolving its times through `time_zones`
+    ///
+    /// The names of properties which are not used are added to `unparsed_properties`.
+    pub fn new(
+        event: &Component,
+        time_zones: &TimeZones,
+        interner: &mut StringInterner,
+        unparsed_properties: &mut UnparsedProperties,
+    ) -> Result<Event> {
+        let mut uid = None;
         let mut summary = None;
         let mut description = None;
         let mut start: Option<OffsetDateTime> = None;
+        let mut all_day = false;
         let mut end: Option<OffsetDateTime> = None;
+        let mut duration = None;
         let mut rrule = None;
+        let mut time_zone = None;
+        let mut recurrence_id = None;
+        let mut extra_dates = Vec::new();
+        let mut excluded_dates = Vec::new();
         let mut location = None;
+        let mut geo = None;
+        let mut organizer = None;
         let mut url = None;
+        let mut price = None;
+        let mut ticket_url = None;
+        let mut categories = Vec::new();
+        let mut extra_properties = Vec::new();
+        let mut attendees = Vec::new();
+        let mut last_modified = None;
+        let mut created = None;
+        let mut sequence = 0;
+        let mut status = None;
+        // busy hints in order of precedence: Microsoft busy status, intended status, transparency
+        let mut busy_hints: [Option<BusyStatus>; 3] = [None; 3];
 
-        let mut unparsed_properties: UnparsedProperties = HashSet::new();
-
-        for property in event.properties {
-            match property.name.as_str() {
-                "SUMMARY" => summary = property.value,
-                "DESCRIPTION" => description = property.value,
-                "DTSTART" => start = property_to_time(&property)?,
-                "DTEND" => end = property_to_time(&property)?,
-                "RRULE" => rrule = property.value,
-                "LOCATION" => location = property.value,
-                "URL" => url = property.value,
+        for property in &event.properties {
+            let mut interned = || property.value.map(|v| interner.intern(v));
+            let text = |interner: &mut StringInterner| {
+                property.value.map(|v| interner.intern(&unescape_text(v)))
+            };
+            match property.name {
+                "UID" => uid = interned(),
+                "SUMMARY" => summary = text(interner),
+                "DESCRIPTION" => description = text(interner),
+                "DTSTART" => {
+                    start = property_to_time(property, time_zones)?;
+                    all_day = is_date(property);
+                    time_zone = property
+                        .param("TZID")
+                        .and_then(iana_name)
+                        .map(|name| interner.intern(name));
+                }
+                "DTEND" => end = property_to_time(property, time_zones)?,
+                "DURATION" => duration = property.value.map(parse_duration).transpose()?,
+                "RRULE" => rrule = interned(),
+                "RECURRENCE-ID" => recurrence_id = property_to_time(property, time_zones)?,
+                "RDATE" => extra_dates.extend(property_to_times(property, time_zones)?),
+                "EXDATE" => excluded_dates.extend(property_to_times(property, time_zones)?),
+                "LOCATION" => location = text(interner),
+                "GEO" => geo = property.value.and_then(parse_geo),
+                "ORGANIZER" => {
+                    organizer = property
+                        .param("CN")
+                        .or_else(|| {
+                            property.value.map(|value| {
+                                value
+                                    .trim()
+                                    .trim_start_matches("mailto:")
+                                    .trim_start_matches("MAILTO:")
+                            })
+                        })
+                        .filter(|name| !name.is_empty())
+                        .map(|name| interner.intern(name))
+                }
+                "URL" => url = interned(),
+                "X-COST" => price = interned(),
+                "X-TICKET-URL" => ticket_url = interned(),
+                "LAST-MODIFIED" => last_modified = property_to_time(property, time_zones)?,
+                "CREATED" => created = property_to_time(property, time_zones)?,
+                "SEQUENCE" => {
+                    sequence = property
+                        .value
+                        .and_then(|value| value.trim().parse().ok())
+                        .unwrap_or_default()
+                }
+                "CATEGORIES" => {
+                    for category in property.value.into_iter().flat_map(split_categories) {
+                        if !categories
+                            .iter()
+                            .any(|known: &IStr| known.eq_ignore_ascii_case(&category))
+                        {
+                            categories.push(interner.intern(&category));
+                        }
+                    }
+                }
+                "ATTENDEE" => {
+                    if let Some(value) = property.value {
+                        let address = value
+                            .trim()
+                            .trim_start_matches("mailto:")
+                            .trim_start_matches("MAILTO:")
+                            .to_ascii_lowercase();
+                        let status = property
+                            .param("PARTSTAT")
+                            .and_then(ParticipationStatus::parse)
+                            .unwrap_or_default();
+                        attendees.push((interner.intern(&address), status));
+                    }
+                }
+                "X-MICROSOFT-CDO-BUSYSTATUS" => {
+                    busy_hints[0] = property.value.and_then(BusyStatus::from_microsoft)
+                }
+                "X-MICROSOFT-CDO-INTENDEDSTATUS" => {
+                    busy_hints[1] = property.value.and_then(BusyStatus::from_microsoft)
+                }
+                "TRANSP" => busy_hints[2] = property.value.and_then(BusyStatus::from_transparency),
+                "STATUS" => status = property.value.and_then(EventStatus::parse),
                 _ => {
-                    unparsed_properties.insert(property.name);
+                    if let (true, Some(value)) = (property.name.starts_with("X-"), property.value) {
+                        extra_properties
+                            .push((interner.intern(property.name), interner.intern(value)));
+                    }
+                    if !unparsed_properties.contains(property.name) {
+                        unparsed_properties.insert(property.name.to_string());
+                    }
                     // TODO collect unparsed params as well
                     // if let Some(params) = property.params {
                     //     println!("{:#?}", params);
@@ -171,73 +1073,495 @@ impl Event {
         }
 
         // bail if we don't have enough info
-        if summary.is_none() {
-            bail!("event has no summary")
-        }
+        let summary = match summary {
+            Some(summary) => summary,
+            None => bail!("event has no summary"),
+        };
         if start.is_none() {
             bail!("event has no start time")
         }
         if end.is_none() {
-            bail!("event has no end time")
+            // many feeds give the length of events instead of their end
+            end = start
+                .zip(duration)
+                .map(|(start, duration)| start + duration);
+        }
+        if all_day && end.is_none() {
+            // an all-day event without an end lasts the day it starts on
+            end = start.map(|start| start + Duration::DAY);
+        }
+        if end.is_none() {
+            bail!("event has neither an end time nor a duration")
         }
 
+        let status = status.unwrap_or_default();
+        // a cancelled event blocks no time, a tentative one only tentatively unless a busy hint says otherwise
+        let busy_status = match status {
+            EventStatus::Cancelled => BusyStatus::Free,
+            EventStatus::Tentative => busy_hints
+                .into_iter()
+                .flatten()
+                .next()
+                .unwrap_or(BusyStatus::Tentative),
+            EventStatus::Confirmed => busy_hints.into_iter().flatten().next().unwrap_or_default(),
+        };
+
         // TODO parse the rrule here, store None if it does not parse
-        Ok((
-            Event {
-                summary,
-                description,
-                start: start.unwrap(),
-                duration: end.unwrap() - start.unwrap(),
-                rrule,
-                location,
-                url,
-            },
-            unparsed_properties,
-        ))
+        Ok(Event {
+            uid,
+            summary,
+            description,
+            start: start.unwrap(),
+            duration: end.unwrap() - start.unwrap(),
+            rrule,
+            time_zone,
+            extra_dates: extra_dates.into(),
+            excluded_dates: excluded_dates.into(),
+            location,
+            geo,
+            organizer,
+            url,
+            price,
+            ticket_url,
+            categories: categories.into(),
+            extra_properties: extra_properties.into(),
+            busy_status,
+            status,
+            attendees: attendees.into(),
+            last_modified: last_modified.or(created),
+            sequence,
+            recurrence: None,
+            recurrence_id,
+            suspended_by: None,
+            all_day,
+        })
+    }
+
+    /// Creates a busy block for every period of a `VFREEBUSY` component
+    ///
+    /// Some systems only export availability, these blocks let the views show when the attendee is busy. Free
+    /// periods are left out.
+    pub fn from_free_busy(
+        free_busy: &Component,
+        interner: &mut StringInterner,
+    ) -> Result<Vec<Event>> {
+        let uid = free_busy
+            .properties
+            .iter()
+            .find(|property| property.name == "UID")
+            .and_then(|property| property.value)
+            .map(|value| interner.intern(value));
+
+        let mut blocks = Vec::new();
+        for property in free_busy
+            .properties
+            .iter()
+            .filter(|property| property.name == "FREEBUSY")
+        {
+            let fb_type = property.param("FBTYPE").unwrap_or("BUSY");
+            let (busy_status, summary) = match fb_type.to_ascii_uppercase().as_str() {
+                "FREE" => continue,
+                "BUSY-TENTATIVE" => (BusyStatus::Tentative, "Tentative"),
+                "BUSY-UNAVAILABLE" => (BusyStatus::Oof, "Unavailable"),
+                _ => (BusyStatus::Busy, "Busy"),
+            };
+            let summary = interner.intern(summary);
+
+            for period in property.value.unwrap_or_default().split(',') {
+                let (start, end) = period
+                    .split_once('/')
+                    .context(format!("free/busy period {:?} has no end", period))?;
+                let start = parse_utc_time(start)?;
+                let duration = if end.starts_with(['P', '+', '-']) {
+                    parse_duration(end)?
+                } else {
+                    parse_utc_time(end)? - start
+                };
+                blocks.push(Event {
+                    uid: uid.clone(),
+                    summary: summary.clone(),
+                    description: None,
+                    start,
+                    duration,
+                    rrule: None,
+                    time_zone: None,
+                    extra_dates: Rc::from([]),
+                    excluded_dates: Rc::from([]),
+                    location: None,
+                    geo: None,
+                    organizer: None,
+                    url: None,
+                    price: None,
+                    ticket_url: None,
+                    categories: Rc::from([]),
+                    extra_properties: Rc::from([]),
+                    busy_status,
+                    status: EventStatus::Confirmed,
+                    attendees: Rc::from([]),
+                    last_modified: None,
+                    sequence: 0,
+                    recurrence: None,
+                    recurrence_id: None,
+                    suspended_by: None,
+                    all_day: false,
+                });
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Creates an all-day event for a holiday, from midnight to midnight in `tz`
+    ///
+    /// Holidays do not keep anyone busy, so the event is free time.
+    pub fn holiday(name: &str, country: &str, date: Date, tz: &Tz) -> Result<Event> {
+        let uid = format!("holiday-{}-{}@statical", country.to_ascii_lowercase(), date);
+        Event::all_day_event(uid, name, "Holiday", date, tz)
+    }
+
+    /// Creates the deadline event `rule` derives from this event, if the rule matches it
+    ///
+    /// The deadline links to the same `URL` as the event and has the category "Deadline". Cancelled events have no
+    /// deadline.
+    pub fn deadline(&self, rule: &DeadlineRule, tz: &Tz) -> Option<Event> {
+        if self.suspended_by.is_some()
+            || self.status == EventStatus::Cancelled
+            || !self.matches(rule.category.as_deref(), rule.summary_regex.as_ref())
+        {
+            return None;
+        }
+        let event_day = self.start.to_timezone(tz).date();
+        let date = event_day - Duration::days(rule.days_before as i64);
+        let uid = format!(
+            "deadline-{}-{}@statical",
+            rule.days_before,
+            self.instance_id()
+        );
+        let summary = rule.summary.replace("{summary}", self.summary());
+        Some(Event {
+            description: Some(format!("{} on {}", self.summary(), event_day).into()),
+            url: self.url.clone(),
+            ..Event::all_day_event(uid, &summary, "Deadline", date, tz).ok()?
+        })
+    }
+
+    /// Creates a free all-day event from midnight to midnight in `tz`, with a single category
+    fn all_day_event(
+        uid: String,
+        summary: &str,
+        category: &str,
+        date: Date,
+        tz: &Tz,
+    ) -> Result<Event> {
+        let start = start_of_day(date, tz)?;
+        let end = start_of_day(date.next_day().unwrap_or(date), tz)?;
+        Ok(Event {
+            uid: Some(uid.into()),
+            summary: summary.into(),
+            description: None,
+            start,
+            duration: end - start,
+            rrule: None,
+            time_zone: None,
+            extra_dates: Rc::from([]),
+            excluded_dates: Rc::from([]),
+            location: None,
+            geo: None,
+            organizer: None,
+            url: None,
+            price: None,
+            ticket_url: None,
+            categories: Rc::from([IStr::from(category)]),
+            extra_properties: Rc::from([]),
+            busy_status: BusyStatus::Free,
+            status: EventStatus::Confirmed,
+            attendees: Rc::from([]),
+            last_modified: None,
+            sequence: 0,
+            recurrence: None,
+            recurrence_id: None,
+            suspended_by: None,
+            all_day: true,
+        })
     }
 
     /// Creates a duplicate event with a different start datetime.
     ///
-    /// This is useful when we are creating events from rrule expansions.
-    pub fn duplicate_with_date(&self, date: OffsetDateTime) -> Event {
+    /// This is useful when we are creating events from rrule expansions, `index` is the position of the occurrence.
+    pub fn duplicate_with_date(&self, date: OffsetDateTime, index: u32) -> Event {
         // TODO might want to link this event back to its parent event in some way, maybe even have a separate event class
         Event {
+            uid: self.uid.clone(),
             summary: self.summary.clone(),
             description: self.description.clone(),
             start: date,
             duration: self.duration,
             // we're un-setting the rrule to prevent recursion issues here
             rrule: None,
+            time_zone: None,
+            extra_dates: Rc::from([]),
+            excluded_dates: Rc::from([]),
             location: self.location.clone(),
+            geo: self.geo,
+            organizer: self.organizer.clone(),
             url: self.url.clone(),
+            price: self.price.clone(),
+            ticket_url: self.ticket_url.clone(),
+            categories: self.categories.clone(),
+            extra_properties: self.extra_properties.clone(),
+            busy_status: self.busy_status,
+            status: self.status,
+            attendees: self.attendees.clone(),
+            last_modified: self.last_modified,
+            sequence: self.sequence,
+            recurrence: self.rrule.clone().map(|rule| Recurrence {
+                rule,
+                series_start: self.start,
+                time_zone: self.time_zone.clone(),
+                index,
+                next_occurrence: None,
+            }),
+            recurrence_id: None,
+            suspended_by: self.suspended_by.clone(),
+            all_day: self.all_day,
         }
     }
 }
 
+/// Returns the first whole number in the text, e.g. 12 for "12 places"
+fn first_count(text: &str) -> Option<u32> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let digits: String = text[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+/// Converts the text found by a `FieldRule` to its kind, `None` if it does not fit
+fn field_value(text: &str, kind: FieldKind) -> Option<Value> {
+    match kind {
+        FieldKind::Text => (!text.is_empty()).then(|| Value::from(text)),
+        FieldKind::Number => {
+            let start = text.find(|c: char| c.is_ascii_digit())?;
+            let number: String = text[start..]
+                .chars()
+                .take_while(|c| c.is_ascii_digit() || *c == '.')
+                .collect();
+            let number = number.trim_end_matches('.');
+            match number.parse::<i64>() {
+                Ok(integer) => Some(Value::from(integer)),
+                Err(_) => number.parse::<f64>().ok().map(Value::from),
+            }
+        }
+        FieldKind::List => {
+            let items: Vec<&str> = text
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .collect();
+            (!items.is_empty()).then(|| Value::from(items))
+        }
+        FieldKind::Flag => Some(Value::from(!matches!(
+            text.to_ascii_lowercase().as_str(),
+            "false" | "no" | "0"
+        ))),
+    }
+}
+
+/// Parses a recurrence rule starting at `start`
+///
+/// With a `time_zone`, the rule is expanded in it, so that e.g. a weekly meeting at 10:00 stays at 10:00 local time
+/// after the clocks change and `BYDAY` counts the local weekdays. Without one, or if the rule cannot be expanded in
+/// it, the rule is expanded in UTC.
+fn parse_rrule(
+    rule: &str,
+    start: OffsetDateTime,
+    time_zone: Option<&str>,
+) -> Result<RRule, rrule::RRuleError> {
+    let local_start = time_zone.and_then(|name| {
+        let local = start.to_timezone(get_by_name(name)?);
+        let local = local
+            .format(format_description!(
+                "[year][month][day]T[hour][minute][second]"
+            ))
+            .ok()?;
+        Some(format!("DTSTART;TZID={}:{}\n{}", name, local, rule))
+    });
+    if let Some(Ok(rrule)) = local_start.map(|rule| rule.parse()) {
+        return Ok(rrule);
+    }
+    format!(
+        "DTSTART:{}\n{}",
+        start
+            // ensure that DTSTART is provided in UTC
+            .to_offset(offset!(+0))
+            .format(format_description!(
+                "[year][month][day]T[hour][minute][second]Z"
+            ))
+            .unwrap(),
+        rule
+    )
+    .parse()
+}
+
+/// Formats the time of day of `time` in `tz` for templates, e.g. "7:30pm"
+fn clock_time(time: OffsetDateTime, tz: &Tz) -> Option<String> {
+    time.to_timezone(tz)
+        .format(format_description!(
+            "[hour repr:12 padding:none]:[minute][period case:lower]"
+        ))
+        .ok()
+}
+
+/// Returns the path of a page of an event instance in `view`, the slug keeps it readable and the id unique
+fn event_page_path(view: &str, summary: &str, instance_id: &str) -> PathBuf {
+    PathBuf::from(view).join(format!("{}-{}.html", slug(summary), instance_id))
+}
+
+/// Hashes bytes with 64 bit FNV-1a, which unlike the std hashers is guaranteed to stay the same between releases
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Parses `GEO`, a latitude and a longitude separated by a semicolon, e.g. `37.386013;-122.082932`
+fn parse_geo(value: &str) -> Option<(f64, f64)> {
+    let (latitude, longitude) = value.split_once(';')?;
+    let latitude: f64 = latitude.trim().parse().ok()?;
+    let longitude: f64 = longitude.trim().parse().ok()?;
+    ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude))
+        .then_some((latitude, longitude))
+}
+
+/// Percent-encodes text for the query of a url
+fn encode_query(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Splits the value of `CATEGORIES` at its commas, keeping escaped commas (`\,`) within a category
+fn split_categories(value: &str) -> Vec<String> {
+    let mut categories = Vec::new();
+    let mut category = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            // keep the escape for `unescape_text()`, only an unescaped comma separates categories
+            ('\\', Some(escaped)) => {
+                category.push(c);
+                category.push(escaped);
+                chars.next();
+            }
+            (',', _) => categories.push(std::mem::take(&mut category)),
+            _ => category.push(c),
+        }
+    }
+    categories.push(category);
+    categories
+        .into_iter()
+        .map(|category| unescape_text(category.trim()).into_owned())
+        .filter(|category| !category.is_empty())
+        .collect()
+}
+
+/// Parses a UTC date and time like `20240514T090000Z`, as used by free/busy periods
+fn parse_utc_time(value: &str) -> Result<OffsetDateTime> {
+    Ok(PrimitiveDateTime::parse(
+        value.trim().trim_end_matches('Z'),
+        format_description!("[year][month][day]T[hour][minute][second]"),
+    )
+    .context(format!("could not parse time {:?}", value))?
+    .assume_utc())
+}
+
+/// Parses a duration like `PT1H30M` or `P1W`
+fn parse_duration(value: &str) -> Result<Duration> {
+    let (negative, rest) = match value.trim().strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.trim().trim_start_matches('+')),
+    };
+    let rest = rest
+        .strip_prefix('P')
+        .context(format!("duration {:?} does not start with P", value))?;
+
+    let mut duration = Duration::ZERO;
+    let mut number = String::new();
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            // separates the date and time parts, iCalendar durations have no months so `M` is always minutes
+            'T' => {}
+            'W' | 'D' | 'H' | 'M' | 'S' => {
+                let amount: i64 = number
+                    .parse()
+                    .context(format!("invalid duration {:?}", value))?;
+                duration += match c {
+                    'W' => Duration::weeks(amount),
+                    'D' => Duration::days(amount),
+                    'H' => Duration::hours(amount),
+                    'M' => Duration::minutes(amount),
+                    _ => Duration::seconds(amount),
+                };
+                number.clear();
+            }
+            _ => bail!("invalid duration {:?}", value),
+        }
+    }
+    Ok(if negative { -duration } else { duration })
+}
+
+/// Returns whether a time based property holds a date without a time, like `DTSTART;VALUE=DATE:20240514`
+fn is_date(property: &Property) -> bool {
+    property
+        .param("VALUE")
+        .is_some_and(|value| value.eq_ignore_ascii_case("DATE"))
+        || property
+            .value
+            .is_some_and(|value| value.len() == 8 && value.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Parses a property holding a list of times, like `EXDATE:20240514T090000Z,20240521T090000Z`
+///
+/// For periods, as `RDATE;VALUE=PERIOD` may give, the start is used.
+fn property_to_times(property: &Property, time_zones: &TimeZones) -> Result<Vec<OffsetDateTime>> {
+    let Some(value) = property.value else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(',')
+        .filter_map(|time| {
+            let single = Property {
+                value: time.split('/').next().map(str::trim),
+                ..property.clone()
+            };
+            property_to_time(&single, time_zones).transpose()
+        })
+        .collect()
+}
+
 /// Given a time based ical property, parse it into a OffsetDateTime
-fn property_to_time(property: &ical::property::Property) -> Result<Option<OffsetDateTime>> {
+///
+/// Times with a `TZID` are resolved through `time_zones`, see `TimeZones::resolve()`, floating times without one through
+/// `TimeZones::resolve_floating()`. A date without a time is the midnight starting it in UTC, until
+/// `Event::pin_all_day()` moves it to the display timezone.
+fn property_to_time(property: &Property, time_zones: &TimeZones) -> Result<Option<OffsetDateTime>> {
+    let value = property.value.context("no value for this property")?;
+    if is_date(property) {
+        let date = Date::parse(value.trim(), format_description!("[year][month][day]"))
+            .context(format!("could not parse date {:?}", value))?;
+        return Ok(Some(date.midnight().assume_utc()));
+    }
     let date_format = Regex::new("^(\\d+T\\d+)(Z)?$")?;
     let date_captures = date_format
-        .captures(
-            property
-                .value
-                .as_ref()
-                .context("no value for this property")?,
-        )
-        .expect("could not get captures");
-
-    let timezone = if date_captures.get(2).map(|c| c.as_str()) == Some("Z") {
-        get_by_name("UTC")
-    } else {
-        // if necessary, parse the primitive time and zone separately
-        if let Some(params) = &property.params {
-            let (_, zones) = params.iter().find(|(name, _zones)| name == "TZID").unwrap();
-            zones.first().and_then(|tz_name| get_by_name(tz_name))
-        } else {
-            // need to set a default timezone
-            get_by_name("America/Phoenix")
-        }
-    };
+        .captures(value)
+        .context(format!("could not parse time {:?}", value))?;
 
     // parse the time without zone information
     let primitive_time = PrimitiveDateTime::parse(
@@ -250,9 +1574,68 @@ fn property_to_time(property: &ical::property::Property) -> Result<Option<Offset
     .context("could not parse this time")?;
 
     // adjust the timezone
-    Ok(Some(
-        primitive_time
-            .assume_timezone(timezone.expect("no timezone determined for start time"))
-            .unwrap(),
-    ))
+    if date_captures.get(2).is_some() {
+        return Ok(Some(primitive_time.assume_utc()));
+    }
+    match property.param("TZID") {
+        Some(tzid) => Ok(Some(time_zones.resolve(tzid, primitive_time)?)),
+        None => Ok(Some(time_zones.resolve_floating(primitive_time)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ics;
+    use pretty_assertions::assert_eq;
+
+    /// Parses the single `VEVENT` of `properties`, given as content lines
+    fn event(properties: &str) -> Event {
+        let input = format!(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n{properties}END:VEVENT\r\nEND:VCALENDAR\r\n"
+        );
+        let lines = ics::unfold(&input);
+        let calendars = ics::parse_components(&lines, "test.ics");
+        let time_zones = TimeZones::from_calendar(&calendars[0], time_tz::timezones::db::UTC);
+        let vevent = calendars[0].components_named("VEVENT").next().unwrap();
+        Event::new(
+            vevent,
+            &time_zones,
+            &mut StringInterner::new(),
+            &mut HashSet::new(),
+        )
+        .unwrap()
+    }
+
+    fn rule(
+        category: Option<&str>,
+        summary_regex: Option<&str>,
+        color: Option<&str>,
+        icon: Option<&str>,
+    ) -> StyleRule {
+        StyleRule {
+            category: category.map(String::from),
+            summary_regex: summary_regex.map(|regex| Regex::new(regex).unwrap()),
+            color: color.map(String::from),
+            icon: icon.map(String::from),
+        }
+    }
+
+    #[test]
+    fn style_takes_each_field_from_the_first_matching_rule_setting_it() {
+        let talk = event(
+            "UID:1\r\nSUMMARY:Keynote talk\r\nCATEGORIES:Talks\r\nDTSTART:20240514T190000Z\r\n\
+                          DTEND:20240514T200000Z\r\n",
+        );
+        let rules = [
+            rule(Some("workshops"), None, Some("green"), Some("🔧")),
+            rule(None, Some("(?i)keynote"), None, Some("⭐")),
+            rule(Some("TALKS"), None, Some("blue"), Some("🎤")),
+            rule(None, None, Some("grey"), Some("📅")),
+        ];
+        assert_eq!(talk.style(&rules), (Some("blue"), Some("⭐")));
+        assert_eq!(talk.style(&rules[3..]), (Some("grey"), Some("📅")));
+        assert_eq!(talk.style(&rules[..2]), (None, Some("⭐")));
+        assert_eq!(talk.style(&[]), (None, None));
+    }
 }
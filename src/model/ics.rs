@@ -0,0 +1,313 @@
+//! A minimal iCalendar reader which borrows from the input instead of copying it.
+//!
+//! Reading happens in two passes: `unfold()` joins folded content lines, only allocating for the lines which were
+//! actually folded, and `parse_components()` splits the lines into properties and components which borrow from
+//! the unfolded lines. Values are passed on as they appear in the feed, `unescape_text()` decodes the values of
+//! TEXT properties like `SUMMARY` where they are read.
+
+use std::borrow::Cow;
+use tracing::warn;
+
+/// A single content line, e.g. `DTSTART;TZID=Europe/Berlin:20240801T100000`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property<'l> {
+    pub name: &'l str,
+    /// Parameters with their (unquoted) values, in the order they appear
+    pub params: Vec<(&'l str, Vec<&'l str>)>,
+    /// The value, `None` if it is empty
+    pub value: Option<&'l str>,
+}
+
+impl<'l> Property<'l> {
+    /// Returns the first value of a parameter, parameter names are case-insensitive
+    pub fn param(&self, name: &str) -> Option<&'l str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first().copied())
+    }
+}
+
+/// A `BEGIN:…`/`END:…` block with its properties and nested components
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Component<'l> {
+    pub name: &'l str,
+    pub properties: Vec<Property<'l>>,
+    pub components: Vec<Component<'l>>,
+}
+
+impl<'l> Component<'l> {
+    /// Returns the nested components with the given name, e.g. the `VEVENT`s of a `VCALENDAR`
+    pub fn components_named<'c>(
+        &'c self,
+        name: &'c str,
+    ) -> impl Iterator<Item = &'c Component<'l>> + 'c {
+        self.components
+            .iter()
+            .filter(move |component| component.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Joins folded lines and drops empty ones
+///
+/// As in RFC 5545, only the line break and the space or tab starting a continuation line are removed, whitespace
+/// next to the fold is part of the value. Lines which were not folded are borrowed from `input`.
+pub fn unfold(input: &str) -> Vec<Cow<'_, str>> {
+    let mut lines: Vec<Cow<'_, str>> = Vec::new();
+    for line in input.lines() {
+        if let Some(continuation) = line.strip_prefix([' ', '\t']) {
+            if let Some(previous) = lines.last_mut() {
+                previous.to_mut().push_str(continuation);
+                continue;
+            }
+        }
+        let line = line.trim_start();
+        if !line.trim_end().is_empty() {
+            lines.push(Cow::Borrowed(line));
+        }
+    }
+    // trailing whitespace at the end of a whole line is not part of its value
+    for line in &mut lines {
+        let length = line.trim_end().len();
+        match line {
+            Cow::Borrowed(borrowed) => *borrowed = &borrowed[..length],
+            Cow::Owned(owned) => owned.truncate(length),
+        }
+    }
+    lines
+}
+
+/// Parses unfolded lines into their top level components
+///
+/// Properties outside of any component are ignored, as are `END` lines without a matching `BEGIN`. Components which
+/// are still open at the end of the input are closed implicitly. Malformed lines are skipped with a warning naming
+/// `source`, so one broken property does not lose the whole feed.
+pub fn parse_components<'l>(lines: &'l [Cow<'_, str>], source: &str) -> Vec<Component<'l>> {
+    let mut top_level = Vec::new();
+    let mut open: Vec<Component<'l>> = Vec::new();
+
+    for (number, line) in lines.iter().enumerate() {
+        let Some(property) = parse_property(line) else {
+            warn!(
+                "Skipping unfolded line {} of {}, it is not a valid content line: {:?}",
+                number + 1,
+                source,
+                line
+            );
+            continue;
+        };
+        let value = property.value.unwrap_or_default();
+
+        if property.name.eq_ignore_ascii_case("BEGIN") {
+            open.push(Component {
+                name: value,
+                ..Default::default()
+            });
+        } else if property.name.eq_ignore_ascii_case("END") {
+            if open
+                .last()
+                .is_some_and(|component| component.name.eq_ignore_ascii_case(value))
+            {
+                close_component(&mut open, &mut top_level);
+            }
+        } else if let Some(component) = open.last_mut() {
+            component.properties.push(property);
+        }
+    }
+
+    while !open.is_empty() {
+        close_component(&mut open, &mut top_level);
+    }
+    top_level
+}
+
+/// Decodes the escapes of a TEXT value (`\\`, `\;`, `\,`, and `\n` or `\N` for a line break)
+///
+/// Values without a backslash are borrowed, a backslash before any other character is kept as it is.
+pub fn unescape_text(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(escaped @ ('\\' | ';' | ','))) => {
+                unescaped.push(escaped);
+                chars.next();
+            }
+            ('\\', Some('n' | 'N')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+/// Escapes a TEXT value for writing, the inverse of `unescape_text()`, line breaks become `\n`
+pub fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+/// Pops the innermost open component and adds it to its parent
+fn close_component<'l>(open: &mut Vec<Component<'l>>, top_level: &mut Vec<Component<'l>>) {
+    if let Some(component) = open.pop() {
+        match open.last_mut() {
+            Some(parent) => parent.components.push(component),
+            None => top_level.push(component),
+        }
+    }
+}
+
+/// Splits a content line into its name, parameters, and value
+///
+/// Returns `None` if the line has no name or no `:` separating the value.
+fn parse_property(line: &str) -> Option<Property<'_>> {
+    let name_end = line.find([';', ':'])?;
+    let name = &line[..name_end];
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut params = Vec::new();
+    let mut rest = &line[name_end..];
+    while let Some(param) = rest.strip_prefix(';') {
+        let (key, mut values_part) = param.split_once('=')?;
+        let mut values = Vec::new();
+        loop {
+            let (param_value, remainder) = match values_part.strip_prefix('"') {
+                Some(quoted) => {
+                    let quote_end = quoted.find('"')?;
+                    (&quoted[..quote_end], &quoted[quote_end + 1..])
+                }
+                None => {
+                    let value_end = values_part.find([',', ';', ':'])?;
+                    values_part.split_at(value_end)
+                }
+            };
+            values.push(param_value);
+            match remainder.strip_prefix(',') {
+                Some(more) => values_part = more,
+                None => {
+                    rest = remainder;
+                    break;
+                }
+            }
+        }
+        params.push((key, values));
+    }
+
+    let value = rest.strip_prefix(':')?;
+    Some(Property {
+        name,
+        params,
+        value: Some(value).filter(|value| !value.is_empty()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        let input = "BEGIN:VEVENT\r\nDESCRIPTION:one\r\n  two\r\n\tthree\r\n\r\nEND:VEVENT  \r\n";
+        let lines = unfold(input);
+        assert_eq!(
+            lines,
+            ["BEGIN:VEVENT", "DESCRIPTION:one twothree", "END:VEVENT"]
+        );
+        assert!(matches!(lines[0], Cow::Borrowed(_)));
+        assert!(matches!(lines[1], Cow::Owned(_)));
+    }
+
+    #[test]
+    fn parses_parameters() {
+        let property =
+            parse_property("ATTENDEE;Role=REQ-PARTICIPANT;DELEGATED-TO=a,b:mailto:c@example.org");
+        assert_eq!(
+            property,
+            Some(Property {
+                name: "ATTENDEE",
+                params: vec![
+                    ("Role", vec!["REQ-PARTICIPANT"]),
+                    ("DELEGATED-TO", vec!["a", "b"])
+                ],
+                value: Some("mailto:c@example.org"),
+            })
+        );
+        assert_eq!(property.unwrap().param("role"), Some("REQ-PARTICIPANT"));
+        assert_eq!(
+            parse_property("DTSTART:"),
+            Some(Property {
+                name: "DTSTART",
+                params: vec![],
+                value: None
+            })
+        );
+    }
+
+    #[test]
+    fn quoted_parameters_may_contain_separators() {
+        let property =
+            parse_property(r#"ORGANIZER;CN="Doe, Jane; PhD":mailto:jane@example.org"#).unwrap();
+        assert_eq!(property.param("CN"), Some("Doe, Jane; PhD"));
+        assert_eq!(property.value, Some("mailto:jane@example.org"));
+        let property = parse_property(r#"X-A;MEMBER="a:b","c":d"#).unwrap();
+        assert_eq!(property.params, [("MEMBER", vec!["a:b", "c"])]);
+        assert_eq!(property.value, Some("d"));
+    }
+
+    #[test]
+    fn rejects_lines_without_name_or_value() {
+        assert_eq!(parse_property(":value"), None);
+        assert_eq!(parse_property("SUMMARY"), None);
+        assert_eq!(parse_property("SUMMARY;CN"), None);
+        assert_eq!(parse_property(r#"SUMMARY;CN="unterminated:value"#), None);
+    }
+
+    #[test]
+    fn nests_components_and_skips_malformed_lines() {
+        let lines = unfold(
+            "SUMMARY:outside\r\nBEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:1\r\nnot a content line\r\n\
+             BEGIN:VALARM\r\nACTION:DISPLAY\r\nEND:VALARM\r\nSUMMARY:First\r\nEND:VEVENT\r\nEND:VTODO\r\n\
+             BEGIN:VEVENT\r\nUID:2\r\n",
+        );
+        let calendars = parse_components(&lines, "test.ics");
+        assert_eq!(calendars.len(), 1);
+        let events: Vec<_> = calendars[0].components_named("vevent").collect();
+        assert_eq!(events.len(), 2);
+        fn names<'l>(component: &Component<'l>) -> Vec<&'l str> {
+            component
+                .properties
+                .iter()
+                .map(|property| property.name)
+                .collect()
+        }
+        assert_eq!(names(events[0]), ["UID", "SUMMARY"]);
+        assert_eq!(names(&events[0].components[0]), ["ACTION"]);
+        // the second event is closed implicitly at the end of the input
+        assert_eq!(names(events[1]), ["UID"]);
+    }
+
+    #[test]
+    fn unescapes_text_values() {
+        assert!(matches!(unescape_text("plain"), Cow::Borrowed("plain")));
+        assert_eq!(
+            unescape_text(r"Room 4\, 2nd floor\; bring\nsnacks\N\\o/ \x"),
+            "Room 4, 2nd floor; bring\nsnacks\n\\o/ \\x"
+        );
+        assert_eq!(unescape_text(r"trailing\"), "trailing\\");
+        let text = "a\\b, c; d\r\ne\nf";
+        assert_eq!(escape_text(text), r"a\\b\, c\; d\ne\nf");
+        assert_eq!(unescape_text(&escape_text(text)), "a\\b, c; d\ne\nf");
+    }
+}
@@ -1,4 +1,11 @@
+pub mod arena;
 pub mod calendar;
 pub mod calendar_collection;
 pub mod day;
 pub mod event;
+pub mod ics;
+pub mod recurrence;
+pub mod tabular;
+pub mod timeline;
+pub mod timezone;
+pub mod year;
@@ -0,0 +1,554 @@
+//! Describes recurrence rules in prose, e.g. `FREQ=MONTHLY;BYDAY=1FR;UNTIL=20250630` as
+//! "Monthly on the first Friday, until June 30, 2025".
+//!
+//! Only the parts which are common in calendar feeds are described: the frequency and interval, weekdays (with their
+//! position in the month, or picked by `BYSETPOS` like "the last weekday"), days of the month, months, and the end of
+//! the recurrence. Unknown parts are left out.
+
+use time::{macros::format_description, Date, Month, PrimitiveDateTime};
+use time_tz::{OffsetDateTimeExt, Tz};
+
+use crate::config::Language;
+
+/// The parts of a rule which are described
+#[derive(Debug, Default)]
+struct Rule<'r> {
+    frequency: &'r str,
+    interval: u32,
+    weekdays: Vec<(Option<i8>, &'r str)>,
+    /// `BYSETPOS`, only described for weekdays without a position of their own
+    set_positions: Vec<i8>,
+    month_days: Vec<i8>,
+    months: Vec<u8>,
+    count: Option<u32>,
+    until: Option<Date>,
+}
+
+/// Describes a recurrence rule, with or without the `RRULE:` prefix
+///
+/// `UNTIL` is shown as a date in `tz`. Returns `None` for rules without a known `FREQ`.
+pub fn describe(rule: &str, tz: &Tz, language: Language) -> Option<String> {
+    let rule = parse(rule, tz)?;
+    Some(match language {
+        Language::En => english(&rule),
+        Language::Fr => french(&rule),
+    })
+}
+
+fn parse<'r>(rule: &'r str, tz: &Tz) -> Option<Rule<'r>> {
+    let mut parsed = Rule {
+        interval: 1,
+        ..Default::default()
+    };
+    for (name, value) in rule
+        .trim_start_matches("RRULE:")
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+    {
+        match name.to_ascii_uppercase().as_str() {
+            "FREQ" => parsed.frequency = value,
+            "INTERVAL" => parsed.interval = value.parse().unwrap_or(1).max(1),
+            "BYDAY" => {
+                parsed.weekdays = value
+                    .split(',')
+                    .filter_map(|day| {
+                        // e.g. `FR`, `1FR`, or `-1SU`
+                        let position_end = day.len().checked_sub(2)?;
+                        let (position, weekday) = day.split_at(position_end);
+                        let position = match position.trim_start_matches('+') {
+                            "" => None,
+                            position => Some(position.parse().ok()?),
+                        };
+                        Some((position, weekday))
+                    })
+                    .collect()
+            }
+            "BYSETPOS" => {
+                parsed.set_positions = value
+                    .split(',')
+                    .filter_map(|position| position.trim_start_matches('+').parse().ok())
+                    .filter(|position| *position != 0)
+                    .collect()
+            }
+            "BYMONTHDAY" => {
+                parsed.month_days = value
+                    .split(',')
+                    .filter_map(|day| day.parse().ok())
+                    .collect()
+            }
+            "BYMONTH" => {
+                parsed.months = value
+                    .split(',')
+                    .filter_map(|month| month.parse().ok())
+                    .collect()
+            }
+            "COUNT" => parsed.count = value.parse().ok(),
+            "UNTIL" => parsed.until = parse_until(value, tz),
+            _ => {}
+        }
+    }
+    matches!(
+        parsed.frequency.to_ascii_uppercase().as_str(),
+        "SECONDLY" | "MINUTELY" | "HOURLY" | "DAILY" | "WEEKLY" | "MONTHLY" | "YEARLY"
+    )
+    .then_some(parsed)
+}
+
+/// Parses `UNTIL`, which is either a date or a date and time (in UTC if it ends with `Z`)
+fn parse_until(value: &str, tz: &Tz) -> Option<Date> {
+    let date = Date::parse(value.get(..8)?, format_description!("[year][month][day]")).ok()?;
+    if !value.ends_with('Z') {
+        return Some(date);
+    }
+    let date_time = PrimitiveDateTime::parse(
+        value.get(..15)?,
+        format_description!("[year][month][day]T[hour][minute][second]"),
+    )
+    .ok()?;
+    Some(date_time.assume_utc().to_timezone(tz).date())
+}
+
+fn english(rule: &Rule) -> String {
+    let unit = match rule.frequency.to_ascii_uppercase().as_str() {
+        "SECONDLY" => "second",
+        "MINUTELY" => "minute",
+        "HOURLY" => "hour",
+        "DAILY" => "day",
+        "WEEKLY" => "week",
+        "MONTHLY" => "month",
+        _ => "year",
+    };
+    let mut text = match (rule.interval, unit) {
+        (1, "hour") => "Hourly".to_string(),
+        (1, "day") => "Daily".to_string(),
+        (1, "week") => "Weekly".to_string(),
+        (1, "month") => "Monthly".to_string(),
+        (1, "year") => "Yearly".to_string(),
+        (1, unit) => format!("Every {}", unit),
+        (interval, unit) => format!("Every {} {}s", interval, unit),
+    };
+
+    let weekdays: Vec<String> = rule
+        .weekdays
+        .iter()
+        .filter_map(|(position, weekday)| {
+            let name = english_weekday(weekday)?;
+            Some(match position {
+                Some(position) => format!("the {} {}", english_position(*position), name),
+                None => name.to_string(),
+            })
+        })
+        .collect();
+    if let Some(weekday_set) = weekday_set(rule) {
+        let positions: Vec<String> = rule
+            .set_positions
+            .iter()
+            .map(|position| english_position(*position))
+            .collect();
+        let days = match weekday_set {
+            WeekdaySet::Weekdays => "weekday".to_string(),
+            WeekdaySet::Weekend => "weekend day".to_string(),
+            WeekdaySet::Week => "day".to_string(),
+            WeekdaySet::Other => join(&weekdays, ", ", " or "),
+        };
+        text.push_str(&format!(
+            " on the {} {}",
+            join(&positions, ", ", " and "),
+            days
+        ));
+    } else if !weekdays.is_empty() {
+        text.push_str(&format!(" on {}", join(&weekdays, ", ", " and ")));
+    }
+
+    let month_days: Vec<String> = rule
+        .month_days
+        .iter()
+        .map(|day| match day {
+            -1 => "the last day".to_string(),
+            day if *day < 0 => format!("the {} to last day", english_ordinal(-day)),
+            day => format!("the {}", english_ordinal(*day)),
+        })
+        .collect();
+    if !month_days.is_empty() {
+        text.push_str(&format!(" on {}", join(&month_days, ", ", " and ")));
+    }
+
+    let months: Vec<String> = month_names(&rule.months)
+        .map(|month| month.to_string())
+        .collect();
+    if !months.is_empty() {
+        text.push_str(&format!(" in {}", join(&months, ", ", " and ")));
+    }
+
+    if let Some(until) = rule.until {
+        text.push_str(&format!(
+            ", until {} {}, {}",
+            until.month(),
+            until.day(),
+            until.year()
+        ));
+    } else if let Some(count) = rule.count {
+        text.push_str(&match count {
+            1 => ", once".to_string(),
+            2 => ", twice".to_string(),
+            count => format!(", {} times", count),
+        });
+    }
+    text
+}
+
+fn english_weekday(weekday: &str) -> Option<&'static str> {
+    Some(match weekday.to_ascii_uppercase().as_str() {
+        "MO" => "Monday",
+        "TU" => "Tuesday",
+        "WE" => "Wednesday",
+        "TH" => "Thursday",
+        "FR" => "Friday",
+        "SA" => "Saturday",
+        "SU" => "Sunday",
+        _ => return None,
+    })
+}
+
+/// The position of a weekday within the month or year, e.g. "first" or "second to last"
+fn english_position(position: i8) -> String {
+    let word = |position: i8| match position {
+        1 => "first".to_string(),
+        2 => "second".to_string(),
+        3 => "third".to_string(),
+        4 => "fourth".to_string(),
+        5 => "fifth".to_string(),
+        position => english_ordinal(position),
+    };
+    match position {
+        -1 => "last".to_string(),
+        position if position < 0 => format!("{} to last", word(-position)),
+        position => word(position),
+    }
+}
+
+/// A number with its ordinal suffix, e.g. "1st", "12th", or "23rd"
+fn english_ordinal(number: i8) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", number, suffix)
+}
+
+fn french(rule: &Rule) -> String {
+    // the unit in plural and whether it is feminine
+    let (units, feminine) = match rule.frequency.to_ascii_uppercase().as_str() {
+        "SECONDLY" => ("secondes", true),
+        "MINUTELY" => ("minutes", true),
+        "HOURLY" => ("heures", true),
+        "DAILY" => ("jours", false),
+        "WEEKLY" => ("semaines", true),
+        "MONTHLY" => ("mois", false),
+        _ => ("ans", false),
+    };
+    let every = if feminine { "Toutes les" } else { "Tous les" };
+    let mut text = match rule.interval {
+        1 => format!("{} {}", every, units),
+        interval => format!("{} {} {}", every, interval, units),
+    };
+
+    let set = weekday_set(rule);
+    if let Some(weekday_set) = &set {
+        let positions: Vec<String> = rule
+            .set_positions
+            .iter()
+            .map(|position| french_position(*position))
+            .collect();
+        let names: Vec<&str> = rule
+            .weekdays
+            .iter()
+            .filter_map(|(_, weekday)| french_weekday(weekday))
+            .collect();
+        let days = match weekday_set {
+            WeekdaySet::Weekdays => "jour ouvré".to_string(),
+            WeekdaySet::Weekend => "jour du week-end".to_string(),
+            WeekdaySet::Week => "jour".to_string(),
+            WeekdaySet::Other => join(&names, ", ", " ou "),
+        };
+        let positions = join(&positions, ", ", " et ");
+        // elided before a vowel, e.g. "l'avant-dernier jour ouvré"
+        let article = if positions.starts_with('a') {
+            "l'"
+        } else {
+            "le "
+        };
+        text.push_str(&format!(" {}{} {}", article, positions, days));
+    }
+    let weekdays: Vec<String> = rule
+        .weekdays
+        .iter()
+        .filter(|_| set.is_none())
+        .filter_map(|(position, weekday)| {
+            let name = french_weekday(weekday)?;
+            Some(match position {
+                Some(position) => match french_position(*position) {
+                    // elided before a vowel, e.g. "l'avant-dernier dimanche"
+                    position if position.starts_with('a') => format!("l'{} {}", position, name),
+                    position => format!("le {} {}", position, name),
+                },
+                None => format!("le {}", name),
+            })
+        })
+        .collect();
+    if !weekdays.is_empty() {
+        text.push_str(&format!(" {}", join(&weekdays, ", ", " et ")));
+    }
+
+    let month_days: Vec<String> = rule
+        .month_days
+        .iter()
+        .map(|day| match day {
+            -1 => "le dernier jour".to_string(),
+            1 => "le 1er".to_string(),
+            day if *day < 0 => format!("le {} jour avant la fin", -day - 1),
+            day => format!("le {}", day),
+        })
+        .collect();
+    if !month_days.is_empty() {
+        text.push_str(&format!(" {}", join(&month_days, ", ", " et ")));
+    }
+
+    let months: Vec<&str> = rule
+        .months
+        .iter()
+        .filter_map(|month| french_month(*month))
+        .collect();
+    if !months.is_empty() {
+        text.push_str(&format!(" en {}", join(&months, ", ", " et ")));
+    }
+
+    if let Some(until) = rule.until {
+        text.push_str(&format!(
+            ", jusqu'au {} {} {}",
+            if until.day() == 1 {
+                "1er".to_string()
+            } else {
+                until.day().to_string()
+            },
+            french_month(until.month() as u8).unwrap_or_default(),
+            until.year()
+        ));
+    } else if let Some(count) = rule.count {
+        text.push_str(&format!(", {} fois", count));
+    }
+    text
+}
+
+fn french_weekday(weekday: &str) -> Option<&'static str> {
+    Some(match weekday.to_ascii_uppercase().as_str() {
+        "MO" => "lundi",
+        "TU" => "mardi",
+        "WE" => "mercredi",
+        "TH" => "jeudi",
+        "FR" => "vendredi",
+        "SA" => "samedi",
+        "SU" => "dimanche",
+        _ => return None,
+    })
+}
+
+/// The position of a weekday within the month or year, e.g. "premier" or "avant-dernier"
+fn french_position(position: i8) -> String {
+    match position {
+        1 => "premier".to_string(),
+        -1 => "dernier".to_string(),
+        -2 => "avant-dernier".to_string(),
+        position if position < 0 => format!("{}e avant la fin", -position - 1),
+        position => format!("{}e", position),
+    }
+}
+
+fn french_month(month: u8) -> Option<&'static str> {
+    Some(match month {
+        1 => "janvier",
+        2 => "février",
+        3 => "mars",
+        4 => "avril",
+        5 => "mai",
+        6 => "juin",
+        7 => "juillet",
+        8 => "août",
+        9 => "septembre",
+        10 => "octobre",
+        11 => "novembre",
+        12 => "décembre",
+        _ => return None,
+    })
+}
+
+/// The weekdays of a rule which `BYSETPOS` picks from, by how they are named
+enum WeekdaySet {
+    /// Monday to Friday
+    Weekdays,
+    /// Saturday and Sunday
+    Weekend,
+    /// Every day of the week
+    Week,
+    Other,
+}
+
+/// Returns which weekdays `BYSETPOS` picks from, `None` if the rule has no set positions or positioned weekdays
+fn weekday_set(rule: &Rule) -> Option<WeekdaySet> {
+    if rule.set_positions.is_empty()
+        || rule.weekdays.is_empty()
+        || rule.weekdays.iter().any(|(position, _)| position.is_some())
+    {
+        return None;
+    }
+    let mut weekdays: Vec<String> = rule
+        .weekdays
+        .iter()
+        .map(|(_, weekday)| weekday.to_ascii_uppercase())
+        .collect();
+    weekdays.sort_by_key(|weekday| WEEKDAYS.iter().position(|known| known == weekday));
+    weekdays.dedup();
+    Some(if weekdays == WEEKDAYS[..5] {
+        WeekdaySet::Weekdays
+    } else if weekdays == WEEKDAYS[5..] {
+        WeekdaySet::Weekend
+    } else if weekdays == WEEKDAYS {
+        WeekdaySet::Week
+    } else {
+        WeekdaySet::Other
+    })
+}
+
+/// The weekdays as in `BYDAY`, from Monday
+const WEEKDAYS: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+/// The English names of the valid month numbers
+fn month_names(months: &[u8]) -> impl Iterator<Item = Month> + '_ {
+    months
+        .iter()
+        .filter_map(|month| Month::try_from(*month).ok())
+}
+
+/// Joins items with `separator`, using `last_separator` before the last one, e.g. "a, b and c"
+fn join<S: AsRef<str>>(items: &[S], separator: &str, last_separator: &str) -> String {
+    match items.split_last() {
+        Some((last, [])) => last.as_ref().to_string(),
+        Some((last, rest)) => format!(
+            "{}{}{}",
+            rest.iter()
+                .map(|item| item.as_ref())
+                .collect::<Vec<_>>()
+                .join(separator),
+            last_separator,
+            last.as_ref()
+        ),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use time_tz::timezones::db::{america::NEW_YORK, UTC};
+
+    fn english(rule: &str) -> String {
+        describe(rule, UTC, Language::En).unwrap()
+    }
+
+    fn french(rule: &str) -> String {
+        describe(rule, UTC, Language::Fr).unwrap()
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(english("RRULE:FREQ=WEEKLY"), "Weekly");
+        assert_eq!(english("FREQ=WEEKLY;INTERVAL=1"), "Weekly");
+        assert_eq!(
+            english("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU"),
+            "Every 2 weeks on Tuesday"
+        );
+        assert_eq!(english("FREQ=MINUTELY;INTERVAL=1"), "Every minute");
+        assert_eq!(
+            english("FREQ=DAILY;INTERVAL=3;COUNT=2"),
+            "Every 3 days, twice"
+        );
+        // an invalid interval is read as 1
+        assert_eq!(english("FREQ=YEARLY;INTERVAL=0"), "Yearly");
+        assert_eq!(
+            french("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU"),
+            "Toutes les 2 semaines le mardi"
+        );
+        assert_eq!(french("FREQ=MONTHLY;INTERVAL=3"), "Tous les 3 mois");
+        assert_eq!(describe("FREQ=FORTNIGHTLY", UTC, Language::En), None);
+    }
+
+    #[test]
+    fn positions() {
+        assert_eq!(
+            english("FREQ=MONTHLY;BYDAY=1FR;UNTIL=20250630"),
+            "Monthly on the first Friday, until June 30, 2025"
+        );
+        assert_eq!(
+            english("FREQ=MONTHLY;BYDAY=-2SU,+3WE"),
+            "Monthly on the second to last Sunday and the third Wednesday"
+        );
+        assert_eq!(
+            english("FREQ=YEARLY;BYMONTH=3,10;BYMONTHDAY=-1,-3,11"),
+            "Yearly on the last day, the 3rd to last day and the 11th in March and October"
+        );
+        assert_eq!(
+            french("FREQ=MONTHLY;BYDAY=-2SU"),
+            "Tous les mois l'avant-dernier dimanche"
+        );
+    }
+
+    #[test]
+    fn set_positions() {
+        assert_eq!(
+            english("FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1"),
+            "Monthly on the last weekday"
+        );
+        assert_eq!(
+            english("FREQ=MONTHLY;INTERVAL=2;BYDAY=SU,SA;BYSETPOS=1,2"),
+            "Every 2 months on the first and second weekend day"
+        );
+        assert_eq!(
+            english("FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR,SA,SU;BYSETPOS=-2"),
+            "Monthly on the second to last day"
+        );
+        assert_eq!(
+            english("FREQ=MONTHLY;BYDAY=MO,WE;BYSETPOS=3"),
+            "Monthly on the third Monday or Wednesday"
+        );
+        // weekdays with their own position are described without the set positions
+        assert_eq!(
+            english("FREQ=MONTHLY;BYDAY=1MO;BYSETPOS=1"),
+            "Monthly on the first Monday"
+        );
+        assert_eq!(
+            french("FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-2"),
+            "Tous les mois l'avant-dernier jour ouvré"
+        );
+        assert_eq!(
+            french("FREQ=MONTHLY;BYDAY=MO,WE;BYSETPOS=1"),
+            "Tous les mois le premier lundi ou mercredi"
+        );
+    }
+
+    #[test]
+    fn until_in_the_timezone() {
+        // 03:00 UTC is still the evening before in New York
+        assert_eq!(
+            describe("FREQ=DAILY;UNTIL=20250701T030000Z", NEW_YORK, Language::En).unwrap(),
+            "Daily, until June 30, 2025"
+        );
+        assert_eq!(
+            french("FREQ=DAILY;UNTIL=20250701"),
+            "Tous les jours, jusqu'au 1er juillet 2025"
+        );
+    }
+}
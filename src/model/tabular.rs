@@ -0,0 +1,326 @@
+//! Reads events from CSV, for groups which keep their schedule in a spreadsheet rather than a calendar app.
+//!
+//! Sources ending in `.csv` are read as CSV, as are those listed in `Config::csv_sources`.
+//!
+//! Like the virtual events of the config, the rows are written as ICS, so they are parsed, expanded, and reported like
+//! the events of any other source. The columns are found by their header, in any order and case:
+//!
+//! - `summary` and `date` (`2024-05-14`) are required
+//! - `start_time` and `end_time` (`19:00`) in the display time zone, an event without an end time lasts an hour and
+//!   one without either lasts the whole day
+//! - `end_date` for events ending on a later day, at `end_time` or else at the end of that day
+//! - `description`, `location`, `geo`, `url`, `uid`, `status`, and `rrule` become the properties of the same name
+//! - `categories`, separated by commas
+//!
+//! Other columns become `X-` properties, e.g. `audience` and `x_audience` both become `X-AUDIENCE`, which
+//! `Config::field_rules` can read.
+//!
+//! Rows without a `uid` get one hashed from the columns of `Config::csv_uid_columns`, so an unchanged row is the same
+//! event in every build and in every source listing it. Each event keeps the line of its row in `X-CSV-LINE`, to find
+//! the row of a UID; copying the UID into a `uid` column keeps it when the identifying columns change.
+
+use color_eyre::eyre::{bail, eyre, Context, Result};
+use sha2::{Digest, Sha256};
+use time::{macros::format_description, Date, Duration, PrimitiveDateTime, Time};
+
+use crate::config::Config;
+use crate::model::ics::escape_text;
+use crate::upload::hex;
+
+/// Whether a source is CSV rather than ICS, by its name ending in `.csv` or its listing in `Config::csv_sources`
+///
+/// The query and fragment of a url are not part of its name, so `sheet.csv?version=2` is CSV too.
+pub fn is_csv(source: &str, config: &Config) -> bool {
+    let path = source.split(['?', '#']).next().unwrap_or_default();
+    path.to_ascii_lowercase().ends_with(".csv")
+        || config.csv_sources.iter().any(|csv| csv == source)
+}
+
+/// Writes the rows of a CSV file as a calendar, returning it with a warning for each row which was skipped
+///
+/// Times are given in the display time zone. Empty rows are left out silently.
+pub fn csv_to_ics(data: &str, config: &Config) -> Result<(String, Vec<String>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.trim_start_matches('\u{feff}').as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .wrap_err("could not read the CSV header")?
+        .iter()
+        .map(column_name)
+        .collect();
+    for required in ["summary", "date"] {
+        if !headers.iter().any(|header| header == required) {
+            bail!("the CSV header has no {:?} column", required);
+        }
+    }
+
+    let mut ics = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//statical//csv//EN\r\n");
+    let mut warnings = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        // the header is the first line of the file
+        let line = index + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                warnings.push(format!("skipped line {}: {}", line, error));
+                continue;
+            }
+        };
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        let row: Vec<(&str, &str)> = headers
+            .iter()
+            .map(String::as_str)
+            .zip(record.iter())
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        match row_to_event(&row, line, config) {
+            Ok(event) => ics.push_str(&event),
+            Err(error) => warnings.push(format!("skipped line {}: {:#}", line, error)),
+        }
+    }
+    ics.push_str("END:VCALENDAR\r\n");
+    Ok((ics, warnings))
+}
+
+/// Writes the `VEVENT` of a row, given as its non-empty columns
+fn row_to_event(row: &[(&str, &str)], line: usize, config: &Config) -> Result<String> {
+    let time_zone = &config.display_timezone;
+    let column = |name: &str| {
+        row.iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| *value)
+    };
+    let summary = column("summary").ok_or_else(|| eyre!("it has no summary"))?;
+    let date = parse_date(column("date").ok_or_else(|| eyre!("it has no date"))?)?;
+    let end_date = column("end_date").map(parse_date).transpose()?;
+    let start_time = column("start_time").map(parse_time).transpose()?;
+    let end_time = column("end_time").map(parse_time).transpose()?;
+
+    let mut event = String::from("BEGIN:VEVENT\r\n");
+    let mut property = |name: &str, value: &str| {
+        event.push_str(&format!(
+            "{}:{}\r\n",
+            name,
+            value.replace(['\r', '\n'], " ")
+        ))
+    };
+    property("SUMMARY", &escape_text(summary));
+    if column("uid").is_none() {
+        property("UID", &synthetic_uid(row, &config.csv_uid_columns));
+    }
+    property("X-CSV-LINE", &line.to_string());
+    if let Some(end_date) = end_date.filter(|end_date| *end_date < date) {
+        bail!("it ends on {} before it starts on {}", end_date, date);
+    }
+    match (start_time, end_time) {
+        (Some(start_time), end_time) => {
+            // without an end time, the event lasts an hour, or until the end of its end date
+            let end = match (end_time, end_date) {
+                (Some(end_time), end_date) => end_date.unwrap_or(date).with_time(end_time),
+                (None, Some(end_date)) => end_date.next_day().unwrap_or(Date::MAX).midnight(),
+                (None, None) => date.with_time(start_time) + Duration::HOUR,
+            };
+            let start = date.with_time(start_time);
+            if end < start {
+                bail!(
+                    "it ends at {} before it starts at {}, an end_date is needed for events ending the next day",
+                    clock_time(end.time()),
+                    clock_time(start_time)
+                );
+            }
+            property(&format!("DTSTART;TZID={}", time_zone), &ics_time(start));
+            property(&format!("DTEND;TZID={}", time_zone), &ics_time(end));
+        }
+        (None, Some(_)) => bail!("it has an end time but no start time"),
+        (None, None) => {
+            // the end of an all-day event is exclusive
+            let end = end_date.unwrap_or(date).next_day().unwrap_or(Date::MAX);
+            property("DTSTART;VALUE=DATE", &ics_date(date));
+            property("DTEND;VALUE=DATE", &ics_date(end));
+        }
+    }
+    for (header, value) in row {
+        match *header {
+            "" | "summary" | "date" | "end_date" | "start_time" | "end_time" => {}
+            "description" | "location" => {
+                property(&header.to_ascii_uppercase(), &escape_text(value))
+            }
+            "geo" | "url" | "uid" | "status" | "categories" => {
+                property(&header.to_ascii_uppercase(), value)
+            }
+            "rrule" => property("RRULE", value.trim_start_matches("RRULE:")),
+            other => {
+                // a column already named like the property, e.g. `x_audience`, does not get a second prefix
+                let name = other.strip_prefix("x_").unwrap_or(other);
+                property(
+                    &format!("X-{}", name.to_ascii_uppercase().replace('_', "-")),
+                    value,
+                )
+            }
+        }
+    }
+    event.push_str("END:VEVENT\r\n");
+    Ok(event)
+}
+
+/// Returns the UID of a row from the values of `columns`, the same for every row with the same values
+fn synthetic_uid(row: &[(&str, &str)], columns: &[String]) -> String {
+    let mut key = String::new();
+    for name in columns.iter().map(|name| column_name(name)) {
+        let value = row
+            .iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| *value)
+            .unwrap_or_default();
+        key.push_str(&format!("{}={}\u{1f}", name, value));
+    }
+    format!(
+        "{}@csv.statical",
+        hex(&Sha256::digest(key.as_bytes())[..12])
+    )
+}
+
+/// Normalizes a header, e.g. "Start Time" to `start_time`
+fn column_name(header: &str) -> String {
+    header
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn parse_date(date: &str) -> Result<Date> {
+    Date::parse(date, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| eyre!("{:?} is not a date like 2024-05-14", date))
+}
+
+fn parse_time(time: &str) -> Result<Time> {
+    Time::parse(time, format_description!("[hour padding:none]:[minute]"))
+        .map_err(|_| eyre!("{:?} is not a time like 19:00", time))
+}
+
+/// Formats a time like the `start_time` and `end_time` columns, e.g. `19:00`
+fn clock_time(time: Time) -> String {
+    time.format(format_description!("[hour]:[minute]"))
+        .unwrap_or_default()
+}
+
+fn ics_time(time: PrimitiveDateTime) -> String {
+    time.format(format_description!(
+        "[year][month][day]T[hour][minute][second]"
+    ))
+    .unwrap_or_default()
+}
+
+fn ics_date(date: Date) -> String {
+    date.format(format_description!("[year][month][day]"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn config() -> Config {
+        Config {
+            display_timezone: "UTC".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the properties of the single event of `csv`, or the warning for its row
+    fn event(csv: &str) -> std::result::Result<Vec<String>, String> {
+        let (ics, warnings) = csv_to_ics(csv, &config()).unwrap();
+        if let Some(warning) = warnings.into_iter().next() {
+            return Err(warning);
+        }
+        Ok(ics
+            .lines()
+            .skip_while(|line| *line != "BEGIN:VEVENT")
+            .skip(1)
+            .take_while(|line| *line != "END:VEVENT")
+            .filter(|line| !line.starts_with("UID:") && !line.starts_with("X-CSV-LINE:"))
+            .map(str::to_string)
+            .collect())
+    }
+
+    #[test]
+    fn detects_csv_by_extension_or_config() {
+        let mut config = config();
+        assert!(is_csv("schedule.CSV", &config));
+        assert!(is_csv("https://example.org/sheet.csv?gid=0#top", &config));
+        assert!(!is_csv("calendar.ics", &config));
+        // a CSV export without the extension, or a calendar without a header, are not guessed from their content
+        assert!(!is_csv("https://example.org/pub?output=csv", &config));
+        config.csv_sources = vec!["https://example.org/pub?output=csv".into()];
+        assert!(is_csv("https://example.org/pub?output=csv", &config));
+    }
+
+    #[test]
+    fn times_with_and_without_end() {
+        assert_eq!(
+            event("summary,date,start_time\nTalk,2024-05-14,19:00\n").unwrap(),
+            [
+                "SUMMARY:Talk",
+                "DTSTART;TZID=UTC:20240514T190000",
+                "DTEND;TZID=UTC:20240514T200000"
+            ]
+        );
+        assert_eq!(
+            event("summary,date,start_time,end_time,end_date\nNight,2024-05-14,22:00,02:00,2024-05-15\n").unwrap(),
+            ["SUMMARY:Night", "DTSTART;TZID=UTC:20240514T220000", "DTEND;TZID=UTC:20240515T020000"]
+        );
+        assert_eq!(
+            event("summary,date\nFair,2024-05-14\n").unwrap(),
+            [
+                "SUMMARY:Fair",
+                "DTSTART;VALUE=DATE:20240514",
+                "DTEND;VALUE=DATE:20240515"
+            ]
+        );
+    }
+
+    #[test]
+    fn end_date_without_end_time_lasts_until_the_end_of_that_day() {
+        assert_eq!(
+            event("summary,date,start_time,end_date\nCamp,2024-05-14,18:00,2024-05-16\n").unwrap(),
+            [
+                "SUMMARY:Camp",
+                "DTSTART;TZID=UTC:20240514T180000",
+                "DTEND;TZID=UTC:20240517T000000"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_events_ending_before_they_start() {
+        assert_eq!(
+            event("summary,date,start_time,end_time\nTalk,2024-05-14,19:00,18:00\n").unwrap_err(),
+            "skipped line 2: it ends at 18:00 before it starts at 19:00, an end_date is needed for events \
+             ending the next day"
+        );
+        assert_eq!(
+            event("summary,date,end_date\nFair,2024-05-14,2024-05-13\n").unwrap_err(),
+            "skipped line 2: it ends on 2024-05-13 before it starts on 2024-05-14"
+        );
+    }
+
+    #[test]
+    fn other_columns_become_x_properties() {
+        assert_eq!(
+            event("Summary,Date,Audience,X-Room Size\n\"Talk, Q&A\",2024-05-14,All,40\n").unwrap(),
+            [
+                "SUMMARY:Talk\\, Q&A",
+                "DTSTART;VALUE=DATE:20240514",
+                "DTEND;VALUE=DATE:20240515",
+                "X-AUDIENCE:All",
+                "X-ROOM-SIZE:40",
+            ]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use time::{Date, OffsetDateTime};
+use time_tz::{OffsetDateTimeExt, Tz};
+
+use super::event::{Event, EventContext};
+use super::timezone::start_of_day;
+use crate::config::Config;
+
+/// A single multi-day event placed on a timeline
+///
+/// `offset` and `length` are measured in days from the start of the timeline and are already clipped to its range.
+#[derive(Debug, Serialize)]
+pub struct TimelineEntry {
+    row: usize,
+    offset: i64,
+    length: i64,
+    continues_before: bool,
+    continues_after: bool,
+    /// The start of the event, or the start of the timeline if the event started before
+    clipped_start_timestamp: i64,
+    /// The end of the event, or the end of the timeline if the event ends after
+    clipped_end_timestamp: i64,
+    /// The time slot the visible part starts at on its first day
+    start_slot: u16,
+    /// The time slot the visible part ends before on its last day
+    end_slot: u16,
+    event: EventContext,
+}
+
+/// Layout information for rendering multi-day events as horizontal bars
+#[derive(Debug, Serialize)]
+pub struct TimelineContext {
+    start: String,
+    days: i64,
+    rows: usize,
+    entries: Vec<TimelineEntry>,
+}
+
+impl TimelineContext {
+    /// Lay out all multi-day events overlapping the days from `start` up to (but excluding) `end`
+    ///
+    /// Rows are assigned greedily so that events which do not overlap share a row.
+    pub fn new<'e, I>(
+        start: Date,
+        end: Date,
+        events: I,
+        tz: &Tz,
+        config: &Config,
+    ) -> Result<TimelineContext>
+    where
+        I: IntoIterator<Item = &'e Event>,
+    {
+        let days = (end - start).whole_days();
+        let (timeline_start, timeline_end) = (start_of_day(start, tz)?, start_of_day(end, tz)?);
+        let slots_per_day = config.slots_per_day();
+        let minutes_of_day = |time: OffsetDateTime| {
+            let local = time.to_timezone(tz);
+            local.hour() as u16 * 60 + local.minute() as u16
+        };
+
+        let mut spans: Vec<(i64, i64, &Event)> = events
+            .into_iter()
+            .filter_map(|event| {
+                let (first_day, last_day) = event.first_and_last_day(tz);
+                if last_day <= first_day || last_day < start || first_day >= end {
+                    return None;
+                }
+                let first_offset = (first_day - start).whole_days();
+                let last_offset = (last_day - start).whole_days();
+                Some((first_offset, last_offset, event))
+            })
+            .collect();
+
+        // longer events first so they end up in the upper rows
+        spans.sort_by_key(|(first, last, event)| (*first, -(last - first), event.start()));
+
+        // the last occupied offset of each row
+        let mut row_ends: Vec<i64> = Vec::new();
+        let mut entries = Vec::new();
+
+        for (first, last, event) in spans {
+            let offset = first.max(0);
+            let last_visible = last.min(days - 1);
+
+            let row = match row_ends.iter().position(|row_end| *row_end < offset) {
+                Some(row) => {
+                    row_ends[row] = last_visible;
+                    row
+                }
+                None => {
+                    row_ends.push(last_visible);
+                    row_ends.len() - 1
+                }
+            };
+
+            let continues_before = first < 0;
+            let continues_after = last >= days;
+            let clipped_start = event.start().max(timeline_start);
+            let clipped_end = event.end().min(timeline_end);
+            let start_slot = if continues_before {
+                0
+            } else {
+                minutes_of_day(event.start()) / config.time_slot_minutes
+            };
+            let end_slot = match minutes_of_day(clipped_end) {
+                _ if continues_after => slots_per_day,
+                // an end at midnight fills the last day
+                0 => slots_per_day,
+                minutes => minutes.div_ceil(config.time_slot_minutes),
+            };
+
+            entries.push(TimelineEntry {
+                row,
+                offset,
+                length: last_visible - offset + 1,
+                continues_before,
+                continues_after,
+                clipped_start_timestamp: clipped_start.unix_timestamp(),
+                clipped_end_timestamp: clipped_end.unix_timestamp(),
+                start_slot,
+                end_slot,
+                event: event.context(tz, config),
+            });
+        }
+
+        Ok(TimelineContext {
+            start: start.to_string(),
+            days,
+            rows: row_ends.len(),
+            entries,
+        })
+    }
+}
@@ -0,0 +1,413 @@
+//! The time zones of the date-times in a calendar.
+//!
+//! A `TZID` parameter names the zone of a date-time. Most feeds use IANA names like `Europe/Berlin`, which are looked
+//! up in the time zone database, sometimes behind a prefix like `/freeassociation.sourceforge.net/`. Others, Outlook
+//! among them, use names like `W. Europe Standard Time` and define them with a `VTIMEZONE` component in the calendar.
+//! Those definitions are evaluated here: each `STANDARD` or `DAYLIGHT` observance starts at its `DTSTART` and, with a
+//! yearly `RRULE`, again every year.
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use std::collections::HashMap;
+use time::macros::format_description;
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, UtcOffset, Weekday};
+use time_tz::{timezones::get_by_name, PrimitiveDateTimeExt, TimeZone};
+
+use super::ics::{Component, Property};
+use crate::holidays::nth_weekday;
+
+/// The `VTIMEZONE` definitions of a calendar by their `TZID`
+#[derive(Debug)]
+pub struct TimeZones {
+    definitions: HashMap<String, Vec<Observance>>,
+    /// The zone of floating times, which have neither `Z` nor a `TZID`
+    floating: &'static time_tz::Tz,
+}
+
+/// A `STANDARD` or `DAYLIGHT` part of a `VTIMEZONE`
+#[derive(Debug)]
+struct Observance {
+    /// The local time the observance first starts, in the offset before it
+    start: PrimitiveDateTime,
+    offset_from: UtcOffset,
+    offset_to: UtcOffset,
+    /// When the observance starts again every year
+    rule: Option<YearlyRule>,
+    /// Further local start times, from `RDATE`
+    extra_starts: Vec<PrimitiveDateTime>,
+}
+
+/// A yearly `RRULE` of an observance, e.g. `FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU` for the last Sunday of March
+#[derive(Debug)]
+struct YearlyRule {
+    month: Month,
+    /// The nth weekday of the month, counting from its end if negative
+    nth: i8,
+    weekday: Weekday,
+    /// The days of the month the weekday has to fall on, for rules like `BYMONTHDAY=8,9,10,11,12,13,14;BYDAY=SU`
+    month_days: Vec<u8>,
+    until: Option<PrimitiveDateTime>,
+}
+
+impl TimeZones {
+    /// Reads the `VTIMEZONE` components of a calendar, definitions which cannot be read are left out
+    ///
+    /// Floating times are in the `X-WR-TIMEZONE` of the calendar if it names an IANA zone, else in `default_floating`.
+    pub fn from_calendar(
+        calendar: &Component,
+        default_floating: &'static time_tz::Tz,
+    ) -> TimeZones {
+        let mut definitions = HashMap::new();
+        for time_zone in calendar.components_named("VTIMEZONE") {
+            let Some(tzid) = time_zone
+                .properties
+                .iter()
+                .find(|property| property.name == "TZID")
+                .and_then(|property| property.value)
+            else {
+                continue;
+            };
+            let observances: Vec<Observance> = time_zone
+                .components
+                .iter()
+                .filter(|part| {
+                    part.name.eq_ignore_ascii_case("STANDARD")
+                        || part.name.eq_ignore_ascii_case("DAYLIGHT")
+                })
+                .filter_map(|part| Observance::new(part).ok())
+                .collect();
+            if !observances.is_empty() {
+                definitions.insert(tzid.trim_matches('"').to_string(), observances);
+            }
+        }
+        let floating = calendar
+            .properties
+            .iter()
+            .find(|property| property.name == "X-WR-TIMEZONE")
+            .and_then(|property| property.value)
+            .and_then(|name| iana_time_zone(name.trim().trim_matches('"')))
+            .unwrap_or(default_floating);
+        TimeZones {
+            definitions,
+            floating,
+        }
+    }
+
+    /// Returns the instant of the local `time` in the zone named `tzid`
+    ///
+    /// IANA names are preferred, as the database knows the history of a zone, a `VTIMEZONE` of the calendar is used
+    /// for the other names.
+    pub fn resolve(&self, tzid: &str, time: PrimitiveDateTime) -> Result<OffsetDateTime> {
+        let tzid = tzid.trim_matches('"');
+        if let Some(time_zone) = iana_time_zone(tzid) {
+            return assume_time_zone(time, time_zone);
+        }
+        match self.definitions.get(tzid) {
+            Some(observances) => Ok(time.assume_offset(offset_at(observances, time))),
+            None => bail!("unknown time zone {:?} without a VTIMEZONE", tzid),
+        }
+    }
+
+    /// Returns the instant of a floating local `time`, one without `Z` or `TZID`
+    pub fn resolve_floating(&self, time: PrimitiveDateTime) -> Result<OffsetDateTime> {
+        assume_time_zone(time, self.floating)
+    }
+}
+
+/// Returns the start of `date` in an IANA zone, which is an hour after midnight where a change to summer time skips
+/// midnight, as in America/Santiago
+pub fn start_of_day(date: Date, time_zone: &time_tz::Tz) -> Result<OffsetDateTime> {
+    assume_time_zone(date.midnight(), time_zone)
+}
+
+/// Returns the instant of the local `time` in an IANA zone
+fn assume_time_zone(time: PrimitiveDateTime, time_zone: &time_tz::Tz) -> Result<OffsetDateTime> {
+    // a time skipped by a change to summer time is moved forward, like the clocks
+    time.assume_timezone(time_zone)
+        .take_first()
+        .or_else(|| {
+            (time + Duration::HOUR)
+                .assume_timezone(time_zone)
+                .take_first()
+        })
+        .ok_or_else(|| eyre!("{} does not exist in {}", time, time_zone.name()))
+}
+
+/// Returns the IANA name of a `TZID`, `None` if it is only defined by a `VTIMEZONE` of the calendar
+pub fn iana_name(tzid: &str) -> Option<&'static str> {
+    iana_time_zone(tzid.trim_matches('"')).map(|time_zone| time_zone.name())
+}
+
+/// Looks up an IANA time zone, also behind a prefix like in `/mozilla.org/20050126_1/Europe/Berlin`
+fn iana_time_zone(tzid: &str) -> Option<&'static time_tz::Tz> {
+    get_by_name(tzid).or_else(|| {
+        tzid.match_indices('/')
+            .find_map(|(index, _)| get_by_name(&tzid[index + 1..]).filter(|_| index > 0))
+    })
+}
+
+/// Returns the offset in effect at the local `time`: the one of the observance which started last before it
+fn offset_at(observances: &[Observance], time: PrimitiveDateTime) -> UtcOffset {
+    observances
+        .iter()
+        .filter_map(|observance| {
+            observance
+                .last_start_before(time)
+                .map(|start| (start, observance.offset_to))
+        })
+        .max_by_key(|(start, _)| *start)
+        .map(|(_, offset)| offset)
+        // before the first observance, the offset it changes from
+        .or_else(|| {
+            observances
+                .iter()
+                .min_by_key(|observance| observance.start)
+                .map(|observance| observance.offset_from)
+        })
+        .unwrap_or(UtcOffset::UTC)
+}
+
+impl Observance {
+    fn new(part: &Component) -> Result<Observance> {
+        let property = |name: &str| {
+            part.properties
+                .iter()
+                .find(|property| property.name == name)
+                .and_then(|property| property.value)
+        };
+        let start = local_time(property("DTSTART").ok_or_else(|| eyre!("no DTSTART"))?)?;
+        let offset_from =
+            parse_offset(property("TZOFFSETFROM").ok_or_else(|| eyre!("no TZOFFSETFROM"))?)?;
+        let offset_to =
+            parse_offset(property("TZOFFSETTO").ok_or_else(|| eyre!("no TZOFFSETTO"))?)?;
+        let rule = property("RRULE").map(YearlyRule::parse).transpose()?;
+        let extra_starts = part
+            .properties
+            .iter()
+            .filter(|property| property.name == "RDATE")
+            .flat_map(|property: &Property| property.value.into_iter().flat_map(|v| v.split(',')))
+            .filter_map(|value| local_time(value).ok())
+            .collect();
+        Ok(Observance {
+            start,
+            offset_from,
+            offset_to,
+            rule,
+            extra_starts,
+        })
+    }
+
+    /// Returns the last local time the observance started at or before `time`
+    fn last_start_before(&self, time: PrimitiveDateTime) -> Option<PrimitiveDateTime> {
+        let yearly = self.rule.iter().flat_map(|rule| {
+            // the start of this year, or if that is still ahead, the one of the year before
+            [time.year(), time.year() - 1]
+                .into_iter()
+                .filter_map(|year| rule.start_in(year, self.start))
+        });
+        std::iter::once(self.start)
+            .chain(self.extra_starts.iter().copied())
+            .chain(yearly)
+            .filter(|start| *start >= self.start && *start <= time)
+            .max()
+    }
+}
+
+impl YearlyRule {
+    fn parse(rule: &str) -> Result<YearlyRule> {
+        let mut month = None;
+        let mut by_day = None;
+        let mut month_days = Vec::new();
+        let mut until = None;
+        for part in rule.split(';') {
+            let (name, value) = part.split_once('=').unwrap_or((part, ""));
+            match name {
+                "FREQ" if value != "YEARLY" => bail!("only yearly time zone rules are supported"),
+                "BYMONTH" => month = Some(Month::try_from(value.parse::<u8>()?)?),
+                "BYDAY" => by_day = Some(value),
+                "BYMONTHDAY" => {
+                    month_days = value.split(',').map(str::parse).collect::<Result<_, _>>()?
+                }
+                "UNTIL" => until = Some(local_time(value)?),
+                _ => {}
+            }
+        }
+        let by_day = by_day.ok_or_else(|| eyre!("no BYDAY in {:?}", rule))?;
+        let weekday = match &by_day[by_day.len().saturating_sub(2)..] {
+            "MO" => Weekday::Monday,
+            "TU" => Weekday::Tuesday,
+            "WE" => Weekday::Wednesday,
+            "TH" => Weekday::Thursday,
+            "FR" => Weekday::Friday,
+            "SA" => Weekday::Saturday,
+            "SU" => Weekday::Sunday,
+            _ => bail!("invalid BYDAY in {:?}", rule),
+        };
+        let nth = match &by_day[..by_day.len() - 2] {
+            "" => 1,
+            nth => nth.trim_start_matches('+').parse()?,
+        };
+        Ok(YearlyRule {
+            month: month.ok_or_else(|| eyre!("no BYMONTH in {:?}", rule))?,
+            nth,
+            weekday,
+            month_days,
+            until,
+        })
+    }
+
+    /// Returns the local time the observance starts in `year`, at the time of day of `first_start`
+    fn start_in(&self, year: i32, first_start: PrimitiveDateTime) -> Option<PrimitiveDateTime> {
+        let date = if self.month_days.is_empty() {
+            nth_weekday(year, self.month, self.nth, self.weekday).ok()?
+        } else {
+            self.month_days.iter().find_map(|day| {
+                time::Date::from_calendar_date(year, self.month, *day)
+                    .ok()
+                    .filter(|date| date.weekday() == self.weekday)
+            })?
+        };
+        let start = date.with_time(first_start.time());
+        self.until
+            .is_none_or(|until| start <= until)
+            .then_some(start)
+    }
+}
+
+/// Parses a local date-time like `19701025T030000`, a trailing `Z` is ignored
+fn local_time(value: &str) -> Result<PrimitiveDateTime> {
+    PrimitiveDateTime::parse(
+        value.trim().trim_end_matches('Z'),
+        format_description!("[year][month][day]T[hour][minute][second]"),
+    )
+    .wrap_err(format!("could not parse time {:?}", value))
+}
+
+/// Parses a UTC offset like `+0200` or `-053000`
+fn parse_offset(value: &str) -> Result<UtcOffset> {
+    let value = value.trim();
+    let (sign, digits) = match value.split_at_checked(1) {
+        Some(("-", digits)) => (-1, digits),
+        Some(("+", digits)) => (1, digits),
+        _ => bail!("invalid UTC offset {:?}", value),
+    };
+    let number = |range: std::ops::Range<usize>| -> Result<i8> {
+        Ok(digits.get(range).map_or(Ok(0), str::parse)?)
+    };
+    if digits.len() < 4 {
+        bail!("invalid UTC offset {:?}", value);
+    }
+    Ok(UtcOffset::from_hms(
+        sign * number(0..2)?,
+        sign * number(2..4)?,
+        sign * number(4..6)?,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use time::macros::{date, datetime, offset};
+
+    use super::*;
+    use crate::model::ics;
+
+    /// A zone like Outlook's `W. Europe Standard Time`, and an older US zone with its second Sunday in March written as
+    /// the Sunday among the 8th to the 14th
+    const VTIMEZONES: &str = "BEGIN:VCALENDAR\r\n\
+        BEGIN:VTIMEZONE\r\nTZID:W. Europe Standard Time\r\n\
+        BEGIN:STANDARD\r\nDTSTART:16010101T030000\r\nTZOFFSETFROM:+0200\r\nTZOFFSETTO:+0100\r\n\
+        RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10\r\nEND:STANDARD\r\n\
+        BEGIN:DAYLIGHT\r\nDTSTART:16010101T020000\r\nTZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\n\
+        RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3\r\nEND:DAYLIGHT\r\nEND:VTIMEZONE\r\n\
+        BEGIN:VTIMEZONE\r\nTZID:\"Eastern\"\r\n\
+        BEGIN:STANDARD\r\nDTSTART:20071104T020000\r\nTZOFFSETFROM:-0400\r\nTZOFFSETTO:-0500\r\n\
+        RRULE:FREQ=YEARLY;BYMONTH=11;BYDAY=1SU\r\nEND:STANDARD\r\n\
+        BEGIN:DAYLIGHT\r\nDTSTART:20070311T020000\r\nTZOFFSETFROM:-0500\r\nTZOFFSETTO:-0400\r\n\
+        RRULE:FREQ=YEARLY;BYMONTH=3;BYMONTHDAY=8,9,10,11,12,13,14;BYDAY=SU;UNTIL=20300310T070000Z\r\n\
+        END:DAYLIGHT\r\nEND:VTIMEZONE\r\nEND:VCALENDAR\r\n";
+
+    fn time_zones() -> TimeZones {
+        let lines = ics::unfold(VTIMEZONES);
+        let calendars = ics::parse_components(&lines, "test.ics");
+        TimeZones::from_calendar(&calendars[0], time_tz::timezones::db::UTC)
+    }
+
+    fn offset_in(time_zones: &TimeZones, tzid: &str, time: PrimitiveDateTime) -> UtcOffset {
+        time_zones.resolve(tzid, time).unwrap().offset()
+    }
+
+    #[test]
+    fn yearly_rules_find_the_start_in_each_year() {
+        let first_start = datetime!(1601-01-01 02:00);
+        let last_sunday = YearlyRule::parse("FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3").unwrap();
+        assert_eq!(
+            last_sunday.start_in(2024, first_start),
+            Some(datetime!(2024-03-31 02:00))
+        );
+        assert_eq!(
+            last_sunday.start_in(2026, first_start),
+            Some(datetime!(2026-03-29 02:00))
+        );
+        let second_sunday = YearlyRule::parse(
+            "FREQ=YEARLY;BYMONTH=3;BYMONTHDAY=8,9,10,11,12,13,14;BYDAY=SU;UNTIL=20250101T000000Z",
+        )
+        .unwrap();
+        assert_eq!(
+            second_sunday.start_in(2024, first_start),
+            Some(datetime!(2024-03-10 02:00))
+        );
+        // the rule ended before this start
+        assert_eq!(second_sunday.start_in(2025, first_start), None);
+        assert!(YearlyRule::parse("FREQ=MONTHLY;BYDAY=-1SU;BYMONTH=3").is_err());
+        assert!(YearlyRule::parse("FREQ=YEARLY;BYMONTH=3").is_err());
+        assert!(YearlyRule::parse("FREQ=YEARLY;BYDAY=-1XX;BYMONTH=3").is_err());
+    }
+
+    #[test]
+    fn offsets_follow_the_observances_of_a_vtimezone() {
+        let time_zones = time_zones();
+        let europe = |time| offset_in(&time_zones, "W. Europe Standard Time", time);
+        assert_eq!(europe(datetime!(2026-01-15 12:00)), offset!(+1));
+        assert_eq!(europe(datetime!(2026-03-29 01:59)), offset!(+1));
+        assert_eq!(europe(datetime!(2026-03-29 02:00)), offset!(+2));
+        assert_eq!(europe(datetime!(2026-07-01 12:00)), offset!(+2));
+        assert_eq!(europe(datetime!(2026-10-25 02:59)), offset!(+2));
+        assert_eq!(europe(datetime!(2026-10-25 03:00)), offset!(+1));
+        assert_eq!(europe(datetime!(2026-12-31 23:00)), offset!(+1));
+
+        let eastern = |time| offset_in(&time_zones, "Eastern", time);
+        assert_eq!(eastern(datetime!(2026-03-07 12:00)), offset!(-5));
+        assert_eq!(eastern(datetime!(2026-03-08 12:00)), offset!(-4));
+        assert_eq!(eastern(datetime!(2026-11-01 01:00)), offset!(-4));
+        assert_eq!(eastern(datetime!(2026-11-01 03:00)), offset!(-5));
+        // before the first observance, the offset it changes from
+        assert_eq!(eastern(datetime!(2007-01-01 12:00)), offset!(-5));
+        // after its UNTIL, daylight time does not start anymore
+        assert_eq!(eastern(datetime!(2031-07-01 12:00)), offset!(-5));
+    }
+
+    #[test]
+    fn iana_names_win_over_vtimezones() {
+        let time_zones = time_zones();
+        assert_eq!(
+            offset_in(&time_zones, "Europe/Berlin", datetime!(2026-07-01 12:00)),
+            offset!(+2)
+        );
+        assert!(time_zones
+            .resolve("Mars Standard Time", datetime!(2026-07-01 12:00))
+            .is_err());
+    }
+
+    #[test]
+    fn a_day_starts_after_a_skipped_midnight() {
+        let santiago = get_by_name("America/Santiago").unwrap();
+        assert_eq!(
+            start_of_day(date!(2026 - 09 - 06), santiago).unwrap(),
+            datetime!(2026-09-06 01:00 -3)
+        );
+        assert_eq!(
+            start_of_day(date!(2026 - 09 - 07), santiago).unwrap(),
+            datetime!(2026-09-07 00:00 -3)
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use serde::Serialize;
+use time::ext::NumericalDuration;
+use time::util::days_in_year_month;
+use time::{Date, Month};
+
+/// A day of a miniature month on a year page
+#[derive(Debug, Serialize)]
+pub struct MiniDayContext {
+    pub(crate) day: u8,
+    pub(crate) date: String,
+    pub(crate) events: usize,
+    pub(crate) has_events: bool,
+    /// The day page, relative to the year page, if the day has one
+    pub(crate) link: Option<String>,
+}
+
+/// A month in miniature on a year page, only telling which days have events
+#[derive(Debug, Serialize)]
+pub struct MiniMonthContext {
+    pub(crate) month: u8,
+    pub(crate) name: String,
+    pub(crate) events: usize,
+    /// The month page, relative to the year page, if the month has one
+    pub(crate) link: Option<String>,
+    /// The weeks from Sunday to Saturday, the days of the adjacent months are left empty
+    pub(crate) weeks: Vec<Vec<Option<MiniDayContext>>>,
+}
+
+impl MiniMonthContext {
+    /// Lays out a month, `day` returns the number of events of a date and the link to its day page
+    pub fn new(
+        year: i32,
+        month: Month,
+        link: Option<String>,
+        mut day: impl FnMut(Date) -> (usize, Option<String>),
+    ) -> time::Result<MiniMonthContext> {
+        let first = Date::from_calendar_date(year, month, 1)?;
+        let mut cells: Vec<Option<MiniDayContext>> = (0..first.weekday().number_days_from_sunday())
+            .map(|_| None)
+            .collect();
+        for offset in 0..days_in_year_month(year, month) {
+            let date = first + (offset as i64).days();
+            let (events, link) = day(date);
+            cells.push(Some(MiniDayContext {
+                day: date.day(),
+                date: date.to_string(),
+                events,
+                has_events: events > 0,
+                link,
+            }));
+        }
+        while !cells.len().is_multiple_of(7) {
+            cells.push(None);
+        }
+
+        let mut weeks = Vec::new();
+        while !cells.is_empty() {
+            let rest = cells.split_off(7);
+            weeks.push(cells);
+            cells = rest;
+        }
+        Ok(MiniMonthContext {
+            month: month as u8,
+            name: month.to_string(),
+            events: weeks
+                .iter()
+                .flatten()
+                .flatten()
+                .map(|day: &MiniDayContext| day.events)
+                .sum(),
+            link,
+            weeks,
+        })
+    }
+}
@@ -0,0 +1,60 @@
+//! The phase of the moon on each day, for fishing and astronomy calendars.
+//!
+//! The phases follow the mean lunation from a known new moon, which puts the principal phases within a day of the
+//! true ones.
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+/// The length of the mean synodic month in days
+const SYNODIC_MONTH: f64 = 29.530_588_853;
+/// A new moon, January 6 2000 at 18:14 UTC, as a Julian day
+const NEW_MOON: f64 = 2_451_550.1;
+/// The Julian day of the Unix epoch
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2_440_587.5;
+
+/// The phase of the moon on a day
+///
+/// A day gets one of the four principal phases if the moon reaches it during the day, else the phase between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MoonPhase {
+    NewMoon,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    FullMoon,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+impl MoonPhase {
+    /// Returns the phase of the day from `start` to `end`
+    pub fn of_day(start: OffsetDateTime, end: OffsetDateTime) -> MoonPhase {
+        let age = lunation_fraction(start);
+        let end_age = age + (end - start).as_seconds_f64() / 86_400.0 / SYNODIC_MONTH;
+        let principal = [
+            (0.0, MoonPhase::NewMoon),
+            (0.25, MoonPhase::FirstQuarter),
+            (0.5, MoonPhase::FullMoon),
+            (0.75, MoonPhase::LastQuarter),
+            (1.0, MoonPhase::NewMoon),
+        ];
+        if let Some((_, phase)) = principal.iter().find(|(at, _)| (age..end_age).contains(at)) {
+            return *phase;
+        }
+        match age {
+            age if age < 0.25 => MoonPhase::WaxingCrescent,
+            age if age < 0.5 => MoonPhase::WaxingGibbous,
+            age if age < 0.75 => MoonPhase::WaningGibbous,
+            _ => MoonPhase::WaningCrescent,
+        }
+    }
+}
+
+/// Returns how far the moon is through its current lunation at `time`, from 0 at new moon to just below 1
+fn lunation_fraction(time: OffsetDateTime) -> f64 {
+    let julian_day = time.unix_timestamp() as f64 / 86_400.0 + UNIX_EPOCH_JULIAN_DAY;
+    ((julian_day - NEW_MOON) / SYNODIC_MONTH).rem_euclid(1.0)
+}
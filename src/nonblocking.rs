@@ -0,0 +1,229 @@
+//! An async API for applications which run statical inside a tokio runtime, e.g. to regenerate on a webhook.
+//!
+//! Sources are fetched and files are written without blocking the runtime threads, parsing and rendering run on
+//! the blocking thread pool. Only available with the `tokio` feature.
+
+use color_eyre::eyre::{eyre, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::time::Instant;
+use time::OffsetDateTime;
+use tokio::fs;
+use tokio::task::{self, JoinSet};
+
+use crate::build;
+use crate::changes::{self, EventChange};
+use crate::config::Config;
+use crate::log;
+use crate::manifest::{EventRecord, Manifest};
+use crate::model::calendar_collection::{
+    sample_source, CalendarCollection, RenderedFiles, SourceData, SourceLocation,
+};
+use crate::options::Opt;
+use crate::report::{BuildReport, PhaseTiming, SourceSummary, UidConflict};
+
+/// What an async build has written
+#[derive(Debug, Clone, Default)]
+pub struct BuildOutput {
+    /// Every file written by the build
+    pub written_files: BTreeSet<PathBuf>,
+    /// The path of every period page written, keyed by view and period
+    pub pages: BTreeMap<String, PathBuf>,
+    /// The views which were rendered
+    pub completed_views: BTreeSet<String>,
+    /// The event instances of the build, keyed by their instance id
+    pub events: BTreeMap<String, EventRecord>,
+    /// The latest changes to upcoming events, newest first
+    pub changes: Vec<EventChange>,
+    /// Fetch and parse statistics for each calendar source
+    pub source_summaries: Vec<SourceSummary>,
+    /// Events which several sources publish with the same UID but different content
+    pub uid_conflicts: Vec<UidConflict>,
+    /// How long each phase of the build took, in the order they ran
+    pub timings: Vec<PhaseTiming>,
+}
+
+/// Reads the calendar files, urls, commands, and sample data requested in `args`, fetching all of them concurrently
+///
+/// Urls which cannot be fetched are read from `cache_dir` if they were cached before, like the blocking build does.
+pub async fn read_sources(args: &Opt, cache_dir: &str) -> Result<Vec<SourceData>> {
+    let mut reads = JoinSet::new();
+
+    for (index, file) in args.file.iter().flatten().enumerate() {
+        let file = file.clone();
+        reads.spawn(async move {
+            let started = Instant::now();
+            let ics = if fs::try_exists(&file).await.unwrap_or(false) {
+                Some(
+                    fs::read_to_string(&file)
+                        .await
+                        .wrap_err(format!("could not read {:?}", file))?,
+                )
+            } else {
+                None
+            };
+            let data = SourceData {
+                source: file.to_string_lossy().to_string(),
+                ics,
+                stale: false,
+            };
+            log::source_read(&data, started);
+            Ok::<_, color_eyre::Report>((index, data))
+        });
+    }
+
+    let file_count = args.file.iter().flatten().count();
+    for (index, url) in args.url.iter().flatten().enumerate() {
+        let url = url.clone();
+        let cache_dir = cache_dir.to_string();
+        // ureq is blocking, so the requests and the cache are handled on the blocking thread pool
+        reads.spawn(async move {
+            let started = Instant::now();
+            let data = task::spawn_blocking(move || -> Result<SourceData> {
+                SourceLocation::Url(url).read_with_cache(&cache_dir)
+            })
+            .await??;
+            log::source_read(&data, started);
+            Ok((file_count + index, data))
+        });
+    }
+
+    let url_count = args.url.iter().flatten().count();
+    for (index, command) in args.exec.iter().flatten().enumerate() {
+        let command = command.clone();
+        reads.spawn(async move {
+            let started = Instant::now();
+            let data = task::spawn_blocking(move || -> Result<SourceData> {
+                SourceLocation::Command(command).read()
+            })
+            .await??;
+            log::source_read(&data, started);
+            Ok((file_count + url_count + index, data))
+        });
+    }
+
+    // keep the order of the command line, like the blocking build does
+    let mut sources = Vec::new();
+    while let Some(read) = reads.join_next().await {
+        sources.push(read??);
+    }
+    sources.sort_by_key(|(index, _)| *index);
+    let mut sources: Vec<SourceData> = sources.into_iter().map(|(_, source)| source).collect();
+
+    if args.sample_data {
+        sources.push(sample_source());
+    }
+    Ok(sources)
+}
+
+/// Writes files concurrently, creating their directories as needed
+pub async fn write_files(files: RenderedFiles) -> Result<()> {
+    let mut writes = JoinSet::new();
+    for (path, contents) in files {
+        writes.spawn(async move {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .wrap_err(format!("could not create {:?}", parent))?;
+            }
+            fs::write(&path, contents)
+                .await
+                .wrap_err(format!("could not write {:?}", path))
+        });
+    }
+    while let Some(write) = writes.join_next().await {
+        write??;
+    }
+    Ok(())
+}
+
+/// Builds the site for `args` and `config`, like the command line does without `--resume` or the checks
+///
+/// The manifest and build report are saved into the output directory as well.
+pub async fn build(args: Opt, config: Config) -> Result<BuildOutput> {
+    let fetch_started = Instant::now();
+    let sources = read_sources(&args, &config.source_cache_dir).await?;
+    let fetch_timing = PhaseTiming::since("fetch", fetch_started);
+    let output_dir = PathBuf::from(&config.output_dir);
+
+    let (files, mut output) = task::spawn_blocking(move || render(sources, &config))
+        .await
+        .map_err(|error| eyre!("rendering failed: {}", error))??;
+    let write_started = Instant::now();
+    write_files(files).await?;
+    output.timings.insert(0, fetch_timing);
+    output
+        .timings
+        .push(PhaseTiming::since("write", write_started));
+
+    let mut manifest = Manifest::default();
+    manifest.record_files(&output_dir, output.written_files.iter().cloned());
+    manifest.pages = output.pages.clone();
+    manifest.completed_views = output.completed_views.clone();
+    manifest.events = output.events.clone();
+    manifest.changes = output.changes.clone();
+    let report = BuildReport {
+        sources: output.source_summaries.clone(),
+        uid_conflicts: output.uid_conflicts.clone(),
+        timings: output.timings.clone(),
+    };
+    task::spawn_blocking(move || -> Result<()> {
+        // the site is not uploaded or deployed here, the hashes of the last ones stay valid for the next ones
+        let previous_manifest = Manifest::load(&output_dir)?.unwrap_or_default();
+        manifest.uploaded = previous_manifest.uploaded;
+        manifest.deployed = previous_manifest.deployed;
+        manifest.save(&output_dir)?;
+        report.save(&output_dir)
+    })
+    .await??;
+
+    Ok(output)
+}
+
+/// Renders every enabled view and the redirects into memory
+fn render(sources: Vec<SourceData>, config: &Config) -> Result<(RenderedFiles, BuildOutput)> {
+    let previous_manifest = Manifest::load(&PathBuf::from(&config.output_dir))?.unwrap_or_default();
+    let mut collection = CalendarCollection::from_sources(sources, config)?;
+    collection.defer_writes();
+    collection.setup_output_dir()?;
+
+    let mut completed_views = BTreeSet::new();
+    let mut timings = collection.timings().to_vec();
+    for (view, enabled, create_pages) in build::views(config) {
+        if enabled {
+            let view_started = Instant::now();
+            create_pages(&collection)?;
+            timings.push(PhaseTiming::since(&format!("view:{}", view), view_started));
+            completed_views.insert(view.to_string());
+        }
+    }
+    let events = collection.event_records();
+    let latest_changes = changes::compare_events(
+        &previous_manifest.events,
+        &events,
+        OffsetDateTime::now_utc(),
+    );
+    let changes =
+        changes::detect_changes(&previous_manifest, &latest_changes, config.feed_max_entries);
+    if config.render_feed {
+        collection.create_changes_feed(&changes)?;
+    }
+    if config.render_changes_ics {
+        collection.create_changes_ics(&latest_changes)?;
+    }
+    collection.create_landing_page()?;
+    collection.create_alias_pages()?;
+    collection.create_redirect_pages(&previous_manifest.pages)?;
+
+    let output = BuildOutput {
+        written_files: collection.written_files(),
+        pages: collection.pages(),
+        completed_views,
+        events,
+        changes,
+        source_summaries: collection.source_summaries().to_vec(),
+        uid_conflicts: collection.uid_conflicts().to_vec(),
+        timings,
+    };
+    Ok((collection.take_deferred_files(), output))
+}
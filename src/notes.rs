@@ -0,0 +1,86 @@
+//! Content written by editors: per-day notes, e.g. `content/2024-05-14.md` for an announcement on May 14th, and the
+//! site-wide announcement.
+//!
+//! The Markdown is rendered to HTML once and attached to the matching days of every view, or to every page for the
+//! announcement.
+
+use color_eyre::eyre::{Context, Result};
+use pulldown_cmark::{html, Options, Parser};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use time::{macros::format_description, Date};
+use tracing::warn;
+
+use crate::config::Config;
+
+/// Reads and renders the notes in `dir`, keyed by their day
+///
+/// Only files named `YYYY-MM-DD.md` are read, other files are skipped. A missing directory has no notes.
+pub fn load_notes(dir: &Path) -> Result<BTreeMap<Date, String>> {
+    let mut notes = BTreeMap::new();
+    if !dir.is_dir() {
+        return Ok(notes);
+    }
+
+    for entry in fs::read_dir(dir).context(format!("could not read notes from {:?}", dir))? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "md") {
+            continue;
+        }
+        let date = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| Date::parse(stem, format_description!("[year]-[month]-[day]")).ok());
+        let Some(date) = date else {
+            warn!(
+                "Skipping note {:?}, it is not named after a day (YYYY-MM-DD.md)",
+                path
+            );
+            continue;
+        };
+        let markdown =
+            fs::read_to_string(&path).context(format!("could not read note {:?}", path))?;
+        notes.insert(date, render_markdown(&markdown));
+    }
+    Ok(notes)
+}
+
+/// Reads and renders the announcement if one is configured and `today` is within its dates
+///
+/// `announcement_file` takes precedence over the `announcement` text.
+pub fn load_announcement(config: &Config, today: Date) -> Result<Option<String>> {
+    let parse_date = |date: &str, name: &str| -> Result<Option<Date>> {
+        if date.is_empty() {
+            return Ok(None);
+        }
+        Date::parse(date, format_description!("[year]-[month]-[day]"))
+            .map(Some)
+            .context(format!(
+                "{} must be a date like 2024-05-14, got {:?}",
+                name, date
+            ))
+    };
+    let start = parse_date(&config.announcement_start, "announcement_start")?;
+    let end = parse_date(&config.announcement_end, "announcement_end")?;
+    if start.is_some_and(|start| today < start) || end.is_some_and(|end| today > end) {
+        return Ok(None);
+    }
+
+    let markdown = if config.announcement_file.is_empty() {
+        config.announcement.clone()
+    } else {
+        fs::read_to_string(&config.announcement_file).context(format!(
+            "could not read announcement {:?}",
+            config.announcement_file
+        ))?
+    };
+    Ok((!markdown.trim().is_empty()).then(|| render_markdown(&markdown)))
+}
+
+/// Renders Markdown, with the common extensions like tables and strikethrough enabled, to HTML
+pub fn render_markdown(markdown: &str) -> String {
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, Parser::new_ext(markdown, Options::all()));
+    rendered
+}
@@ -1,19 +1,248 @@
-use clap::Parser;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use color_eyre::eyre::{self, WrapErr};
+use std::io::{Read, Write};
 use std::path::PathBuf;
 
+use crate::config::Config;
+use crate::dry_run::DryRunFormat;
+use crate::log::LogFormat;
+
 /// Command line options
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 pub struct Opt {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// The config file to read
-    #[clap(short, long, default_value_t = String::from("statical.toml"))]
+    #[clap(short, long, default_value_t = String::from("statical.toml"), env = "STATICAL_CONFIG", global = true)]
     pub config: String,
 
     /// The calendar files to read
-    #[clap(short, long)]
+    #[clap(
+        short,
+        long,
+        env = "STATICAL_FILE",
+        value_delimiter = ',',
+        global = true
+    )]
     pub file: Option<Vec<PathBuf>>,
 
     /// The calendar urls to read
-    #[clap(short, long)]
+    #[clap(
+        short,
+        long,
+        env = "STATICAL_URL",
+        value_delimiter = ',',
+        global = true
+    )]
     pub url: Option<Vec<String>>,
+
+    /// Shell commands printing calendars to read, for systems without an ICS export
+    #[clap(short, long, env = "STATICAL_EXEC", global = true)]
+    pub exec: Option<Vec<String>>,
+
+    /// Render a generated sample calendar instead of real sources, useful when developing templates
+    #[clap(long, env = "STATICAL_SAMPLE_DATA", global = true)]
+    pub sample_data: bool,
+
+    /// Resume a failed build, skipping the views it already completed
+    #[clap(long, env = "STATICAL_RESUME", global = true)]
+    pub resume: bool,
+
+    /// Only print the files the build would write with their number of events, as `text` or `json`, without writing
+    /// anything
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "FORMAT",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "text",
+        env = "STATICAL_DRY_RUN",
+        global = true
+    )]
+    pub dry_run: Option<DryRunFormat>,
+
+    /// Fail the build if a generated page links to a page which was not generated
+    #[clap(long, env = "STATICAL_CHECK_LINKS", global = true)]
+    pub check_links: bool,
+
+    /// The directory to write the site to, overriding `output_dir` from the config file
+    #[clap(long, env = "STATICAL_OUTPUT_DIR", global = true)]
+    pub output_dir: Option<String>,
+
+    /// The timezone to display events in, overriding `display_timezone` from the config file
+    #[clap(long, env = "STATICAL_TIMEZONE", global = true)]
+    pub timezone: Option<String>,
+
+    /// The directory to load the templates from, overriding `templates_dir` from the config file
+    #[clap(long, env = "STATICAL_TEMPLATES_DIR", global = true)]
+    pub templates_dir: Option<String>,
+
+    /// The first day (yyyy-mm-dd) to keep events and expand recurring events from, overriding the config file
+    #[clap(long, env = "STATICAL_START_DATE", global = true)]
+    pub start_date: Option<String>,
+
+    /// The last day (yyyy-mm-dd) to keep events and expand recurring events to, overriding the config file
+    #[clap(long, env = "STATICAL_END_DATE", global = true)]
+    pub end_date: Option<String>,
+
+    /// Start the range this many months before the current month, instead of a start date
+    #[clap(long, env = "STATICAL_MONTHS_BACK", global = true)]
+    pub months_back: Option<u32>,
+
+    /// End the range this many months after the current month, instead of an end date
+    #[clap(long, env = "STATICAL_MONTHS_AHEAD", global = true)]
+    pub months_ahead: Option<u32>,
+
+    /// Also write structured log events to stderr, as JSON lines with `json`
+    #[clap(long, arg_enum, default_value_t = LogFormat::Text, env = "STATICAL_LOG_FORMAT", global = true)]
+    pub log_format: LogFormat,
+
+    /// Show each written page, and with `-vv` each event
+    #[clap(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only show warnings and errors
+    #[clap(
+        short,
+        long,
+        conflicts_with = "verbose",
+        env = "STATICAL_QUIET",
+        global = true
+    )]
+    pub quiet: bool,
+}
+
+impl Opt {
+    /// Reads the config file, writing one with the defaults if it does not exist, and merges it with the flags
+    ///
+    /// Flags given on the command line win over the config file. The calendar sources of the config file are only
+    /// used when no `--file`, `--url`, `--exec` or `--sample-data` was given.
+    pub fn load_config(&mut self) -> eyre::Result<Config> {
+        let mut config: Config = if let Ok(mut config_file) = std::fs::File::open(&self.config) {
+            let mut config_raw = String::new();
+            config_file.read_to_string(&mut config_raw)?;
+            toml_edit::easy::from_str(&config_raw)
+                .wrap_err_with(|| format!("could not parse the config file {}", self.config))?
+        } else {
+            let config = Default::default();
+            if let Ok(mut config_file) = std::fs::File::create(&self.config) {
+                if let Ok(config_raw) = toml_edit::easy::to_string_pretty(&config) {
+                    config_file.write_all(config_raw.as_bytes()).ok();
+                }
+            }
+            config
+        };
+
+        if let Some(output_dir) = &self.output_dir {
+            config.output_dir = output_dir.clone();
+        }
+        if let Some(timezone) = &self.timezone {
+            config.display_timezone = timezone.clone();
+        }
+        if let Some(templates_dir) = &self.templates_dir {
+            config.templates_dir = templates_dir.clone();
+        }
+        if let Some(months_back) = self.months_back {
+            config.months_back = Some(months_back);
+            config.start_date.clear();
+        }
+        if let Some(months_ahead) = self.months_ahead {
+            config.months_ahead = Some(months_ahead);
+            config.end_date.clear();
+        }
+        if let Some(start_date) = &self.start_date {
+            config.start_date = start_date.clone();
+        }
+        if let Some(end_date) = &self.end_date {
+            config.end_date = end_date.clone();
+        }
+
+        if self.file.is_none() && self.url.is_none() && self.exec.is_none() && !self.sample_data {
+            if !config.calendar_files.is_empty() {
+                self.file = Some(config.calendar_files.iter().map(PathBuf::from).collect());
+            }
+            if !config.calendar_urls.is_empty() {
+                self.url = Some(config.calendar_urls.clone());
+            }
+            if !config.calendar_commands.is_empty() {
+                self.exec = Some(config.calendar_commands.clone());
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Writes the completion script of `shell` for every flag and subcommand to stdout
+pub fn print_completions(shell: Shell) {
+    clap_complete::generate(
+        shell,
+        &mut Opt::command(),
+        env!("CARGO_PKG_NAME"),
+        &mut std::io::stdout(),
+    );
+}
+
+/// The commands, `build` if none is given
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Build the site into the output directory
+    Build,
+    /// Create a starter config, templates, and sample calendar to build a first site from
+    Init {
+        /// The directory to create the site in
+        #[clap(default_value_t = String::from("."))]
+        dir: String,
+    },
+    /// Read and parse the sources and report their problems, without writing anything
+    Check,
+    /// Remove the files written by the last build from the output directory
+    Clean,
+    /// Build the site and deploy the changed files to the configured `deploy_target`
+    Deploy,
+    /// Build the site and serve the output directory to preview it
+    Serve {
+        /// The address to serve on
+        #[clap(long, default_value_t = String::from("127.0.0.1:8000"), env = "STATICAL_LISTEN")]
+        listen: String,
+    },
+    /// Check that the templates load and render with sample data, without touching the output directory
+    CheckTemplates,
+    /// Stay resident, rebuilding on the configured schedule and serving a health endpoint
+    Daemon,
+    /// Print the events and pages which were added, removed, or changed between two builds
+    Diff {
+        /// The output directory of the older build
+        old: PathBuf,
+        /// The output directory of the newer build
+        new: PathBuf,
+        /// Print the differences as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Print the completion script of a shell, e.g. `statical completions bash > /etc/bash_completion.d/statical`
+    Completions {
+        /// The shell to complete in
+        #[clap(arg_enum)]
+        shell: Shell,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_flags_are_accepted_after_the_subcommand() {
+        Opt::command().debug_assert();
+        let args = Opt::try_parse_from(["statical", "build", "--check-links", "--resume"]).unwrap();
+        assert!(args.check_links && args.resume);
+        assert!(matches!(args.command, Some(Command::Build)));
+        let args = Opt::try_parse_from(["statical", "serve", "--dry-run=json"]).unwrap();
+        assert!(matches!(args.dry_run, Some(DryRunFormat::Json)));
+    }
 }
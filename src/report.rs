@@ -0,0 +1,151 @@
+use color_eyre::eyre::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::log;
+
+/// Name of the build report written into the output directory
+pub const REPORT_FILE_NAME: &str = ".statical-report.json";
+
+/// A summary of a build, written next to the generated pages
+#[derive(Debug, Default, Serialize)]
+pub struct BuildReport {
+    /// Fetch and parse statistics for each calendar source
+    pub sources: Vec<SourceSummary>,
+    /// Events which several sources publish with the same UID but different content
+    pub uid_conflicts: Vec<UidConflict>,
+    /// How long each phase of the build took, in the order they ran
+    pub timings: Vec<PhaseTiming>,
+}
+
+/// Statistics about a single calendar source (file or url)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SourceSummary {
+    /// The file path or url of the source
+    pub source: String,
+    /// Number of events which were parsed successfully, counting recurring events once
+    pub events_parsed: usize,
+    /// Number of events which were left out because they could not be parsed or were out of range
+    pub events_skipped: usize,
+    /// Number of instances generated from recurring events
+    pub recurrences_expanded: usize,
+    /// Start of the earliest event in `yyyy-mm-dd` form
+    pub first_event: Option<String>,
+    /// End of the latest event in `yyyy-mm-dd` form
+    pub last_event: Option<String>,
+    /// Problems found while parsing this source
+    pub warnings: Vec<String>,
+}
+
+/// An event UID which several sources disagree about, usually because one of them is out of sync with upstream
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UidConflict {
+    pub uid: String,
+    /// The sources publishing the UID
+    pub sources: Vec<String>,
+    /// The fields which differ
+    pub differences: Vec<FieldDifference>,
+}
+
+/// A field of an event with the value each source has for it
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FieldDifference {
+    pub field: String,
+    /// The value of the field, keyed by source
+    pub values: BTreeMap<String, String>,
+}
+
+/// The elapsed time of a build phase, e.g. `fetch`, `parse`, or `view:month`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub duration_ms: f64,
+}
+
+impl PhaseTiming {
+    /// Records a phase which started at `started` and just ended
+    pub fn since(phase: &str, started: Instant) -> PhaseTiming {
+        PhaseTiming {
+            phase: phase.to_string(),
+            duration_ms: log::millis(started.elapsed()),
+        }
+    }
+}
+
+impl BuildReport {
+    /// Writes the report into an output directory
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let path = output_dir.join(REPORT_FILE_NAME);
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .context(format!("could not write build report {:?}", path))
+    }
+
+    /// Prints a table with one line per source followed by its warnings
+    pub fn print_source_table(&self) {
+        info!(
+            "{:<40} {:>7} {:>7} {:>9}  {:<23} {:>8}",
+            "source", "parsed", "skipped", "expanded", "range", "warnings"
+        );
+        for summary in &self.sources {
+            let range = match (&summary.first_event, &summary.last_event) {
+                (Some(first), Some(last)) => format!("{} - {}", first, last),
+                _ => "-".to_string(),
+            };
+            info!(
+                "{:<40} {:>7} {:>7} {:>9}  {:<23} {:>8}",
+                summary.source,
+                summary.events_parsed,
+                summary.events_skipped,
+                summary.recurrences_expanded,
+                range,
+                summary.warnings.len()
+            );
+            for warning in &summary.warnings {
+                warn!("  warning: {}", warning);
+            }
+        }
+    }
+
+    /// Prints how long each phase took and its share of the total
+    pub fn print_timings(&self) {
+        let total: f64 = self.timings.iter().map(|timing| timing.duration_ms).sum();
+        info!("{:<20} {:>10} {:>6}", "phase", "ms", "share");
+        for timing in &self.timings {
+            info!(
+                "{:<20} {:>10.1} {:>5.1}%",
+                timing.phase,
+                timing.duration_ms,
+                if total > 0.0 {
+                    timing.duration_ms / total * 100.0
+                } else {
+                    0.0
+                }
+            );
+        }
+        info!("{:<20} {:>10.1}", "total", total);
+    }
+
+    /// Prints the UIDs with conflicting content and how the sources differ, nothing if there are none
+    pub fn print_uid_conflicts(&self) {
+        if self.uid_conflicts.is_empty() {
+            return;
+        }
+        info!(
+            "Events with the same UID but different content in several sources: {}",
+            self.uid_conflicts.len()
+        );
+        for conflict in &self.uid_conflicts {
+            info!("{} ({})", conflict.uid, conflict.sources.join(", "));
+            for difference in &conflict.differences {
+                info!("  {}:", difference.field);
+                for (source, value) in &difference.values {
+                    info!("    {}: {:?}", source, value);
+                }
+            }
+        }
+    }
+}
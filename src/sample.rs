@@ -0,0 +1,151 @@
+//! Synthetic calendar data, used to exercise the templates without a real calendar.
+//!
+//! The generated calendar is deliberately messy: it has recurring events, overlapping events, events spanning
+//! several days (and month boundaries), all-day events, and events crossing midnight.
+
+use time::ext::NumericalDuration;
+use time::macros::{format_description, time};
+use time::util::days_in_year_month;
+use time::{Date, PrimitiveDateTime, Time};
+
+/// Generates an ICS calendar with events around `today`
+pub fn sample_calendar(today: Date) -> String {
+    let monday = today - (today.weekday().number_days_from_monday() as i64).days();
+    let first_of_month = today.replace_day(1).expect("every month has a first day");
+    let first_of_next_month =
+        first_of_month + (days_in_year_month(today.year(), today.month()) as i64).days();
+    let mut events = Vec::new();
+
+    // recurring events
+    events.push(sample_event(
+        "sample-standup",
+        "Morning standup",
+        at(monday - 14.days(), time!(9:00)),
+        at(monday - 14.days(), time!(9:15)),
+        &[
+            "RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;COUNT=40",
+            "CATEGORIES:Work",
+            "LOCATION:Room 101",
+        ],
+    ));
+    events.push(sample_event(
+        "sample-weekly",
+        "Evening yoga class",
+        at(monday - 6.days(), time!(18:00)),
+        at(monday - 6.days(), time!(19:30)),
+        &[
+            "RRULE:FREQ=WEEKLY;INTERVAL=1;COUNT=12",
+            "CATEGORIES:Sports,Classes",
+            "DESCRIPTION:Bring your own mat.",
+        ],
+    ));
+    events.push(sample_event(
+        "sample-board",
+        "Board meeting",
+        at(first_of_month, time!(17:00)),
+        at(first_of_month, time!(19:00)),
+        &["RRULE:FREQ=MONTHLY;BYDAY=1TU;COUNT=6", "CATEGORIES:Work"],
+    ));
+
+    // overlapping events
+    events.push(sample_event(
+        "sample-review",
+        "Design review",
+        at(today, time!(10:00)),
+        at(today, time!(11:30)),
+        &["CATEGORIES:Work", "URL:https://example.com/review"],
+    ));
+    events.push(sample_event(
+        "sample-call",
+        "Customer call",
+        at(today, time!(11:00)),
+        at(today, time!(12:00)),
+        &["CATEGORIES:Work"],
+    ));
+    events.push(sample_event(
+        "sample-lunch",
+        "Lunch talk: a very long summary that templates need to wrap or truncate gracefully",
+        at(today, time!(11:30)),
+        at(today, time!(13:00)),
+        &["DESCRIPTION:Talks over lunch.\\nSandwiches provided."],
+    ));
+
+    // multi-day events
+    events.push(sample_event(
+        "sample-conference",
+        "Conference",
+        at(today + 2.days(), time!(9:00)),
+        at(today + 4.days(), time!(17:00)),
+        &["CATEGORIES:Conferences", "LOCATION:Convention Center"],
+    ));
+    events.push(sample_event(
+        "sample-rental",
+        "Cabin rental",
+        at(first_of_next_month - 3.days(), time!(15:00)),
+        at(first_of_next_month + 4.days(), time!(11:00)),
+        &["CATEGORIES:Travel"],
+    ));
+
+    // all-day events, spanning midnight to midnight
+    events.push(sample_event(
+        "sample-holiday",
+        "Office closed",
+        at(today + 7.days(), Time::MIDNIGHT),
+        at(today + 8.days(), Time::MIDNIGHT),
+        &["TRANSP:TRANSPARENT"],
+    ));
+    events.push(sample_event(
+        "sample-festival",
+        "Summer festival",
+        at(today + 10.days(), Time::MIDNIGHT),
+        at(today + 13.days(), Time::MIDNIGHT),
+        &["CATEGORIES:Music"],
+    ));
+
+    // events crossing midnight
+    events.push(sample_event(
+        "sample-late-show",
+        "Late show",
+        at(today + 1.days(), time!(22:00)),
+        at(today + 2.days(), time!(2:00)),
+        &["CATEGORIES:Music", "X-MICROSOFT-CDO-BUSYSTATUS:TENTATIVE"],
+    ));
+
+    format!(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//statical//sample data//EN\r\nX-WR-CALNAME:Sample\r\nX-WR-CALDESC:Synthetic events for template development\r\n{}END:VCALENDAR\r\n",
+        events.concat()
+    )
+}
+
+/// Formats a single VEVENT, `extra_lines` are added as they are
+fn sample_event(
+    uid: &str,
+    summary: &str,
+    start: PrimitiveDateTime,
+    end: PrimitiveDateTime,
+    extra_lines: &[&str],
+) -> String {
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@statical.sample", uid),
+        format!("SUMMARY:{}", summary),
+        format!("DTSTART:{}", ics_utc(start)),
+        format!("DTEND:{}", ics_utc(end)),
+    ];
+    lines.extend(extra_lines.iter().map(|line| line.to_string()));
+    lines.push("END:VEVENT".to_string());
+    lines.iter().map(|line| format!("{}\r\n", line)).collect()
+}
+
+fn at(date: Date, time: Time) -> PrimitiveDateTime {
+    PrimitiveDateTime::new(date, time)
+}
+
+/// Formats a date and time as an ICS UTC timestamp
+fn ics_utc(date_time: PrimitiveDateTime) -> String {
+    date_time
+        .format(format_description!(
+            "[year][month][day]T[hour][minute][second]Z"
+        ))
+        .expect("could not format sample date")
+}
@@ -0,0 +1,127 @@
+//! Cron-like schedules for the daemon.
+
+use color_eyre::eyre::{bail, eyre, Context, Result};
+use std::fmt;
+use time::{Duration, OffsetDateTime};
+
+/// How far ahead `Schedule::next_after()` looks before giving up, a leap year covers every possible date
+const MAX_MINUTES_AHEAD: i64 = 366 * 24 * 60;
+
+/// A five field cron expression: minute, hour, day of month, month, and day of week
+///
+/// Each field accepts `*`, numbers, ranges (`1-5`), lists (`1,15`), and steps (`*/15`, `8-18/2`). Days of the week
+/// count from 0 (Sunday) to 6, 7 is accepted for Sunday as well. Like cron, a day matches if either the day of the
+/// month or the day of the week matches when both are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Schedule> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            bail!(
+                "schedule {:?} must have five fields: minute hour day-of-month month day-of-week",
+                expression
+            );
+        }
+        let field = |index: usize, name: &str, min: u8, max: u8| {
+            parse_field(fields[index], min, max)
+                .wrap_err(format!("invalid {} in schedule {:?}", name, expression))
+        };
+
+        let mut days_of_week = field(4, "day of week", 0, 7)?;
+        // 7 is another name for Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+
+        Ok(Schedule {
+            expression: expression.to_string(),
+            minutes: field(0, "minute", 0, 59)?,
+            hours: field(1, "hour", 0, 23)?,
+            days_of_month: field(2, "day of month", 1, 31)?,
+            months: field(3, "month", 1, 12)?,
+            days_of_week,
+            any_day_of_month: fields[2] == "*",
+            any_day_of_week: fields[4] == "*",
+        })
+    }
+
+    /// Returns the first whole minute after `after` matching the schedule
+    ///
+    /// The schedule is evaluated at the offset of `after`. Returns `None` if no date in the next year matches,
+    /// e.g. for `0 0 30 2 *`.
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let start = after
+            .replace_second(0)
+            .and_then(|t| t.replace_nanosecond(0))
+            .ok()?
+            + Duration::minutes(1);
+        (0..MAX_MINUTES_AHEAD)
+            .map(|minute| start + Duration::minutes(minute))
+            .find(|candidate| self.matches(*candidate))
+    }
+
+    /// Checks whether the minute of `time` matches the schedule
+    pub fn matches(&self, time: OffsetDateTime) -> bool {
+        let has = |set: u64, value: u8| set & (1 << value) != 0;
+        let day_of_month = has(self.days_of_month, time.day());
+        let day_of_week = has(self.days_of_week, time.weekday().number_days_from_sunday());
+        let day = match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (true, false) => day_of_week,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || day_of_week,
+        };
+        day && has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, time.month() as u8)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+/// Parses one field into a bit set of the values it matches
+fn parse_field(field: &str, min: u8, max: u8) -> Result<u64> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u8>()?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("step must not be 0");
+        }
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((first, last)) => (first.parse()?, last.parse()?),
+                None => {
+                    let value = range.parse()?;
+                    // `5/15` means starting at 5, every 15
+                    (value, if part.contains('/') { max } else { value })
+                }
+            },
+        };
+        if first < min || last > max || first > last {
+            return Err(eyre!("{:?} is outside of {}-{}", part, min, max));
+        }
+        for value in (first..=last).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
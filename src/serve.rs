@@ -0,0 +1,118 @@
+//! Serves the output directory over HTTP to preview the site before publishing it.
+
+use color_eyre::eyre::{Context, Result};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the files of `output_dir` on `listen_address` until the process is stopped
+pub fn run(output_dir: &Path, listen_address: &str) -> Result<()> {
+    let listener = TcpListener::bind(listen_address)
+        .wrap_err(format!("could not listen on {}", listen_address))?;
+    info!("Serving {:?} on http://{}/", output_dir, listen_address);
+    for stream in listener.incoming().flatten() {
+        if let Err(error) = handle_request(output_dir, stream) {
+            warn!("Could not answer request: {:#}", error);
+        }
+    }
+    Ok(())
+}
+
+fn handle_request(output_dir: &Path, mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next(), parts.next().unwrap_or("/"));
+    let (status, content_type, body) = match method {
+        Some("GET" | "HEAD") => match resolve(output_dir, target).and_then(|file| {
+            let body = fs::read(&file).ok()?;
+            Some((file, body))
+        }) {
+            Some((file, body)) => ("200 OK", content_type(&file), body),
+            None => ("404 Not Found", "text/plain", b"not found".to_vec()),
+        },
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            b"method not allowed".to_vec(),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if method != Some("HEAD") {
+        stream.write_all(&body)?;
+    }
+    Ok(())
+}
+
+/// Maps a request target to a file within `output_dir`, directories are served by their `index.html`
+///
+/// Targets reaching outside of the output directory are not resolved.
+fn resolve(output_dir: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let path = PathBuf::from(percent_decode(path.trim_start_matches('/'))?);
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let file = output_dir.join(path);
+    if file.is_dir() {
+        Some(file.join("index.html"))
+    } else {
+        Some(file)
+    }
+}
+
+/// Decodes `%xx` escapes, `None` if an escape is malformed or the result is not UTF-8
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The content type of the files a build writes, by extension
+pub(crate) fn content_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("atom") => "application/atom+xml",
+        Some("ics") => "text/calendar",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,150 @@
+//! Where the generated site is written to.
+//!
+//! The calendar collection hands every file it renders to a `SiteWriter`, with its path relative to the output
+//! directory. Builds write to the output directory with a `FilesystemWriter`, embedders can keep the site in memory
+//! with a `MemoryWriter` or pack it into a tar archive with an `ArchiveWriter`.
+
+use color_eyre::eyre::{Result, WrapErr};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Receives the files of the generated site
+pub trait SiteWriter {
+    /// Writes a file, `path` is relative to the root of the site
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<()>;
+
+    /// Creates an empty directory, relative to the root of the site
+    ///
+    /// Writers without directories can ignore this, files are written with their whole path anyway.
+    fn create_dir(&mut self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Completes the site after the last file, e.g. to write the end of an archive
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the site into a directory
+#[derive(Debug, Clone)]
+pub struct FilesystemWriter {
+    root: PathBuf,
+}
+
+impl FilesystemWriter {
+    pub fn new(root: impl Into<PathBuf>) -> FilesystemWriter {
+        FilesystemWriter { root: root.into() }
+    }
+}
+
+impl SiteWriter for FilesystemWriter {
+    /// Writes a file, creating its directory if necessary
+    ///
+    /// Files whose contents did not change are left alone, so their modification time only changes with the page.
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<()> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).wrap_err(format!("could not create {:?}", parent))?;
+        }
+        let unchanged = fs::read(&path).is_ok_and(|existing| existing == contents);
+        if !unchanged {
+            fs::write(&path, contents).wrap_err(format!("could not write {:?}", path))?;
+        }
+        Ok(())
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        let path = self.root.join(path);
+        fs::create_dir_all(&path).wrap_err(format!("could not create {:?}", path))
+    }
+}
+
+/// Keeps the site in memory
+///
+/// Clones share their files, so a clone kept by the caller sees what the collection wrote through the other.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryWriter {
+    files: Rc<RefCell<BTreeMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MemoryWriter {
+    pub fn new() -> MemoryWriter {
+        MemoryWriter::default()
+    }
+
+    /// Returns the files written so far, keyed by their path relative to the root of the site
+    #[must_use]
+    pub fn files(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        self.files.borrow().clone()
+    }
+
+    /// Returns the contents of a file as text, if it was written and is valid UTF-8
+    #[must_use]
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<String> {
+        self.files
+            .borrow()
+            .get(path.as_ref())
+            .and_then(|contents| String::from_utf8(contents.clone()).ok())
+    }
+}
+
+impl SiteWriter for MemoryWriter {
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.files
+            .borrow_mut()
+            .insert(path.to_path_buf(), contents.to_vec());
+        Ok(())
+    }
+}
+
+/// Packs the site into a tar archive, e.g. to upload it in one piece
+pub struct ArchiveWriter<W: Write> {
+    builder: tar::Builder<W>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(writer: W) -> ArchiveWriter<W> {
+        let mut builder = tar::Builder::new(writer);
+        builder.mode(tar::HeaderMode::Deterministic);
+        ArchiveWriter { builder }
+    }
+
+    /// Finishes the archive if necessary and returns the underlying writer
+    pub fn into_inner(self) -> Result<W> {
+        self.builder
+            .into_inner()
+            .wrap_err("could not finish the archive")
+    }
+}
+
+impl<W: Write> SiteWriter for ArchiveWriter<W> {
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        self.builder
+            .append_data(&mut header, path, contents)
+            .wrap_err(format!("could not add {:?} to the archive", path))
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        self.builder
+            .append_data(&mut header, path, std::io::empty())
+            .wrap_err(format!("could not add {:?} to the archive", path))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.builder
+            .finish()
+            .wrap_err("could not finish the archive")
+    }
+}
@@ -0,0 +1,39 @@
+//! Checks the sources without building the site.
+
+use color_eyre::eyre::{bail, Result};
+use tracing::info;
+
+use crate::config::Config;
+use crate::model::calendar_collection::CalendarCollection;
+use crate::options::Opt;
+use crate::report::BuildReport;
+
+/// Reads and parses the sources like a build does and prints the source table, without writing anything
+///
+/// Fails if a source has warnings, e.g. because it could not be read or events were skipped, so the check can gate
+/// a deployment.
+pub fn check_sources(args: Opt, config: &Config) -> Result<()> {
+    let calendar_collection = CalendarCollection::new(args, config)?;
+    let report = BuildReport {
+        sources: calendar_collection.source_summaries().to_vec(),
+        uid_conflicts: calendar_collection.uid_conflicts().to_vec(),
+        timings: calendar_collection.timings().to_vec(),
+    };
+    report.print_source_table();
+    report.print_uid_conflicts();
+
+    let warnings: usize = report
+        .sources
+        .iter()
+        .map(|summary| summary.warnings.len())
+        .sum();
+    if warnings > 0 {
+        bail!("the sources have {} warnings", warnings);
+    }
+    info!(
+        "Checked {} sources with {} events",
+        report.sources.len(),
+        calendar_collection.events().len()
+    );
+    Ok(())
+}
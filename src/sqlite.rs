@@ -0,0 +1,447 @@
+//! Exports the expanded events as a SQLite database, for analytics and ad-hoc SQL queries on the calendar data.
+//!
+//! The database is written directly in the SQLite file format, so statical needs neither a SQLite library nor the
+//! `sqlite3` tool. Only what a freshly created database with plain tables contains is written: the tables are not
+//! indexed and have no `PRIMARY KEY` or `UNIQUE` constraints, which would need index b-trees.
+//!
+//! The schema is `SCHEMA`, every row of `events` is an event instance, recurring events have one row per date.
+
+use color_eyre::eyre::{bail, Result};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
+
+use crate::model::event::Event;
+
+/// Name of the database written into the output directory
+pub const FILE_NAME: &str = "events.sqlite";
+
+/// The tables of the database with the statement creating them
+pub const SCHEMA: [(&str, &str); 2] = [
+    (
+        "events",
+        "CREATE TABLE events (
+  instance_id TEXT NOT NULL,
+  uid TEXT,
+  summary TEXT NOT NULL,
+  description TEXT,
+  location TEXT,
+  url TEXT,
+  start_time TEXT NOT NULL,
+  end_time TEXT NOT NULL,
+  start_unix INTEGER NOT NULL,
+  end_unix INTEGER NOT NULL,
+  all_day INTEGER NOT NULL,
+  recurring INTEGER NOT NULL,
+  busy_status TEXT NOT NULL,
+  status TEXT NOT NULL
+)",
+    ),
+    (
+        "event_categories",
+        "CREATE TABLE event_categories (
+  instance_id TEXT NOT NULL,
+  category TEXT NOT NULL
+)",
+    ),
+];
+
+const PAGE_SIZE: usize = 4096;
+/// The largest payload stored in a leaf cell without overflow pages, see "B-tree Pages" in the file format docs
+const MAX_LOCAL: usize = PAGE_SIZE - 35;
+const MIN_LOCAL: usize = (PAGE_SIZE - 12) * 32 / 255 - 23;
+const LEAF_TABLE: u8 = 0x0d;
+const INTERIOR_TABLE: u8 = 0x05;
+
+/// A column value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqlValue {
+    Null,
+    Integer(i64),
+    Text(String),
+}
+
+impl From<Option<&str>> for SqlValue {
+    fn from(value: Option<&str>) -> SqlValue {
+        value.map_or(SqlValue::Null, |text| SqlValue::Text(text.to_string()))
+    }
+}
+
+/// Returns the database with the events in the tables of `SCHEMA`, times are RFC 3339 in UTC and Unix timestamps
+pub fn events_database<'e>(events: impl Iterator<Item = &'e Event>) -> Result<Vec<u8>> {
+    let mut event_rows = Vec::new();
+    let mut category_rows = Vec::new();
+    for event in events {
+        let instance_id = event.instance_id();
+        for category in event.categories() {
+            category_rows.push(vec![
+                SqlValue::Text(instance_id.clone()),
+                SqlValue::Text(category.to_string()),
+            ]);
+        }
+        event_rows.push(vec![
+            SqlValue::Text(instance_id),
+            event.uid().into(),
+            SqlValue::Text(event.summary().to_string()),
+            event.description().into(),
+            event.location().into(),
+            event.url().into(),
+            SqlValue::Text(rfc3339(event.start())),
+            SqlValue::Text(rfc3339(event.end())),
+            SqlValue::Integer(event.start().unix_timestamp()),
+            SqlValue::Integer(event.end().unix_timestamp()),
+            SqlValue::Integer(event.is_all_day().into()),
+            SqlValue::Integer(event.is_recurring().into()),
+            SqlValue::Text(
+                serde_json::to_value(event.busy_status())?
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            SqlValue::Text(
+                serde_json::to_value(event.status())?
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+        ]);
+    }
+    database(&[
+        (SCHEMA[0].0, SCHEMA[0].1, event_rows),
+        (SCHEMA[1].0, SCHEMA[1].1, category_rows),
+    ])
+}
+
+/// Returns a database with the tables, given by their name, `CREATE TABLE` statement, and rows
+pub fn database(tables: &[(&str, &str, Vec<Vec<SqlValue>>)]) -> Result<Vec<u8>> {
+    // Page 1 holds the header and the schema table, it is filled in once the root pages of the tables are known
+    let mut pages = vec![vec![0; PAGE_SIZE]];
+    let mut schema_rows = Vec::new();
+    for (name, sql, rows) in tables {
+        let root = write_table(&mut pages, rows);
+        schema_rows.push(vec![
+            SqlValue::Text("table".to_string()),
+            SqlValue::Text(name.to_string()),
+            SqlValue::Text(name.to_string()),
+            SqlValue::Integer(root.into()),
+            SqlValue::Text(sql.to_string()),
+        ]);
+    }
+
+    let mut cells = Vec::new();
+    for (index, row) in schema_rows.iter().enumerate() {
+        let payload = record(row);
+        if payload.len() > MAX_LOCAL {
+            bail!("the SQLite schema is too large");
+        }
+        let mut cell = varint(payload.len() as u64);
+        cell.extend(varint(index as u64 + 1));
+        cell.extend(payload);
+        cells.push(cell);
+    }
+    if 100 + 8 + cells.iter().map(|cell| cell.len() + 2).sum::<usize>() > PAGE_SIZE {
+        bail!("the SQLite schema does not fit into the first page");
+    }
+    write_page(&mut pages[0], 100, LEAF_TABLE, &cells, None);
+    let page_count = pages.len() as u32;
+    write_header(&mut pages[0], page_count);
+
+    Ok(pages.concat())
+}
+
+/// Writes the rows into a table b-tree and returns the number of its root page
+fn write_table(pages: &mut Vec<Vec<u8>>, rows: &[Vec<SqlValue>]) -> u32 {
+    // The pages of the current level with the largest rowid in each
+    let mut children: Vec<(u32, u64)> = Vec::new();
+    let mut cells = Vec::new();
+    let mut used = 8;
+    for (index, row) in rows.iter().enumerate() {
+        let rowid = index as u64 + 1;
+        let cell = leaf_cell(pages, rowid, &record(row));
+        if used + cell.len() + 2 > PAGE_SIZE {
+            children.push((add_page(pages, LEAF_TABLE, &cells, None), rowid - 1));
+            cells.clear();
+            used = 8;
+        }
+        used += cell.len() + 2;
+        cells.push(cell);
+    }
+    children.push((add_page(pages, LEAF_TABLE, &cells, None), rows.len() as u64));
+
+    while children.len() > 1 {
+        let mut parents = Vec::new();
+        let mut cells = Vec::new();
+        let mut used = 12;
+        let mut iter = children.into_iter().peekable();
+        while let Some((page, key)) = iter.next() {
+            let mut cell = page.to_be_bytes().to_vec();
+            cell.extend(varint(key));
+            // The last child of a page is its right-most pointer instead of a cell
+            if iter.peek().is_none() || used + cell.len() + 2 > PAGE_SIZE {
+                parents.push((add_page(pages, INTERIOR_TABLE, &cells, Some(page)), key));
+                cells.clear();
+                used = 12;
+            } else {
+                used += cell.len() + 2;
+                cells.push(cell);
+            }
+        }
+        children = parents;
+    }
+    children[0].0
+}
+
+/// Returns the cell of a row in a leaf page, moving the end of a large payload into overflow pages
+fn leaf_cell(pages: &mut Vec<Vec<u8>>, rowid: u64, payload: &[u8]) -> Vec<u8> {
+    let mut cell = varint(payload.len() as u64);
+    cell.extend(varint(rowid));
+    if payload.len() <= MAX_LOCAL {
+        cell.extend(payload);
+        return cell;
+    }
+    let overflow_size = PAGE_SIZE - 4;
+    let mut local = MIN_LOCAL + (payload.len() - MIN_LOCAL) % overflow_size;
+    if local > MAX_LOCAL {
+        local = MIN_LOCAL;
+    }
+    cell.extend(&payload[..local]);
+
+    let chunks: Vec<&[u8]> = payload[local..].chunks(overflow_size).collect();
+    let first = pages.len() as u32 + 1;
+    for (index, chunk) in chunks.iter().enumerate() {
+        let mut page = vec![0; PAGE_SIZE];
+        if index + 1 < chunks.len() {
+            page[..4].copy_from_slice(&(first + index as u32 + 1).to_be_bytes());
+        }
+        page[4..4 + chunk.len()].copy_from_slice(chunk);
+        pages.push(page);
+    }
+    cell.extend(first.to_be_bytes());
+    cell
+}
+
+/// Appends a b-tree page with the cells and returns its number
+fn add_page(
+    pages: &mut Vec<Vec<u8>>,
+    page_type: u8,
+    cells: &[Vec<u8>],
+    right_most: Option<u32>,
+) -> u32 {
+    let mut page = vec![0; PAGE_SIZE];
+    write_page(&mut page, 0, page_type, cells, right_most);
+    pages.push(page);
+    pages.len() as u32
+}
+
+/// Writes the b-tree page header at `offset`, the cell pointers after it, and the cells at the end of the page
+fn write_page(
+    page: &mut [u8],
+    offset: usize,
+    page_type: u8,
+    cells: &[Vec<u8>],
+    right_most: Option<u32>,
+) {
+    let header_size = if right_most.is_some() { 12 } else { 8 };
+    let mut content_start = PAGE_SIZE;
+    for (index, cell) in cells.iter().enumerate() {
+        content_start -= cell.len();
+        page[content_start..content_start + cell.len()].copy_from_slice(cell);
+        let pointer = offset + header_size + 2 * index;
+        page[pointer..pointer + 2].copy_from_slice(&(content_start as u16).to_be_bytes());
+    }
+    page[offset] = page_type;
+    page[offset + 3..offset + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+    page[offset + 5..offset + 7].copy_from_slice(&(content_start as u16).to_be_bytes());
+    if let Some(right_most) = right_most {
+        page[offset + 8..offset + 12].copy_from_slice(&right_most.to_be_bytes());
+    }
+}
+
+/// Writes the database header, see "The Database Header" in the file format docs
+fn write_header(page: &mut [u8], page_count: u32) {
+    page[..16].copy_from_slice(b"SQLite format 3\0");
+    page[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+    // File format versions, unused bytes per page, and the payload fractions, which must be 64, 32, and 32
+    page[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+    // The change counter, it must match the "version-valid-for" number
+    page[24..28].copy_from_slice(&1u32.to_be_bytes());
+    page[28..32].copy_from_slice(&page_count.to_be_bytes());
+    // The schema cookie and format
+    page[40..44].copy_from_slice(&1u32.to_be_bytes());
+    page[44..48].copy_from_slice(&4u32.to_be_bytes());
+    // UTF-8
+    page[56..60].copy_from_slice(&1u32.to_be_bytes());
+    page[92..96].copy_from_slice(&1u32.to_be_bytes());
+    page[96..100].copy_from_slice(&3_045_000u32.to_be_bytes());
+}
+
+/// Encodes a row in the record format: the size of the header, the serial type of each value, then the values
+fn record(values: &[SqlValue]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for value in values {
+        match value {
+            SqlValue::Null => types.extend(varint(0)),
+            SqlValue::Integer(0) => types.extend(varint(8)),
+            SqlValue::Integer(1) => types.extend(varint(9)),
+            SqlValue::Integer(integer) => {
+                types.extend(varint(6));
+                body.extend(integer.to_be_bytes());
+            }
+            SqlValue::Text(text) => {
+                types.extend(varint(text.len() as u64 * 2 + 13));
+                body.extend(text.as_bytes());
+            }
+        }
+    }
+    // The size includes its own varint, which takes a second byte past 127
+    let mut header_size = types.len() + 1;
+    if header_size > 127 {
+        header_size += 1;
+    }
+    let mut record = varint(header_size as u64);
+    record.extend(types);
+    record.extend(body);
+    record
+}
+
+/// Encodes a variable-length integer, big-endian with 7 bits per byte and all 8 bits in a ninth byte
+fn varint(value: u64) -> Vec<u8> {
+    if value >> 56 != 0 {
+        let mut bytes: Vec<u8> = (0..8)
+            .map(|index| ((value >> (57 - 7 * index)) as u8 & 0x7f) | 0x80)
+            .collect();
+        bytes.push(value as u8);
+        return bytes;
+    }
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest != 0 {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+fn rfc3339(time: OffsetDateTime) -> String {
+    time.to_offset(UtcOffset::UTC)
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rusqlite::Connection;
+
+    /// Writes the database to a temporary file and opens it with SQLite, after checking its integrity
+    fn open(name: &str, tables: &[(&str, &str, Vec<Vec<SqlValue>>)]) -> Connection {
+        let path =
+            std::env::temp_dir().join(format!("statical-{}-{}.sqlite", std::process::id(), name));
+        std::fs::write(&path, database(tables).unwrap()).unwrap();
+        let connection = Connection::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let integrity: String = connection
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(integrity, "ok");
+        connection
+    }
+
+    fn texts(connection: &Connection, sql: &str) -> Vec<String> {
+        let mut statement = connection.prepare(sql).unwrap();
+        let rows = statement.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn empty_tables() {
+        let connection = open(
+            "empty",
+            &[
+                (SCHEMA[0].0, SCHEMA[0].1, Vec::new()),
+                (SCHEMA[1].0, SCHEMA[1].1, Vec::new()),
+            ],
+        );
+        let count: i64 = connection
+            .query_row("SELECT count(*) FROM events", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(
+            texts(&connection, "SELECT name FROM sqlite_schema ORDER BY name"),
+            ["event_categories", "events"]
+        );
+    }
+
+    #[test]
+    fn rows_spanning_interior_pages() {
+        // Enough rows for several hundred leaf pages, which need more than one level of interior pages
+        let rows: Vec<_> = (0..12_000)
+            .map(|index| {
+                vec![
+                    SqlValue::Integer(index),
+                    SqlValue::Text(format!("{index:0>250}")),
+                    if index % 3 == 0 {
+                        SqlValue::Null
+                    } else {
+                        SqlValue::Integer(index % 2)
+                    },
+                ]
+            })
+            .collect();
+        let connection = open(
+            "pages",
+            &[(
+                "rows",
+                "CREATE TABLE rows (number INTEGER, text TEXT, flag INTEGER)",
+                rows,
+            )],
+        );
+        let (count, sum): (i64, i64) = connection
+            .query_row("SELECT count(*), sum(number) FROM rows", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((count, sum), (12_000, (0..12_000).sum()));
+        let nulls: i64 = connection
+            .query_row("SELECT count(*) FROM rows WHERE flag IS NULL", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(nulls, 4_000);
+        assert_eq!(
+            texts(&connection, "SELECT text FROM rows WHERE number = 11999"),
+            [format!("{:0>250}", 11999)]
+        );
+    }
+
+    #[test]
+    fn overflowing_rows() {
+        // Around the largest local payload, and long enough for a chain of overflow pages
+        let lengths = [
+            MIN_LOCAL,
+            MAX_LOCAL - 10,
+            MAX_LOCAL,
+            MAX_LOCAL + 10,
+            10_000,
+            100_000,
+        ];
+        let expected: Vec<String> = lengths
+            .iter()
+            .enumerate()
+            .map(|(index, length)| char::from(b'a' + index as u8).to_string().repeat(*length))
+            .collect();
+        let rows = expected
+            .iter()
+            .map(|text| vec![SqlValue::Text(text.clone())])
+            .collect();
+        let connection = open(
+            "overflow",
+            &[("texts", "CREATE TABLE texts (text TEXT)", rows)],
+        );
+        assert_eq!(
+            texts(&connection, "SELECT text FROM texts ORDER BY rowid"),
+            expected
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Sunrise and sunset times, for calendars of outdoor activities.
+//!
+//! The times follow the sunrise equation with the usual corrections for refraction and the size of the sun, which is
+//! accurate to a minute or two away from the polar circles.
+
+use time::{Date, OffsetDateTime};
+
+/// The Julian day of the J2000 epoch, January 1 2000 at noon
+const J2000: f64 = 2_451_545.0;
+/// The Julian day of the Unix epoch
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2_440_587.5;
+
+/// The course of the sun on a day at a place
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Daylight {
+    /// The sun rises and sets, at these times
+    RiseAndSet(OffsetDateTime, OffsetDateTime),
+    /// The sun stays above the horizon all day
+    PolarDay,
+    /// The sun stays below the horizon all day
+    PolarNight,
+}
+
+/// Computes when the sun rises and sets on `date` at `latitude` and `longitude`, in degrees with north and east positive
+pub fn daylight(date: Date, latitude: f64, longitude: f64) -> Daylight {
+    let days = (date.to_julian_day() as f64 - J2000).ceil() + 0.0008;
+    let mean_solar_time = days - longitude / 360.0;
+    let mean_anomaly = (357.5291 + 0.985_600_28 * mean_solar_time).rem_euclid(360.0);
+    let m = mean_anomaly.to_radians();
+    let center = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let ecliptic_longitude = (mean_anomaly + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit =
+        J2000 + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination_sin = ecliptic_longitude.sin() * 23.4397_f64.to_radians().sin();
+    let declination_cos = declination_sin.asin().cos();
+    let latitude = latitude.to_radians();
+    let hour_angle_cos = ((-0.833_f64).to_radians().sin() - latitude.sin() * declination_sin)
+        / (latitude.cos() * declination_cos);
+    if hour_angle_cos < -1.0 {
+        return Daylight::PolarDay;
+    }
+    if hour_angle_cos > 1.0 {
+        return Daylight::PolarNight;
+    }
+    let hour_angle = hour_angle_cos.acos().to_degrees() / 360.0;
+    Daylight::RiseAndSet(
+        from_julian_day(transit - hour_angle),
+        from_julian_day(transit + hour_angle),
+    )
+}
+
+fn from_julian_day(julian_day: f64) -> OffsetDateTime {
+    let timestamp = ((julian_day - UNIX_EPOCH_JULIAN_DAY) * 86_400.0).round() as i64;
+    OffsetDateTime::from_unix_timestamp(timestamp).unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
@@ -0,0 +1,91 @@
+//! Checks the templates before they are used by a real build.
+
+use color_eyre::eyre::{bail, Context, Result};
+use std::fs;
+use tera::Tera;
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::config::Config;
+use crate::model::arena::StringInterner;
+use crate::model::calendar::Calendar;
+use crate::model::calendar_collection::{validate_config, CalendarCollection};
+use crate::sample::sample_calendar;
+
+/// The templates each view is rendered with
+pub const REQUIRED_TEMPLATES: [&str; 4] = ["month.html", "week.html", "day.html", "agenda.html"];
+
+/// Loads the templates, checks the required ones exist, and renders every view from sample data
+///
+/// The pages are rendered into a temporary directory which is removed afterwards, so nothing in the configured
+/// output directory is touched.
+pub fn check_templates(config: &Config) -> Result<()> {
+    let tera = Tera::new(&config.template_glob()).context("could not load the templates")?;
+    let missing: Vec<&str> = REQUIRED_TEMPLATES
+        .iter()
+        .filter(|name| !tera.get_template_names().any(|t| t == **name))
+        .copied()
+        .collect();
+    if !missing.is_empty() {
+        bail!("missing required templates: {}", missing.join(", "));
+    }
+
+    let output_dir = std::env::temp_dir().join(format!("statical-check-{}", std::process::id()));
+    let check_config = Config {
+        output_dir: output_dir.to_string_lossy().to_string(),
+        copy_stylesheet_to_output: false,
+        ..config.clone()
+    };
+    let result = render_sample_pages(&check_config);
+    if output_dir.exists() {
+        fs::remove_dir_all(&output_dir).context(format!(
+            "could not remove temporary output {:?}",
+            output_dir
+        ))?;
+    }
+    result
+}
+
+fn render_sample_pages(config: &Config) -> Result<()> {
+    let ics = sample_calendar(OffsetDateTime::now_utc().date());
+    let time_zone = validate_config(config)?;
+    let (calendars, _unparsed_properties) = Calendar::parse_calendars(
+        ics.as_bytes(),
+        "sample",
+        &mut StringInterner::new(),
+        time_zone,
+    )?;
+    let collection = CalendarCollection::from_calendars(calendars, Vec::new(), config)?;
+
+    collection.setup_output_dir()?;
+    collection.create_month_pages()?;
+    collection.create_week_pages()?;
+    collection.create_day_pages()?;
+    collection.create_agenda_pages()?;
+    if config.render_year {
+        collection.create_year_pages()?;
+    }
+    if config.render_event_pages {
+        collection.create_event_pages()?;
+    }
+    if config.render_series_pages {
+        collection.create_series_pages()?;
+    }
+    if config.has_featured_events() {
+        collection.create_countdown_pages()?;
+    }
+    if config.render_tracks {
+        collection.create_track_pages()?;
+    }
+    if config.render_week_by_location {
+        collection.create_week_location_pages()?;
+    }
+    collection.create_feed_pages()?;
+
+    info!(
+        "Rendered {} sample pages from {} templates without errors",
+        collection.written_files().len(),
+        REQUIRED_TEMPLATES.len()
+    );
+    Ok(())
+}
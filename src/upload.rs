@@ -0,0 +1,307 @@
+//! Uploads the built site to an S3-compatible bucket or a WebDAV server.
+//!
+//! The manifest remembers a hash of every uploaded file, so only the files which changed since the last upload are
+//! sent again, and files which are no longer part of the site are deleted from the target.
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::macros::format_description;
+use time::OffsetDateTime;
+use tracing::{debug, info};
+
+use crate::config::{Config, UploadTarget};
+use crate::manifest::Manifest;
+use crate::serve::content_type;
+
+/// Sends files to an upload target
+trait Uploader {
+    fn put(&self, path: &Path, contents: &[u8]) -> Result<()>;
+    fn delete(&self, path: &Path) -> Result<()>;
+}
+
+/// What an upload did
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UploadSummary {
+    pub uploaded: usize,
+    pub unchanged: usize,
+    pub deleted: usize,
+}
+
+/// Uploads the files of the last build which changed since the previous upload, and deletes the ones it removed
+///
+/// The files are the ones recorded in `manifest` and the stylesheet, whose hashes are recorded in `manifest` as they
+/// are uploaded. The caller saves the manifest afterwards, also when the upload fails partway.
+pub fn upload_site(
+    output_dir: &Path,
+    manifest: &mut Manifest,
+    config: &Config,
+) -> Result<UploadSummary> {
+    let uploader: Box<dyn Uploader> = match config.upload_target {
+        UploadTarget::None => return Ok(UploadSummary::default()),
+        UploadTarget::S3 => Box::new(S3::from_config(config)?),
+        UploadTarget::WebDav => Box::new(WebDav::from_config(config)?),
+    };
+
+    let files = site_files(output_dir, manifest);
+    let mut summary = UploadSummary::default();
+    for file in &files {
+        let contents =
+            fs::read(output_dir.join(file)).wrap_err(format!("could not read {:?}", file))?;
+        let hash = hex(&Sha256::digest(&contents));
+        if manifest.uploaded.get(file) == Some(&hash) {
+            summary.unchanged += 1;
+            continue;
+        }
+        debug!("Uploading {:?}", file);
+        uploader.put(file, &contents)?;
+        manifest.uploaded.insert(file.clone(), hash);
+        summary.uploaded += 1;
+    }
+
+    let removed: Vec<PathBuf> = manifest
+        .uploaded
+        .keys()
+        .filter(|file| !files.contains(*file))
+        .cloned()
+        .collect();
+    for file in removed {
+        debug!("Deleting {:?}", file);
+        uploader.delete(&file)?;
+        manifest.uploaded.remove(&file);
+        summary.deleted += 1;
+    }
+
+    info!(
+        "Uploaded {} changed files to {}, deleted {}, {} were unchanged",
+        summary.uploaded, config.upload_url, summary.deleted, summary.unchanged
+    );
+    Ok(summary)
+}
+
+/// Returns the files of the last build recorded in `manifest` and the stylesheet, relative to the output directory
+pub(crate) fn site_files(output_dir: &Path, manifest: &Manifest) -> BTreeSet<PathBuf> {
+    let mut files = manifest.files.clone();
+    let stylesheet = PathBuf::from("styles/style.css");
+    if output_dir.join(&stylesheet).exists() {
+        files.insert(stylesheet);
+    }
+    files
+}
+
+/// A bucket of an S3-compatible service, addressed path-style as `<upload_url>/<bucket>/<key>`
+struct S3 {
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3 {
+    fn from_config(config: &Config) -> Result<S3> {
+        if config.upload_bucket.is_empty() {
+            bail!("uploading to S3 needs upload_bucket");
+        }
+        let endpoint = config.upload_url.trim_end_matches('/').to_string();
+        let host = endpoint
+            .split_once("://")
+            .map(|(_, rest)| rest.split('/').next().unwrap_or(rest).to_string())
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| eyre!("upload_url {:?} is not an http(s) url", config.upload_url))?;
+        let credential = |name: &str| {
+            std::env::var(name).wrap_err(format!("uploading to S3 needs {} to be set", name))
+        };
+        Ok(S3 {
+            endpoint,
+            host,
+            bucket: config.upload_bucket.clone(),
+            region: if config.upload_region.is_empty() {
+                "us-east-1".to_string()
+            } else {
+                config.upload_region.clone()
+            },
+            access_key: credential("AWS_ACCESS_KEY_ID")?,
+            secret_key: credential("AWS_SECRET_ACCESS_KEY")?,
+        })
+    }
+
+    /// Sends a request signed with AWS Signature Version 4
+    fn send(&self, method: &str, path: &Path, body: &[u8]) -> Result<()> {
+        let key = encode_path(path);
+        let uri = format!("/{}/{}", self.bucket, key);
+        let now = OffsetDateTime::now_utc();
+        let amz_date = now
+            .format(format_description!(
+                "[year][month][day]T[hour][minute][second]Z"
+            ))
+            .wrap_err("could not format the request time")?;
+        let date = &amz_date[..8];
+        let payload_hash = hex(&Sha256::digest(body));
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, uri, self.host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut signing_key = hmac(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&signing_key, string_to_sign.as_bytes()));
+
+        let request = ureq::request(method, &format!("{}{}", self.endpoint, uri))
+            .set("x-amz-date", &amz_date)
+            .set("x-amz-content-sha256", &payload_hash)
+            .set(
+                "Authorization",
+                &format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.access_key, scope, signed_headers, signature
+                ),
+            );
+        let result = if method == "PUT" {
+            request
+                .set("Content-Type", content_type(path))
+                .send_bytes(body)
+        } else {
+            request.call()
+        };
+        result.wrap_err(format!(
+            "could not {} {:?} in bucket {}",
+            method, path, self.bucket
+        ))?;
+        Ok(())
+    }
+}
+
+impl Uploader for S3 {
+    fn put(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.send("PUT", path, contents)
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        self.send("DELETE", path, &[])
+    }
+}
+
+/// A WebDAV collection, the directories of the site are created in it as needed
+struct WebDav {
+    url: String,
+    authorization: Option<String>,
+    /// The directories known to exist on the server
+    created_dirs: RefCell<BTreeSet<PathBuf>>,
+}
+
+impl WebDav {
+    fn from_config(config: &Config) -> Result<WebDav> {
+        if config.upload_url.is_empty() {
+            bail!("uploading to WebDAV needs upload_url");
+        }
+        let authorization = std::env::var("STATICAL_UPLOAD_USER").ok().map(|user| {
+            let password = std::env::var("STATICAL_UPLOAD_PASSWORD").unwrap_or_default();
+            format!("Basic {}", base64::encode(format!("{}:{}", user, password)))
+        });
+        Ok(WebDav {
+            url: config.upload_url.trim_end_matches('/').to_string(),
+            authorization,
+            created_dirs: RefCell::new(BTreeSet::new()),
+        })
+    }
+
+    fn request(&self, method: &str, path: &Path) -> ureq::Request {
+        let request = ureq::request(method, &format!("{}/{}", self.url, encode_path(path)));
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+
+    /// Creates the directories of a file which were not created yet, servers answer 405 for existing ones
+    fn create_parents(&self, path: &Path) -> Result<()> {
+        let mut dirs: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+        dirs.reverse();
+        for dir in dirs {
+            if self.created_dirs.borrow().contains(dir) {
+                continue;
+            }
+            match self.request("MKCOL", dir).call() {
+                Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+                Err(error) => {
+                    return Err(error).wrap_err(format!("could not create directory {:?}", dir))
+                }
+            }
+            self.created_dirs.borrow_mut().insert(dir.to_path_buf());
+        }
+        Ok(())
+    }
+}
+
+impl Uploader for WebDav {
+    fn put(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.create_parents(path)?;
+        self.request("PUT", path)
+            .set("Content-Type", content_type(path))
+            .send_bytes(contents)
+            .wrap_err(format!("could not upload {:?} to {}", path, self.url))?;
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        match self.request("DELETE", path).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(error) => {
+                Err(error).wrap_err(format!("could not delete {:?} from {}", path, self.url))
+            }
+        }
+    }
+}
+
+/// Percent-encodes the segments of a relative path for a url, keeping the slashes between them
+fn encode_path(path: &Path) -> String {
+    path.components()
+        .map(|component| {
+            component
+                .as_os_str()
+                .to_string_lossy()
+                .bytes()
+                .map(|byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                        (byte as char).to_string()
+                    }
+                    _ => format!("%{:02X}", byte),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}